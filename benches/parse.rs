@@ -0,0 +1,65 @@
+//! Parser benchmarks.
+//!
+//! Run with `cargo bench`. Each document is lexed and parsed a number of times
+//! and the mean time per run and the throughput are printed.
+
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use khi::lex::Lexer;
+use khi::List;
+use khi::parse::parse_list_str;
+
+const ROWS: usize = 20_000;
+const RUNS: u32 = 10;
+
+/// A list in tabular notation.
+fn tabular_list() -> String {
+    let mut document = String::new();
+    for i in 0..ROWS {
+        writeln!(document, "| {} | Element{} | name`:{} | <Solid> | [2; 8; {}] | \\transcribed {}\\ |", i, i, i, i % 18, i).unwrap();
+    }
+    document
+}
+
+/// A list in delimited notation.
+fn delimited_list() -> String {
+    let mut document = String::new();
+    for i in 0..ROWS {
+        write!(document, "{}; word{} text; ", i, i).unwrap();
+        if i % 10 == 9 {
+            document.push('\n');
+        }
+    }
+    document
+}
+
+/// Time a function. It returns a count derived from its result so that the
+/// work is not optimized away.
+fn bench<F: FnMut() -> usize>(name: &str, bytes: usize, mut f: F) {
+    let mut count = f(); // Warm up
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        count += f();
+    }
+    let mean = start.elapsed() / RUNS;
+    let throughput = bytes as f64 / mean.max(Duration::from_nanos(1)).as_secs_f64() / 1_000_000.0;
+    println!("{:<16} {:>10.3} ms {:>10.1} MB/s {:>10}", name, mean.as_secs_f64() * 1000.0, throughput, count);
+}
+
+fn main() {
+    let tabular = tabular_list();
+    let delimited = delimited_list();
+    bench("lex tabular", tabular.len(), || Lexer::new(&tabular).count());
+    bench("lex delimited", delimited.len(), || Lexer::new(&delimited).count());
+    bench("parse tabular", tabular.len(), || parse_list_str(&tabular).unwrap().len());
+    bench("parse delimited", delimited.len(), || parse_list_str(&delimited).unwrap().len());
+    #[cfg(feature = "arena")]
+    {
+        use khi::arena::{Arena, parse_arena_list};
+        let mut arena = Arena::new();
+        bench("arena tabular", tabular.len(), || {
+            arena.reset();
+            parse_arena_list(&arena, &tabular).unwrap().len()
+        });
+    }
+}
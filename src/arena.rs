@@ -0,0 +1,676 @@
+//! Arena allocated parse tree.
+//!
+//! A parse tree whose nodes and text are allocated in one [Arena] per
+//! document, for parsing many small documents without allocator churn. The
+//! nodes have no destructors, so dropping or resetting the arena frees the
+//! whole tree at once.
+//!
+//! The tree is built from the events of the [event](crate::event) parser.
+//! Dictionaries assigned to the same key are merged, and assigning any other
+//! value to a key twice is an error. Dictionary entries are sorted by key.
+
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+use crate::{Attribute, AttributeValue, Compound, Dictionary, Element, List, Tagged, Text, Tuple, Value};
+use crate::event::{Event, Events, parse_dictionary_events_with, parse_list_events_with, parse_value_events_with, Reader};
+use crate::parse::ParseOptions;
+use crate::parse::parser::ParseError;
+use crate::pdm::{ParsedAttribute, ParsedCompound, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedText, ParsedTuple, ParsedValue, Position, SharedStr};
+
+/// An allocation region for parse trees.
+///
+/// Reset the arena to reuse its memory for the next document.
+#[derive(Default)]
+pub struct Arena {
+    bump: Bump,
+}
+
+impl Arena {
+
+    pub fn new() -> Self {
+        Arena { bump: Bump::new() }
+    }
+
+    /// Create an arena with room for a number of bytes.
+    pub fn with_capacity(bytes: usize) -> Self {
+        Arena { bump: Bump::with_capacity(bytes) }
+    }
+
+    /// Free all trees allocated in the arena, keeping its memory.
+    pub fn reset(&mut self) {
+        self.bump.reset();
+    }
+
+    /// Number of bytes allocated by the arena.
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+}
+
+/// Parse a value document string into an arena.
+pub fn parse_arena_value<'a>(arena: &'a Arena, document: &str) -> Result<ArenaValue<'a>, Vec<ParseError>> {
+    parse_arena_value_with(arena, document, &ParseOptions::default())
+}
+
+/// Parse a value document string into an arena within the limits of the
+/// options.
+pub fn parse_arena_value_with<'a>(arena: &'a Arena, document: &str, options: &ParseOptions) -> Result<ArenaValue<'a>, Vec<ParseError>> {
+    build_document(arena, parse_value_events_with(document, options))
+}
+
+/// Parse a dictionary document string into an arena.
+pub fn parse_arena_dictionary<'a>(arena: &'a Arena, document: &str) -> Result<ArenaDictionary<'a>, Vec<ParseError>> {
+    parse_arena_dictionary_with(arena, document, &ParseOptions::default())
+}
+
+/// Parse a dictionary document string into an arena within the limits of the
+/// options.
+pub fn parse_arena_dictionary_with<'a>(arena: &'a Arena, document: &str, options: &ParseOptions) -> Result<ArenaDictionary<'a>, Vec<ParseError>> {
+    match build_document(arena, parse_dictionary_events_with(document, options))? {
+        ArenaValue::Dictionary(dictionary, ..) => Ok(dictionary),
+        _ => unreachable!("A dictionary document is a dictionary."),
+    }
+}
+
+/// Parse a list document string into an arena.
+pub fn parse_arena_list<'a>(arena: &'a Arena, document: &str) -> Result<ArenaList<'a>, Vec<ParseError>> {
+    parse_arena_list_with(arena, document, &ParseOptions::default())
+}
+
+/// Parse a list document string into an arena within the limits of the options.
+pub fn parse_arena_list_with<'a>(arena: &'a Arena, document: &str, options: &ParseOptions) -> Result<ArenaList<'a>, Vec<ParseError>> {
+    match build_document(arena, parse_list_events_with(document, options))? {
+        ArenaValue::List(list, ..) => Ok(list),
+        _ => unreachable!("A list document is a list."),
+    }
+}
+
+//// Value
+
+/// A parsed value allocated in an arena.
+pub enum ArenaValue<'a> {
+    Text(ArenaText<'a>, Position, Position),
+    Tagged(ArenaTaggedValue<'a>, Position, Position),
+    Tuple(ArenaTuple<'a>, Position, Position),
+    Dictionary(ArenaDictionary<'a>, Position, Position),
+    List(ArenaList<'a>, Position, Position),
+    Compound(ArenaCompound<'a>, Position, Position),
+    Nil(Position, Position),
+}
+
+impl<'a> ArenaValue<'a> {
+
+    pub fn from(&self) -> Position {
+        match self {
+            ArenaValue::Text(.., from, _) => *from,
+            ArenaValue::Tagged(.., from, _) => *from,
+            ArenaValue::Tuple(.., from, _) => *from,
+            ArenaValue::Dictionary(.., from, _) => *from,
+            ArenaValue::List(.., from, _) => *from,
+            ArenaValue::Compound(.., from, _) => *from,
+            ArenaValue::Nil(from, _) => *from,
+        }
+    }
+
+    pub fn to(&self) -> Position {
+        match self {
+            ArenaValue::Text(.., to) => *to,
+            ArenaValue::Tagged(.., to) => *to,
+            ArenaValue::Tuple(.., to) => *to,
+            ArenaValue::Dictionary(.., to) => *to,
+            ArenaValue::List(.., to) => *to,
+            ArenaValue::Compound(.., to) => *to,
+            ArenaValue::Nil(_, to) => *to,
+        }
+    }
+
+    /// Copy this value out of the arena into a parsed value.
+    pub fn to_parsed(&self) -> ParsedValue {
+        match self {
+            ArenaValue::Text(text, from, to) => ParsedValue::Text(ParsedText { str: SharedStr::from(text.str) }, *from, *to),
+            ArenaValue::Tagged(tag, from, to) => {
+                let attributes = tag.attributes.iter().map(|(k, v)| ParsedAttribute(SharedStr::from(*k), v.map(SharedStr::from))).collect();
+                let tag = ParsedTaggedValue { name: SharedStr::from(tag.name), attributes, value: Box::new(tag.value.to_parsed()) };
+                ParsedValue::Tagged(tag, *from, *to)
+            }
+            ArenaValue::Tuple(tuple, from, to) => {
+                let tuple = match &*tuple.elements {
+                    [] => ParsedTuple::Unit,
+                    [value] => ParsedTuple::Single(Box::new(value.to_parsed())),
+                    values => ParsedTuple::Multiple(values.iter().map(ArenaValue::to_parsed).collect()),
+                };
+                ParsedValue::Tuple(tuple, *from, *to)
+            }
+            ArenaValue::Dictionary(dictionary, from, to) => ParsedValue::Dictionary(dictionary.to_parsed(), *from, *to),
+            ArenaValue::List(list, from, to) => ParsedValue::List(list.to_parsed(), *from, *to),
+            ArenaValue::Compound(compound, from, to) => {
+                let components = compound.components.iter().map(ArenaValue::to_parsed).collect();
+                let compound = ParsedCompound { components, whitespace: compound.whitespace.to_vec() };
+                ParsedValue::Compound(compound, *from, *to)
+            }
+            ArenaValue::Nil(from, to) => ParsedValue::Nil(*from, *to),
+        }
+    }
+
+}
+
+impl<'a> Value<ArenaValue<'a>, ArenaText<'a>, ArenaDictionary<'a>, ArenaList<'a>, ArenaCompound<'a>, ArenaTuple<'a>, ArenaTaggedValue<'a>> for ArenaValue<'a> {
+
+    fn is_text(&self) -> bool {
+        matches!(self, ArenaValue::Text(..))
+    }
+
+    fn is_tagged(&self) -> bool {
+        matches!(self, ArenaValue::Tagged(..))
+    }
+
+    fn is_tuple(&self) -> bool {
+        matches!(self, ArenaValue::Tuple(..))
+    }
+
+    fn is_dictionary(&self) -> bool {
+        matches!(self, ArenaValue::Dictionary(..))
+    }
+
+    fn is_list(&self) -> bool {
+        matches!(self, ArenaValue::List(..))
+    }
+
+    fn is_compound(&self) -> bool {
+        matches!(self, ArenaValue::Compound(..))
+    }
+
+    fn is_nil(&self) -> bool {
+        matches!(self, ArenaValue::Nil(..))
+    }
+
+    fn as_text(&self) -> Option<&ArenaText<'a>> {
+        if let ArenaValue::Text(text, ..) = self { Some(text) } else { None }
+    }
+
+    fn as_tagged(&self) -> Option<&ArenaTaggedValue<'a>> {
+        if let ArenaValue::Tagged(tag, ..) = self { Some(tag) } else { None }
+    }
+
+    fn as_tuple(&self) -> Option<&ArenaTuple<'a>> {
+        if let ArenaValue::Tuple(tuple, ..) = self { Some(tuple) } else { None }
+    }
+
+    fn as_dictionary(&self) -> Option<&ArenaDictionary<'a>> {
+        if let ArenaValue::Dictionary(dictionary, ..) = self { Some(dictionary) } else { None }
+    }
+
+    fn as_list(&self) -> Option<&ArenaList<'a>> {
+        if let ArenaValue::List(list, ..) = self { Some(list) } else { None }
+    }
+
+    fn as_compound(&self) -> Option<&ArenaCompound<'a>> {
+        if let ArenaValue::Compound(compound, ..) = self { Some(compound) } else { None }
+    }
+
+    fn as_mut_text(&mut self) -> Option<&mut ArenaText<'a>> {
+        if let ArenaValue::Text(text, ..) = self { Some(text) } else { None }
+    }
+
+    fn as_mut_tagged(&mut self) -> Option<&mut ArenaTaggedValue<'a>> {
+        if let ArenaValue::Tagged(tag, ..) = self { Some(tag) } else { None }
+    }
+
+    fn as_mut_tuple(&mut self) -> Option<&mut ArenaTuple<'a>> {
+        if let ArenaValue::Tuple(tuple, ..) = self { Some(tuple) } else { None }
+    }
+
+    fn as_mut_dictionary(&mut self) -> Option<&mut ArenaDictionary<'a>> {
+        if let ArenaValue::Dictionary(dictionary, ..) = self { Some(dictionary) } else { None }
+    }
+
+    fn as_mut_list(&mut self) -> Option<&mut ArenaList<'a>> {
+        if let ArenaValue::List(list, ..) = self { Some(list) } else { None }
+    }
+
+    fn as_mut_compound(&mut self) -> Option<&mut ArenaCompound<'a>> {
+        if let ArenaValue::Compound(compound, ..) = self { Some(compound) } else { None }
+    }
+
+    fn iter_as_tuple<'b>(&'b self) -> impl Iterator<Item=&'b ArenaValue<'a>> where ArenaValue<'a>: 'b {
+        match self {
+            ArenaValue::Tuple(tuple, ..) => tuple.elements.iter(),
+            value => std::slice::from_ref(value).iter(),
+        }
+    }
+
+    fn len_as_tuple(&self) -> usize {
+        match self {
+            ArenaValue::Tuple(tuple, ..) => tuple.elements.len(),
+            _ => 1,
+        }
+    }
+
+}
+
+//// Text
+
+pub struct ArenaText<'a> {
+    pub str: &'a str,
+}
+
+impl<'a> Text<ArenaValue<'a>, ArenaText<'a>, ArenaDictionary<'a>, ArenaList<'a>, ArenaCompound<'a>, ArenaTuple<'a>, ArenaTaggedValue<'a>> for ArenaText<'a> {
+
+    fn as_str(&self) -> &str {
+        self.str
+    }
+
+}
+
+//// Tagged value
+
+pub struct ArenaTaggedValue<'a> {
+    pub name: &'a str,
+    pub attributes: &'a [(&'a str, Option<&'a str>)],
+    pub value: &'a mut ArenaValue<'a>,
+}
+
+impl<'a> Tagged<ArenaValue<'a>, ArenaText<'a>, ArenaDictionary<'a>, ArenaList<'a>, ArenaCompound<'a>, ArenaTuple<'a>, ArenaTaggedValue<'a>> for ArenaTaggedValue<'a> {
+
+    type AttributeIterator<'b> = ArenaAttributeIterator<'b> where Self: 'b;
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn has_attributes(&self) -> bool {
+        !self.attributes.is_empty()
+    }
+
+    fn get_attribute_by(&self, key: &str) -> Option<AttributeValue<'_>> {
+        self.attributes.iter().find(|(k, _)| *k == key).map(|(_, v)| AttributeValue(*v))
+    }
+
+    fn get_attribute_at(&self, index: usize) -> Option<Attribute<'_>> {
+        self.attributes.get(index).map(|(k, v)| Attribute(k, *v))
+    }
+
+    fn iter_attributes(&self) -> Self::AttributeIterator<'_> {
+        ArenaAttributeIterator { iter: self.attributes.iter() }
+    }
+
+    fn get(&self) -> &ArenaValue<'a> {
+        self.value
+    }
+
+}
+
+pub struct ArenaAttributeIterator<'b> {
+    iter: std::slice::Iter<'b, (&'b str, Option<&'b str>)>,
+}
+
+impl<'b> Iterator for ArenaAttributeIterator<'b> {
+
+    type Item = Attribute<'b>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, v)| Attribute(k, *v))
+    }
+
+}
+
+//// Tuple
+
+pub struct ArenaTuple<'a> {
+    pub elements: &'a mut [ArenaValue<'a>],
+}
+
+impl<'a> Tuple<ArenaValue<'a>, ArenaText<'a>, ArenaDictionary<'a>, ArenaList<'a>, ArenaCompound<'a>, ArenaTuple<'a>, ArenaTaggedValue<'a>> for ArenaTuple<'a> {
+
+    type TupleIterator<'b> = std::slice::Iter<'b, ArenaValue<'a>> where Self: 'b, ArenaValue<'a>: 'b;
+
+    fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    fn get(&self, index: usize) -> Option<&ArenaValue<'a>> {
+        self.elements.get(index)
+    }
+
+    fn iter(&self) -> Self::TupleIterator<'_> {
+        self.elements.iter()
+    }
+
+}
+
+//// Dictionary
+
+pub struct ArenaDictionary<'a> {
+    /// Entries sorted by key.
+    pub entries: &'a mut [(&'a str, ArenaValue<'a>)],
+}
+
+impl<'a> ArenaDictionary<'a> {
+
+    fn index_of(&self, key: &str) -> Option<usize> {
+        self.entries.binary_search_by(|(k, _)| (*k).cmp(key)).ok()
+    }
+
+    /// Copy this dictionary out of the arena into a parsed dictionary.
+    pub fn to_parsed(&self) -> ParsedDictionary {
+        let entries = self.entries.iter().map(|(k, v)| (SharedStr::from(*k), v.to_parsed())).collect();
+        ParsedDictionary { entries }
+    }
+
+}
+
+impl<'a> Dictionary<ArenaValue<'a>, ArenaText<'a>, ArenaDictionary<'a>, ArenaList<'a>, ArenaCompound<'a>, ArenaTuple<'a>, ArenaTaggedValue<'a>> for ArenaDictionary<'a> {
+
+    type EntryIterator<'b> = ArenaEntryIterator<'a, 'b> where Self: 'b, ArenaValue<'a>: 'b;
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn get(&self, key: &str) -> Option<&ArenaValue<'a>> {
+        self.index_of(key).map(|i| &self.entries[i].1)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut ArenaValue<'a>> {
+        self.index_of(key).map(|i| &mut self.entries[i].1)
+    }
+
+    fn iter(&self) -> Self::EntryIterator<'_> {
+        ArenaEntryIterator { iter: self.entries.iter() }
+    }
+
+}
+
+pub struct ArenaEntryIterator<'a, 'b> {
+    iter: std::slice::Iter<'b, (&'a str, ArenaValue<'a>)>,
+}
+
+impl<'a, 'b> Iterator for ArenaEntryIterator<'a, 'b> {
+
+    type Item = (&'b str, &'b ArenaValue<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, v)| (*k, v))
+    }
+
+}
+
+//// List
+
+pub struct ArenaList<'a> {
+    pub elements: &'a mut [ArenaValue<'a>],
+}
+
+impl<'a> ArenaList<'a> {
+
+    /// Copy this list out of the arena into a parsed list.
+    pub fn to_parsed(&self) -> ParsedList {
+        ParsedList { elements: self.elements.iter().map(ArenaValue::to_parsed).collect() }
+    }
+
+}
+
+impl<'a> List<ArenaValue<'a>, ArenaText<'a>, ArenaDictionary<'a>, ArenaList<'a>, ArenaCompound<'a>, ArenaTuple<'a>, ArenaTaggedValue<'a>> for ArenaList<'a> {
+
+    type ListIterator<'b> = std::slice::Iter<'b, ArenaValue<'a>> where Self: 'b, ArenaValue<'a>: 'b;
+
+    fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    fn get_element(&self, index: usize) -> Option<&ArenaValue<'a>> {
+        self.elements.get(index)
+    }
+
+    fn iter(&self) -> Self::ListIterator<'_> {
+        self.elements.iter()
+    }
+
+}
+
+//// Compound
+
+pub struct ArenaCompound<'a> {
+    pub components: &'a mut [ArenaValue<'a>],
+    /// Whether there is whitespace between each pair of adjacent components.
+    pub whitespace: &'a [bool],
+}
+
+impl<'a> ArenaCompound<'a> {
+
+    fn element(&self, index: usize) -> Option<(Element<&ArenaValue<'a>>, usize)> {
+        let mut component = 0;
+        let mut i = 0;
+        while component < self.components.len() {
+            if i == index {
+                return Some((Element::Element(&self.components[component]), component));
+            }
+            i += 1;
+            if self.whitespace.get(component) == Some(&true) {
+                if i == index {
+                    return Some((Element::Whitespace, component));
+                }
+                i += 1;
+            }
+            component += 1;
+        }
+        None
+    }
+
+}
+
+impl<'a> Compound<ArenaValue<'a>, ArenaText<'a>, ArenaDictionary<'a>, ArenaList<'a>, ArenaCompound<'a>, ArenaTuple<'a>, ArenaTaggedValue<'a>> for ArenaCompound<'a> {
+
+    type ElementIterator<'b> = ArenaElementIterator<'a, 'b> where Self: 'b, ArenaValue<'a>: 'b;
+
+    fn len(&self) -> usize {
+        self.components.len() + self.whitespace.iter().filter(|w| **w).count()
+    }
+
+    fn get(&self, index: usize) -> Option<Element<&ArenaValue<'a>>> {
+        self.element(index).map(|(element, _)| element)
+    }
+
+    fn iter(&self) -> Self::ElementIterator<'_> {
+        ArenaElementIterator { components: self.components, whitespace: self.whitespace, index: 0, after_component: false }
+    }
+
+}
+
+pub struct ArenaElementIterator<'a, 'b> {
+    components: &'b [ArenaValue<'a>],
+    whitespace: &'b [bool],
+    index: usize,
+    after_component: bool,
+}
+
+impl<'a, 'b> Iterator for ArenaElementIterator<'a, 'b> {
+
+    type Item = Element<&'b ArenaValue<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.after_component {
+            let whitespace = self.whitespace.get(self.index).copied();
+            self.index += 1;
+            self.after_component = false;
+            if whitespace == Some(true) {
+                return Some(Element::Whitespace);
+            }
+        }
+        let component = self.components.get(self.index)?;
+        self.after_component = true;
+        Some(Element::Element(component))
+    }
+
+}
+
+//// Builder
+
+/// Builds a tree in an arena.
+///
+/// The children of the nodes being built are kept on shared stacks and moved
+/// into the arena when their parent ends.
+/// Builds a tree from events, keeping the values of the constructs being built
+/// on stacks in the arena.
+struct Builder<'a> {
+    bump: &'a Bump,
+    values: BumpVec<'a, ArenaValue<'a>>,
+    entries: BumpVec<'a, (Position, &'a str, ArenaValue<'a>)>,
+    whitespace: BumpVec<'a, bool>,
+    errors: Vec<ParseError>,
+}
+
+fn build_document<'a>(arena: &'a Arena, events: Events<'_>) -> Result<ArenaValue<'a>, Vec<ParseError>> {
+    let bump = &arena.bump;
+    let mut builder = Builder { bump, values: BumpVec::new_in(bump), entries: BumpVec::new_in(bump), whitespace: BumpVec::new_in(bump), errors: vec![] };
+    let mut reader = Reader::new(events);
+    let value = builder.build(&mut reader);
+    reader.finish();
+    let mut errors = reader.errors;
+    errors.append(&mut builder.errors);
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors)
+    }
+}
+
+impl<'a> Builder<'a> {
+
+    /// Build a value from its events.
+    ///
+    /// The events of a document with errors may end early, in which case the
+    /// value is incomplete.
+    fn build(&mut self, reader: &mut Reader<'_>) -> ArenaValue<'a> {
+        match reader.next() {
+            Some(Event::Text(from, to, text)) => ArenaValue::Text(ArenaText { str: self.bump.alloc_str(&text) }, from, to),
+            Some(Event::Nil(from, to)) => ArenaValue::Nil(from, to),
+            Some(Event::StartTuple(from)) => {
+                let elements = self.build_sequence(reader);
+                let to = reader.end();
+                ArenaValue::Tuple(ArenaTuple { elements }, from, to)
+            }
+            Some(Event::StartList(from)) => {
+                let elements = self.build_sequence(reader);
+                let to = reader.end();
+                ArenaValue::List(ArenaList { elements }, from, to)
+            }
+            Some(Event::StartCompound(from)) => {
+                let start = self.values.len();
+                let whitespace_start = self.whitespace.len();
+                let mut space = false;
+                while !reader.at_end() {
+                    if let Some(Event::Whitespace) = reader.peek() {
+                        reader.next();
+                        space = true;
+                    } else {
+                        if self.values.len() > start {
+                            self.whitespace.push(space);
+                        }
+                        space = false;
+                        let value = self.build(reader);
+                        self.values.push(value);
+                    }
+                }
+                let to = reader.end();
+                let components = self.bump.alloc_slice_fill_iter(self.values.drain(start..));
+                let whitespace = self.bump.alloc_slice_copy(&self.whitespace[whitespace_start..]);
+                self.whitespace.truncate(whitespace_start);
+                ArenaValue::Compound(ArenaCompound { components, whitespace }, from, to)
+            }
+            Some(Event::StartDictionary(from)) => {
+                let start = self.entries.len();
+                while !reader.at_end() {
+                    if let Some(Event::Key(at, _, key)) = reader.next() {
+                        let key = self.bump.alloc_str(&key);
+                        let value = self.build(reader);
+                        self.entries.push((at, key, value));
+                    }
+                }
+                let to = reader.end();
+                ArenaValue::Dictionary(self.finish_dictionary(start), from, to)
+            }
+            Some(Event::StartTag(from, name, attributes)) => {
+                let name = self.bump.alloc_str(&name);
+                let bump = self.bump;
+                let attributes = bump.alloc_slice_fill_iter(attributes.iter().map(|(k, v)| {
+                    (&*bump.alloc_str(k), v.as_ref().map(|v| &*bump.alloc_str(v)))
+                }));
+                let value = self.build(reader);
+                let value = self.bump.alloc(value);
+                let to = reader.end();
+                ArenaValue::Tagged(ArenaTaggedValue { name, attributes, value }, from, to)
+            }
+            _ => {
+                let at = Position { index: 0, line: 0, column: 0 };
+                ArenaValue::Nil(at, at)
+            }
+        }
+    }
+
+    /// Build values until an end event.
+    fn build_sequence(&mut self, reader: &mut Reader<'_>) -> &'a mut [ArenaValue<'a>] {
+        let start = self.values.len();
+        while !reader.at_end() {
+            let value = self.build(reader);
+            self.values.push(value);
+        }
+        self.bump.alloc_slice_fill_iter(self.values.drain(start..))
+    }
+
+    /// Move the entries from start into the arena, sorted by key, merging
+    /// dictionaries assigned to the same key.
+    fn finish_dictionary(&mut self, start: usize) -> ArenaDictionary<'a> {
+        let entries = &mut self.entries[start..];
+        entries.sort_unstable_by(|(a_at, a, _), (b_at, b, _)| a.cmp(b).then(a_at.index.cmp(&b_at.index)));
+        if entries.windows(2).any(|w| w[0].1 == w[1].1) {
+            let entries = BumpVec::from_iter_in(self.entries.drain(start..), self.bump);
+            for (at, key, value) in entries {
+                match self.entries[start..].last_mut() {
+                    Some((_, last, existing)) if *last == key => {
+                        let existing = std::mem::replace(existing, ArenaValue::Nil(at, at));
+                        let merged = self.merge(existing, value, at);
+                        self.entries.last_mut().unwrap().2 = merged;
+                    }
+                    _ => self.entries.push((at, key, value)),
+                }
+            }
+        }
+        let entries = self.bump.alloc_slice_fill_iter(self.entries.drain(start..).map(|(_, key, value)| (key, value)));
+        ArenaDictionary { entries }
+    }
+
+    /// Merge a value assigned to a key that already has a value.
+    fn merge(&mut self, existing: ArenaValue<'a>, value: ArenaValue<'a>, at: Position) -> ArenaValue<'a> {
+        match (existing, value) {
+            (ArenaValue::Dictionary(existing, from, to), ArenaValue::Dictionary(value, ..)) => {
+                let start = self.entries.len();
+                for entries in [existing.entries, value.entries] {
+                    for (key, value) in entries.iter_mut() {
+                        let value = std::mem::replace(value, ArenaValue::Nil(at, at));
+                        self.entries.push((at, key, value));
+                    }
+                }
+                ArenaValue::Dictionary(self.finish_dictionary(start), from, to)
+            }
+            (existing, _) => {
+                self.errors.push(ParseError::KeyAlreadyAssigned(at));
+                existing
+            }
+        }
+    }
+
+}
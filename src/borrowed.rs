@@ -0,0 +1,409 @@
+//! Borrowed parse tree.
+//!
+//! A parse tree whose text is borrowed from the document instead of copied
+//! into reference counted strings, for parsing large read-only documents. Text,
+//! keys, tag names and attributes are [Cow::Borrowed] unless escape sequences,
+//! text block trimming or words joined across lines make them differ from the
+//! document.
+//!
+//! The tree is built from the events of the [event](crate::event) parser.
+//! Dictionaries assigned to the same key are merged, and assigning any other
+//! value to a key twice is an error.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use crate::event::{Event, Events, parse_dictionary_events_with, parse_list_events_with, parse_value_events_with, Reader};
+use crate::parse::ParseOptions;
+use crate::parse::parser::ParseError;
+use crate::pdm::{ParsedAttribute, ParsedCompound, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedText, ParsedTuple, ParsedValue, Position, SharedStr};
+
+/// Parse a value document string into a borrowed tree.
+pub fn parse_borrowed_value(document: &str) -> Result<BorrowedValue<'_>, Vec<ParseError>> {
+    parse_borrowed_value_with(document, &ParseOptions::default())
+}
+
+/// Parse a value document string into a borrowed tree within the limits of the
+/// options.
+pub fn parse_borrowed_value_with<'a>(document: &'a str, options: &ParseOptions) -> Result<BorrowedValue<'a>, Vec<ParseError>> {
+    build_document(parse_value_events_with(document, options))
+}
+
+/// Parse a dictionary document string into a borrowed tree.
+pub fn parse_borrowed_dictionary(document: &str) -> Result<BorrowedDictionary<'_>, Vec<ParseError>> {
+    parse_borrowed_dictionary_with(document, &ParseOptions::default())
+}
+
+/// Parse a dictionary document string into a borrowed tree within the limits of
+/// the options.
+pub fn parse_borrowed_dictionary_with<'a>(document: &'a str, options: &ParseOptions) -> Result<BorrowedDictionary<'a>, Vec<ParseError>> {
+    match build_document(parse_dictionary_events_with(document, options))? {
+        BorrowedValue::Dictionary(dictionary, ..) => Ok(dictionary),
+        _ => unreachable!("A dictionary document is a dictionary."),
+    }
+}
+
+/// Parse a list document string into a borrowed tree.
+pub fn parse_borrowed_list(document: &str) -> Result<BorrowedList<'_>, Vec<ParseError>> {
+    parse_borrowed_list_with(document, &ParseOptions::default())
+}
+
+/// Parse a list document string into a borrowed tree within the limits of the
+/// options.
+pub fn parse_borrowed_list_with<'a>(document: &'a str, options: &ParseOptions) -> Result<BorrowedList<'a>, Vec<ParseError>> {
+    match build_document(parse_list_events_with(document, options))? {
+        BorrowedValue::List(list, ..) => Ok(list),
+        _ => unreachable!("A list document is a list."),
+    }
+}
+
+//// Value
+
+/// A parsed value borrowing its text from the document.
+#[derive(Clone)]
+pub enum BorrowedValue<'a> {
+    Text(Cow<'a, str>, Position, Position),
+    Tagged(BorrowedTaggedValue<'a>, Position, Position),
+    Tuple(BorrowedTuple<'a>, Position, Position),
+    Dictionary(BorrowedDictionary<'a>, Position, Position),
+    List(BorrowedList<'a>, Position, Position),
+    Compound(BorrowedCompound<'a>, Position, Position),
+    Nil(Position, Position),
+}
+
+impl<'a> BorrowedValue<'a> {
+
+    pub fn from(&self) -> Position {
+        match self {
+            BorrowedValue::Text(.., from, _) => *from,
+            BorrowedValue::Tagged(.., from, _) => *from,
+            BorrowedValue::Tuple(.., from, _) => *from,
+            BorrowedValue::Dictionary(.., from, _) => *from,
+            BorrowedValue::List(.., from, _) => *from,
+            BorrowedValue::Compound(.., from, _) => *from,
+            BorrowedValue::Nil(from, _) => *from,
+        }
+    }
+
+    pub fn to(&self) -> Position {
+        match self {
+            BorrowedValue::Text(.., to) => *to,
+            BorrowedValue::Tagged(.., to) => *to,
+            BorrowedValue::Tuple(.., to) => *to,
+            BorrowedValue::Dictionary(.., to) => *to,
+            BorrowedValue::List(.., to) => *to,
+            BorrowedValue::Compound(.., to) => *to,
+            BorrowedValue::Nil(_, to) => *to,
+        }
+    }
+
+    /// Get as text.
+    pub fn as_text(&self) -> Option<&Cow<'a, str>> {
+        match self {
+            BorrowedValue::Text(text, ..) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Get as a tagged value.
+    pub fn as_tagged(&self) -> Option<&BorrowedTaggedValue<'a>> {
+        match self {
+            BorrowedValue::Tagged(tag, ..) => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// Get as a tuple.
+    pub fn as_tuple(&self) -> Option<&BorrowedTuple<'a>> {
+        match self {
+            BorrowedValue::Tuple(tuple, ..) => Some(tuple),
+            _ => None,
+        }
+    }
+
+    /// Get as a dictionary.
+    pub fn as_dictionary(&self) -> Option<&BorrowedDictionary<'a>> {
+        match self {
+            BorrowedValue::Dictionary(dictionary, ..) => Some(dictionary),
+            _ => None,
+        }
+    }
+
+    /// Get as a list.
+    pub fn as_list(&self) -> Option<&BorrowedList<'a>> {
+        match self {
+            BorrowedValue::List(list, ..) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Get as a compound.
+    pub fn as_compound(&self) -> Option<&BorrowedCompound<'a>> {
+        match self {
+            BorrowedValue::Compound(compound, ..) => Some(compound),
+            _ => None,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, BorrowedValue::Nil(..))
+    }
+
+    /// Copy this value into a parsed value.
+    pub fn to_parsed(&self) -> ParsedValue {
+        match self {
+            BorrowedValue::Text(text, from, to) => ParsedValue::Text(ParsedText { str: SharedStr::from(&**text) }, *from, *to),
+            BorrowedValue::Tagged(tag, from, to) => ParsedValue::Tagged(tag.to_parsed(), *from, *to),
+            BorrowedValue::Tuple(tuple, from, to) => {
+                let tuple = match tuple {
+                    BorrowedTuple::Unit => ParsedTuple::Unit,
+                    BorrowedTuple::Single(value) => ParsedTuple::Single(Box::new(value.to_parsed())),
+                    BorrowedTuple::Multiple(values) => ParsedTuple::Multiple(values.iter().map(BorrowedValue::to_parsed).collect()),
+                };
+                ParsedValue::Tuple(tuple, *from, *to)
+            }
+            BorrowedValue::Dictionary(dictionary, from, to) => ParsedValue::Dictionary(dictionary.to_parsed(), *from, *to),
+            BorrowedValue::List(list, from, to) => ParsedValue::List(list.to_parsed(), *from, *to),
+            BorrowedValue::Compound(compound, from, to) => {
+                let components = compound.components.iter().map(BorrowedValue::to_parsed).collect();
+                let compound = ParsedCompound { components, whitespace: compound.whitespace.clone() };
+                ParsedValue::Compound(compound, *from, *to)
+            }
+            BorrowedValue::Nil(from, to) => ParsedValue::Nil(*from, *to),
+        }
+    }
+
+}
+
+//// Tagged
+
+#[derive(Clone)]
+pub struct BorrowedTaggedValue<'a> {
+    pub name: Cow<'a, str>,
+    pub attributes: Vec<(Cow<'a, str>, Option<Cow<'a, str>>)>,
+    pub value: Box<BorrowedValue<'a>>,
+}
+
+impl<'a> BorrowedTaggedValue<'a> {
+
+    /// Get the attribute by key.
+    pub fn get_attribute(&self, key: &str) -> Option<Option<&Cow<'a, str>>> {
+        self.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_ref())
+    }
+
+    fn to_parsed(&self) -> ParsedTaggedValue {
+        let attributes = self.attributes.iter().map(|(k, v)| {
+            ParsedAttribute(SharedStr::from(&**k), v.as_ref().map(|v| SharedStr::from(&**v)))
+        }).collect();
+        ParsedTaggedValue { name: SharedStr::from(&*self.name), attributes, value: Box::new(self.value.to_parsed()) }
+    }
+
+}
+
+//// Tuple
+
+#[derive(Clone)]
+pub enum BorrowedTuple<'a> {
+    Unit,
+    Single(Box<BorrowedValue<'a>>),
+    Multiple(Vec<BorrowedValue<'a>>),
+}
+
+impl<'a> BorrowedTuple<'a> {
+
+    pub fn len(&self) -> usize {
+        match self {
+            BorrowedTuple::Unit => 0,
+            BorrowedTuple::Single(..) => 1,
+            BorrowedTuple::Multiple(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, BorrowedTuple::Unit)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&BorrowedValue<'a>> {
+        match self {
+            BorrowedTuple::Unit => None,
+            BorrowedTuple::Single(value) => if index == 0 { Some(value) } else { None },
+            BorrowedTuple::Multiple(values) => values.get(index),
+        }
+    }
+
+}
+
+//// Dictionary
+
+#[derive(Clone)]
+pub struct BorrowedDictionary<'a> {
+    pub entries: HashMap<Cow<'a, str>, BorrowedValue<'a>>,
+}
+
+impl<'a> BorrowedDictionary<'a> {
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BorrowedValue<'a>> {
+        self.entries.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Cow<'a, str>, &BorrowedValue<'a>)> {
+        self.entries.iter()
+    }
+
+    /// Copy this dictionary into a parsed dictionary.
+    pub fn to_parsed(&self) -> ParsedDictionary {
+        let entries = self.entries.iter().map(|(k, v)| (SharedStr::from(&**k), v.to_parsed())).collect();
+        ParsedDictionary { entries }
+    }
+
+}
+
+//// List
+
+#[derive(Clone)]
+pub struct BorrowedList<'a> {
+    pub elements: Vec<BorrowedValue<'a>>,
+}
+
+impl<'a> BorrowedList<'a> {
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&BorrowedValue<'a>> {
+        self.elements.get(index)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, BorrowedValue<'a>> {
+        self.elements.iter()
+    }
+
+    /// Copy this list into a parsed list.
+    pub fn to_parsed(&self) -> ParsedList {
+        ParsedList { elements: self.elements.iter().map(BorrowedValue::to_parsed).collect() }
+    }
+
+}
+
+//// Compound
+
+#[derive(Clone)]
+pub struct BorrowedCompound<'a> {
+    pub components: Vec<BorrowedValue<'a>>,
+    /// Whether there is whitespace between each pair of adjacent components.
+    pub whitespace: Vec<bool>,
+}
+
+//// Builder
+
+fn build_document(events: Events<'_>) -> Result<BorrowedValue<'_>, Vec<ParseError>> {
+    let mut reader = Reader::new(events);
+    let value = build(&mut reader);
+    reader.finish();
+    if reader.errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(reader.errors)
+    }
+}
+
+/// Build a value from its events.
+///
+/// The events of a document with errors may end early, in which case the
+/// value is incomplete.
+fn build<'a>(reader: &mut Reader<'a>) -> BorrowedValue<'a> {
+    match reader.next() {
+        Some(Event::Text(from, to, text)) => BorrowedValue::Text(text, from, to),
+        Some(Event::Nil(from, to)) => BorrowedValue::Nil(from, to),
+        Some(Event::StartTuple(from)) => {
+            let mut values = build_sequence(reader);
+            let to = reader.end();
+            let tuple = match values.len() {
+                0 => BorrowedTuple::Unit,
+                1 => BorrowedTuple::Single(Box::new(values.pop().unwrap())),
+                _ => BorrowedTuple::Multiple(values),
+            };
+            BorrowedValue::Tuple(tuple, from, to)
+        }
+        Some(Event::StartList(from)) => {
+            let elements = build_sequence(reader);
+            let to = reader.end();
+            BorrowedValue::List(BorrowedList { elements }, from, to)
+        }
+        Some(Event::StartCompound(from)) => {
+            let mut components = vec![];
+            let mut whitespace = vec![];
+            let mut space = false;
+            while !reader.at_end() {
+                if let Some(Event::Whitespace) = reader.peek() {
+                    reader.next();
+                    space = true;
+                } else {
+                    if !components.is_empty() {
+                        whitespace.push(space);
+                    }
+                    space = false;
+                    components.push(build(reader));
+                }
+            }
+            let to = reader.end();
+            BorrowedValue::Compound(BorrowedCompound { components, whitespace }, from, to)
+        }
+        Some(Event::StartDictionary(from)) => {
+            let mut dictionary = BorrowedDictionary { entries: HashMap::new() };
+            while !reader.at_end() {
+                if let Some(Event::Key(at, _, key)) = reader.next() {
+                    let value = build(reader);
+                    insert(&mut dictionary, key, value, at, &mut reader.errors);
+                }
+            }
+            let to = reader.end();
+            BorrowedValue::Dictionary(dictionary, from, to)
+        }
+        Some(Event::StartTag(from, name, attributes)) => {
+            let value = Box::new(build(reader));
+            let to = reader.end();
+            BorrowedValue::Tagged(BorrowedTaggedValue { name, attributes, value }, from, to)
+        }
+        _ => {
+            let at = Position { index: 0, line: 0, column: 0 };
+            BorrowedValue::Nil(at, at)
+        }
+    }
+}
+
+/// Build values until an end event.
+fn build_sequence<'a>(reader: &mut Reader<'a>) -> Vec<BorrowedValue<'a>> {
+    let mut values = vec![];
+    while !reader.at_end() {
+        values.push(build(reader));
+    }
+    values
+}
+
+/// Insert an entry, merging dictionaries assigned to the same key.
+fn insert<'a>(dictionary: &mut BorrowedDictionary<'a>, key: Cow<'a, str>, value: BorrowedValue<'a>, at: Position, errors: &mut Vec<ParseError>) {
+    match (dictionary.entries.get_mut(&key), value) {
+        (None, value) => {
+            dictionary.entries.insert(key, value);
+        }
+        (Some(BorrowedValue::Dictionary(existing, ..)), BorrowedValue::Dictionary(value, ..)) => {
+            for (key, value) in value.entries {
+                insert(existing, key, value, at, errors);
+            }
+        }
+        (Some(..), ..) => errors.push(ParseError::KeyAlreadyAssigned(at)),
+    }
+}
@@ -0,0 +1,186 @@
+//! Conditional inclusion.
+//!
+//! `<if!>:name:value` is replaced by the value if name is defined, and removed
+//! otherwise. The condition `!name` holds if name is not defined. An
+//! `<else!>:value` directly following a conditional in a compound or list is
+//! included if the condition does not hold:
+//!
+//! ```text
+//! <if!>:draft:{<p>:Draft} <else!>:{<p>:Final}
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use crate::{Tagged, Value};
+use crate::pdm::{ParsedDictionary, ParsedList, ParsedTuple, ParsedValue, Position};
+
+/// Name of the conditional tag.
+pub const IF_TAG: &str = "if!";
+
+/// Name of the alternative tag.
+pub const ELSE_TAG: &str = "else!";
+
+/// Evaluate the conditionals of a value document against a set of defines.
+pub fn evaluate_conditionals(value: &mut ParsedValue, defines: &HashSet<String>) -> Result<(), Vec<ConditionError>> {
+    let mut evaluator = Evaluator { defines, errors: vec![] };
+    let (from, to) = (value.from(), value.to());
+    let taken = std::mem::replace(value, ParsedValue::Nil(from, to));
+    let mut last = None;
+    *value = evaluator.evaluate(taken, &mut last).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to));
+    if evaluator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(evaluator.errors)
+    }
+}
+
+struct Evaluator<'a> {
+    defines: &'a HashSet<String>,
+    errors: Vec<ConditionError>,
+}
+
+impl<'a> Evaluator<'a> {
+
+    /// Evaluate a value. Returns `None` if the value is removed.
+    ///
+    /// Last is the outcome of the preceding conditional among the siblings of
+    /// the value, if the value directly follows one.
+    fn evaluate(&mut self, value: ParsedValue, last: &mut Option<bool>) -> Option<ParsedValue> {
+        match value {
+            ParsedValue::Tagged(tag, from, _) if tag.name() == IF_TAG => {
+                let (condition, body) = match *tag.value {
+                    ParsedValue::Tuple(ParsedTuple::Multiple(arguments), ..) if arguments.len() == 2 && !tag.has_attributes() => {
+                        let mut arguments = arguments.into_vec();
+                        let body = arguments.pop().unwrap();
+                        (arguments.pop().unwrap(), body)
+                    }
+                    _ => {
+                        self.errors.push(ConditionError::IllegalConditional(from));
+                        *last = None;
+                        return None;
+                    }
+                };
+                let holds = match condition.as_text() {
+                    Some(condition) => self.holds(&condition.str),
+                    None => {
+                        self.errors.push(ConditionError::IllegalConditional(from));
+                        *last = None;
+                        return None;
+                    }
+                };
+                *last = Some(holds);
+                if holds {
+                    self.evaluate(body, &mut None)
+                } else {
+                    None
+                }
+            }
+            ParsedValue::Tagged(tag, from, _) if tag.name() == ELSE_TAG => {
+                let holds = match last.take() {
+                    Some(holds) => holds,
+                    None => {
+                        self.errors.push(ConditionError::UnmatchedElse(from));
+                        return None;
+                    }
+                };
+                if holds {
+                    None
+                } else {
+                    self.evaluate(*tag.value, &mut None)
+                }
+            }
+            value => {
+                *last = None;
+                self.evaluate_children(value)
+            }
+        }
+    }
+
+    fn evaluate_children(&mut self, value: ParsedValue) -> Option<ParsedValue> {
+        match value {
+            ParsedValue::Tagged(mut tag, from, to) => {
+                let inner = std::mem::replace(&mut *tag.value, ParsedValue::Nil(from, to));
+                *tag.value = self.evaluate(inner, &mut None).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to));
+                Some(ParsedValue::Tagged(tag, from, to))
+            }
+            ParsedValue::Compound(compound, from, to) => {
+                let mut components = vec![];
+                let mut whitespace = vec![];
+                let mut last = None;
+                for (i, component) in compound.components.into_iter().enumerate() {
+                    let space = i > 0 && compound.whitespace[i - 1];
+                    if let Some(component) = self.evaluate(component, &mut last) {
+                        if !components.is_empty() {
+                            whitespace.push(space);
+                        }
+                        components.push(component);
+                    }
+                }
+                if components.is_empty() {
+                    None
+                } else {
+                    Some(ParsedValue::from_terms(from, to, components, whitespace))
+                }
+            }
+            ParsedValue::List(list, from, to) => {
+                let mut last = None;
+                let elements = list.elements.into_iter().filter_map(|e| self.evaluate(e, &mut last)).collect();
+                Some(ParsedValue::List(ParsedList { elements }, from, to))
+            }
+            ParsedValue::Dictionary(dictionary, from, to) => {
+                let mut entries = HashMap::new();
+                for (key, value) in dictionary.entries {
+                    if let Some(value) = self.evaluate(value, &mut None) {
+                        entries.insert(key, value);
+                    }
+                }
+                Some(ParsedValue::Dictionary(ParsedDictionary { entries }, from, to))
+            }
+            ParsedValue::Tuple(ParsedTuple::Single(value), from, to) => {
+                let value = self.evaluate(*value, &mut None).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to));
+                Some(ParsedValue::Tuple(ParsedTuple::Single(Box::new(value)), from, to))
+            }
+            ParsedValue::Tuple(ParsedTuple::Multiple(values), from, to) => {
+                let mut last = None;
+                let values: Vec<ParsedValue> = values.into_vec().into_iter().filter_map(|v| self.evaluate(v, &mut last)).collect();
+                Some(ParsedValue::from_tuple(values, from, to))
+            }
+            value => Some(value),
+        }
+    }
+
+    fn holds(&self, condition: &str) -> bool {
+        match condition.strip_prefix('!') {
+            Some(name) => !self.defines.contains(name),
+            None => self.defines.contains(condition),
+        }
+    }
+
+}
+
+//// Errors
+
+/// Error evaluating conditionals.
+pub enum ConditionError {
+    /// Conditional at X is malformed.
+    IllegalConditional(Position),
+    /// Alternative at X does not follow a conditional.
+    UnmatchedElse(Position),
+}
+
+impl Debug for ConditionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", condition_error_to_string(self))
+    }
+}
+
+pub fn condition_error_to_string(error: &ConditionError) -> String {
+    match error {
+        ConditionError::IllegalConditional(at) => {
+            format!("Conditional at {}:{} must take a condition and a value.", at.line, at.column)
+        }
+        ConditionError::UnmatchedElse(at) => {
+            format!("Alternative at {}:{} does not follow a conditional.", at.line, at.column)
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Configuration loader.
+//!
+//! Loads a configuration from a stack of layers: dictionary documents,
+//! environment variables and command line overrides. Later layers take
+//! precedence over earlier ones, and every value remembers its origin.
+//!
+//! An environment variable `APP__DB__PORT` with prefix `APP` sets the text
+//! value of the key `db.port`. A command line override `--set db.port=6543`
+//! sets `db.port` to the value document `6543`.
+
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use crate::merge::{merge, MergedDictionary, MergedValue, MergeStrategy, Origin, origin_to_string, Source, source_to_string};
+use crate::parse::{parse_dictionary_str, parse_value_str};
+use crate::parse::parser::{error_to_string, ParseError};
+use crate::pdm::{ParsedText, ParsedValue, Position, SharedStr};
+
+/// Separator of keys in environment variable names.
+const ENVIRONMENT_SEPARATOR: &str = "__";
+
+/// Strategy used for environment variables and command line overrides.
+///
+/// An override only replaces the value at its key path.
+const OVERRIDE_STRATEGY: MergeStrategy = MergeStrategy { deep: true, lists: crate::merge::ListStrategy::Replace };
+
+//// Loader
+
+/// A configuration loader.
+pub struct ConfigLoader {
+    strategy: MergeStrategy,
+    layers: Vec<(MergedDictionary, MergeStrategy)>,
+}
+
+impl ConfigLoader {
+
+    pub fn new() -> Self {
+        ConfigLoader { strategy: MergeStrategy::default(), layers: vec![] }
+    }
+
+    /// Set the strategy used to merge documents.
+    pub fn with_strategy(strategy: MergeStrategy) -> Self {
+        ConfigLoader { strategy, layers: vec![] }
+    }
+
+    /// Add a dictionary document file.
+    pub fn file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self, ConfigError> {
+        let path = path.as_ref();
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => return Err(ConfigError::IoError(path.to_path_buf(), error)),
+        };
+        self.document(&path.to_string_lossy(), &source)
+    }
+
+    /// Add a dictionary document file if it exists.
+    pub fn optional_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self, ConfigError> {
+        if path.as_ref().exists() {
+            self.file(path)
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Add a dictionary document with the given name.
+    pub fn document(&mut self, name: &str, source: &str) -> Result<&mut Self, ConfigError> {
+        let source_name = Source::Document(SharedStr::from(name));
+        let dictionary = match parse_dictionary_str(source) {
+            Ok(dictionary) => dictionary,
+            Err(errors) => return Err(ConfigError::ParseError(source_name, errors)),
+        };
+        self.layers.push((MergedDictionary::new(&dictionary, source_name), self.strategy));
+        Ok(self)
+    }
+
+    /// Add the environment variables of this process starting with the prefix.
+    pub fn environment(&mut self, prefix: &str) -> &mut Self {
+        self.variables(prefix, std::env::vars())
+    }
+
+    /// Add variables starting with the prefix.
+    ///
+    /// The name `PREFIX__A__B` sets the key `a.b` to the text of the variable.
+    pub fn variables<I: IntoIterator<Item = (String, String)>>(&mut self, prefix: &str, variables: I) -> &mut Self {
+        let prefix = format!("{}{}", prefix, ENVIRONMENT_SEPARATOR);
+        let mut variables: Vec<(String, String)> = variables.into_iter().filter(|(name, _)| name.starts_with(&prefix)).collect();
+        variables.sort();
+        for (name, value) in variables {
+            let path: Vec<String> = name[prefix.len()..].split(ENVIRONMENT_SEPARATOR).map(|k| k.to_lowercase()).collect();
+            if path.iter().any(|k| k.is_empty()) {
+                continue;
+            }
+            let at = Position { index: 0, line: 1, column: 1 };
+            let value = ParsedValue::Text(ParsedText { str: SharedStr::from(value.as_str()) }, at, at);
+            self.layers.push((layer(&path, &value, Source::Environment(SharedStr::from(name.as_str()))), OVERRIDE_STRATEGY));
+        }
+        self
+    }
+
+    /// Add an override of the form `key.path=value`, where value is a value
+    /// document.
+    pub fn set(&mut self, assignment: &str) -> Result<&mut Self, ConfigError> {
+        let source = Source::Argument(SharedStr::from(assignment));
+        let (path, value) = match assignment.split_once('=') {
+            Some((path, value)) => (path, value),
+            None => return Err(ConfigError::InvalidOverride(assignment.to_string())),
+        };
+        let path: Vec<String> = path.trim().split('.').map(String::from).collect();
+        if path.iter().any(|k| k.is_empty()) {
+            return Err(ConfigError::InvalidOverride(assignment.to_string()));
+        }
+        let value = match parse_value_str(value) {
+            Ok(value) => value,
+            Err(errors) => return Err(ConfigError::ParseError(source, errors)),
+        };
+        self.layers.push((layer(&path, &value, source), OVERRIDE_STRATEGY));
+        Ok(self)
+    }
+
+    /// Add the `--set key.path=value` overrides of command line arguments.
+    ///
+    /// Returns the remaining arguments.
+    pub fn args<I: IntoIterator<Item = String>>(&mut self, args: I) -> Result<Vec<String>, ConfigError> {
+        let mut remaining = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--set" {
+                match args.next() {
+                    Some(assignment) => self.set(&assignment)?,
+                    None => return Err(ConfigError::InvalidOverride(arg)),
+                };
+            } else if let Some(assignment) = arg.strip_prefix("--set=") {
+                self.set(assignment)?;
+            } else {
+                remaining.push(arg);
+            }
+        }
+        Ok(remaining)
+    }
+
+    /// Merge the layers.
+    pub fn load(&self) -> Config {
+        let mut merged = MergedDictionary::empty();
+        for (layer, strategy) in &self.layers {
+            merged = merge(&merged, layer, strategy);
+        }
+        Config { dictionary: merged }
+    }
+
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Create a layer setting a single key path.
+fn layer(path: &[String], value: &ParsedValue, source: Source) -> MergedDictionary {
+    let origin = Origin { source: source.clone(), from: value.from(), to: value.to() };
+    let mut current = MergedValue::from_value(value, &source);
+    for key in path.iter().skip(1).rev() {
+        let mut dictionary = MergedDictionary::empty();
+        dictionary.entries.insert(SharedStr::from(key.as_str()), current);
+        current = MergedValue::Dictionary(dictionary, origin.clone());
+    }
+    let mut dictionary = MergedDictionary::empty();
+    dictionary.entries.insert(SharedStr::from(path[0].as_str()), current);
+    dictionary
+}
+
+//// Config
+
+/// A loaded configuration.
+pub struct Config {
+    pub dictionary: MergedDictionary,
+}
+
+impl Config {
+
+    /// Get the value at a dotted key path.
+    pub fn get(&self, path: &str) -> Option<ParsedValue> {
+        self.dictionary.get_path(&split_path(path))?.to_value()
+    }
+
+    /// Get the origin of the value at a dotted key path.
+    pub fn origin(&self, path: &str) -> Option<&Origin> {
+        self.dictionary.origin(&split_path(path))
+    }
+
+    /// Explain where the value at a dotted key path came from.
+    pub fn explain(&self, path: &str) -> Option<String> {
+        let value = self.dictionary.get_path(&split_path(path))?;
+        let text = crate::format::format_value(&value.to_value()?);
+        Some(format!("{} = {} (from {})", path, text, origin_to_string(value.origin())))
+    }
+
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('.').collect()
+}
+
+//// Errors
+
+pub enum ConfigError {
+    /// File X could not be read.
+    IoError(PathBuf, std::io::Error),
+    /// Source X could not be parsed.
+    ParseError(Source, Vec<ParseError>),
+    /// Override X is not of the form `key.path=value`.
+    InvalidOverride(String),
+}
+
+impl Debug for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", config_error_to_string(self))
+    }
+}
+
+pub fn config_error_to_string(error: &ConfigError) -> String {
+    match error {
+        ConfigError::IoError(path, error) => {
+            format!("Could not read {}: {}", path.display(), error)
+        }
+        ConfigError::ParseError(source, errors) => {
+            let mut string = format!("Could not parse {}:", source_to_string(source));
+            for error in errors {
+                string.push(' ');
+                string.push_str(&error_to_string(error));
+            }
+            string
+        }
+        ConfigError::InvalidOverride(argument) => {
+            format!("Override {} is not of the form key.path=value.", argument)
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Structural diff between documents.
+//!
+//! Compares two parsed documents and produces the list of changes that turn
+//! the first into the second. Layout such as alignment, notation and comments
+//! is ignored, only the structure is compared.
+
+use std::collections::BTreeSet;
+use std::fmt::{Debug, Formatter};
+use crate::{Dictionary, Tagged, Tuple};
+use crate::format::{format_key, format_value};
+use crate::pdm::{ParsedAttribute, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedTuple, ParsedValue, Position, SharedStr};
+
+/// Lists longer than this are compared element by element when the remaining
+/// difference table would exceed this many cells.
+const MAX_LCS_CELLS: usize = 1 << 22;
+
+//// Path
+
+/// A step in a path from the document root to a value.
+#[derive(PartialEq, Eq, Clone)]
+pub enum Step {
+    /// Entry of a dictionary.
+    Key(SharedStr),
+    /// Element of a list.
+    Index(usize),
+    /// Element of a tuple.
+    Element(usize),
+    /// Value of a tagged value with the given tag name.
+    Tag(SharedStr),
+}
+
+/// Render a path.
+pub fn path_to_string(path: &[Step]) -> String {
+    let mut string = String::new();
+    for step in path {
+        match step {
+            Step::Key(key) => {
+                if !string.is_empty() {
+                    string.push('.');
+                }
+                string.push_str(&format_key(key));
+            }
+            Step::Index(index) => string.push_str(&format!("[{}]", index)),
+            Step::Element(index) => string.push_str(&format!("|{}", index)),
+            Step::Tag(name) => string.push_str(&format!("<{}>", name)),
+        }
+    }
+    if string.is_empty() {
+        string.push('.');
+    }
+    string
+}
+
+//// Change
+
+/// A change from one document to another.
+///
+/// Paths refer to the document as it is when the preceding changes have been
+/// applied. For list elements, this is the index in the new list.
+#[derive(Clone)]
+pub struct Change {
+    pub path: Vec<Step>,
+    pub kind: ChangeKind,
+    /// Span of the changed value in the old document.
+    pub before: Option<(Position, Position)>,
+    /// Span of the changed value in the new document.
+    pub after: Option<(Position, Position)>,
+}
+
+#[derive(Clone)]
+pub enum ChangeKind {
+    /// A key was added to a dictionary.
+    Added(ParsedValue),
+    /// A key was removed from a dictionary.
+    Removed(ParsedValue),
+    /// A value was replaced.
+    Changed(ParsedValue, ParsedValue),
+    /// An element was inserted into a list.
+    Inserted(ParsedValue),
+    /// An element was deleted from a list. Holds the index in the old list.
+    Deleted(usize, ParsedValue),
+    /// A tag was renamed.
+    Renamed(SharedStr, SharedStr),
+    /// An attribute was added to a tag.
+    AttributeAdded(SharedStr, Option<SharedStr>),
+    /// An attribute was removed from a tag.
+    AttributeRemoved(SharedStr, Option<SharedStr>),
+    /// The value of an attribute was changed.
+    AttributeChanged(SharedStr, Option<SharedStr>, Option<SharedStr>),
+}
+
+impl Debug for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", change_to_string(self))
+    }
+}
+
+//// Diff
+
+/// Compute the changes between two values.
+pub fn diff(a: &ParsedValue, b: &ParsedValue) -> Vec<Change> {
+    let mut differ = Differ { path: vec![], changes: vec![] };
+    differ.diff_value(a, b);
+    differ.changes
+}
+
+/// Compute the changes between two dictionary documents.
+pub fn diff_dictionaries(a: &ParsedDictionary, b: &ParsedDictionary) -> Vec<Change> {
+    let mut differ = Differ { path: vec![], changes: vec![] };
+    differ.diff_dictionary(a, b);
+    differ.changes
+}
+
+/// Compute the changes between two list documents.
+pub fn diff_lists(a: &ParsedList, b: &ParsedList) -> Vec<Change> {
+    let mut differ = Differ { path: vec![], changes: vec![] };
+    differ.diff_list(a, b);
+    differ.changes
+}
+
+struct Differ {
+    path: Vec<Step>,
+    changes: Vec<Change>,
+}
+
+impl Differ {
+
+    fn push(&mut self, kind: ChangeKind, before: Option<&ParsedValue>, after: Option<&ParsedValue>) {
+        self.changes.push(Change {
+            path: self.path.clone(),
+            kind,
+            before: before.map(|v| (v.from(), v.to())),
+            after: after.map(|v| (v.from(), v.to())),
+        });
+    }
+
+    fn diff_value(&mut self, a: &ParsedValue, b: &ParsedValue) {
+        match (a, b) {
+            (ParsedValue::Dictionary(x, ..), ParsedValue::Dictionary(y, ..)) => self.diff_dictionary(x, y),
+            (ParsedValue::List(x, ..), ParsedValue::List(y, ..)) => self.diff_list(x, y),
+            (ParsedValue::Tuple(x, ..), ParsedValue::Tuple(y, ..)) if x.len() == y.len() => {
+                for (i, (x, y)) in x.iter().zip(y.iter()).enumerate() {
+                    self.path.push(Step::Element(i));
+                    self.diff_value(x, y);
+                    self.path.pop();
+                }
+            }
+            (ParsedValue::Tagged(x, ..), ParsedValue::Tagged(y, ..)) => self.diff_tagged(x, y, a, b),
+            _ => {
+                if !equal(a, b) {
+                    self.push(ChangeKind::Changed(a.clone(), b.clone()), Some(a), Some(b));
+                }
+            }
+        }
+    }
+
+    fn diff_dictionary(&mut self, a: &ParsedDictionary, b: &ParsedDictionary) {
+        let keys: BTreeSet<&SharedStr> = a.entries.keys().chain(b.entries.keys()).collect();
+        for key in keys {
+            self.path.push(Step::Key(key.clone()));
+            match (a.entries.get(key), b.entries.get(key)) {
+                (Some(x), Some(y)) => self.diff_value(x, y),
+                (Some(x), None) => self.push(ChangeKind::Removed(x.clone()), Some(x), None),
+                (None, Some(y)) => self.push(ChangeKind::Added(y.clone()), None, Some(y)),
+                (None, None) => {}
+            }
+            self.path.pop();
+        }
+    }
+
+    fn diff_tagged(&mut self, x: &ParsedTaggedValue, y: &ParsedTaggedValue, a: &ParsedValue, b: &ParsedValue) {
+        if x.name != y.name {
+            self.push(ChangeKind::Renamed(x.name.clone(), y.name.clone()), Some(a), Some(b));
+        }
+        for ParsedAttribute(key, value) in &x.attributes {
+            match find_attribute(y, key) {
+                Some(other) => {
+                    if value != other {
+                        self.push(ChangeKind::AttributeChanged(key.clone(), value.clone(), other.clone()), Some(a), Some(b));
+                    }
+                }
+                None => self.push(ChangeKind::AttributeRemoved(key.clone(), value.clone()), Some(a), Some(b)),
+            }
+        }
+        for ParsedAttribute(key, value) in &y.attributes {
+            if find_attribute(x, key).is_none() {
+                self.push(ChangeKind::AttributeAdded(key.clone(), value.clone()), Some(a), Some(b));
+            }
+        }
+        self.path.push(Step::Tag(y.name.clone()));
+        self.diff_value(x.get(), y.get());
+        self.path.pop();
+    }
+
+    /// Compare lists by their longest common subsequence.
+    ///
+    /// Elements between matched elements are paired up and compared, and the
+    /// remaining elements are inserted or deleted.
+    fn diff_list(&mut self, a: &ParsedList, b: &ParsedList) {
+        let a = &a.elements;
+        let b = &b.elements;
+        let mut prefix = 0;
+        while prefix < a.len() && prefix < b.len() && equal(&a[prefix], &b[prefix]) {
+            prefix += 1;
+        }
+        let mut suffix = 0;
+        while suffix < a.len() - prefix && suffix < b.len() - prefix && equal(&a[a.len() - 1 - suffix], &b[b.len() - 1 - suffix]) {
+            suffix += 1;
+        }
+        let x = &a[prefix..a.len() - suffix];
+        let y = &b[prefix..b.len() - suffix];
+        let matches = if x.len() * y.len() <= MAX_LCS_CELLS {
+            longest_common_subsequence(x, y)
+        } else {
+            vec![]
+        };
+        let (mut i, mut j) = (0, 0);
+        for (mi, mj) in matches.into_iter().chain(std::iter::once((x.len(), y.len()))) {
+            self.diff_gap(&x[i..mi], &y[j..mj], prefix + i, prefix + j);
+            i = mi + 1;
+            j = mj + 1;
+        }
+    }
+
+    /// Compare elements between two matched elements.
+    fn diff_gap(&mut self, x: &[ParsedValue], y: &[ParsedValue], i: usize, j: usize) {
+        let paired = x.len().min(y.len());
+        for k in 0..paired {
+            self.path.push(Step::Index(j + k));
+            self.diff_value(&x[k], &y[k]);
+            self.path.pop();
+        }
+        for (k, value) in x[paired..].iter().enumerate() {
+            self.path.push(Step::Index(j + paired));
+            self.push(ChangeKind::Deleted(i + paired + k, value.clone()), Some(value), None);
+            self.path.pop();
+        }
+        for (k, value) in y[paired..].iter().enumerate() {
+            self.path.push(Step::Index(j + paired + k));
+            self.push(ChangeKind::Inserted(value.clone()), None, Some(value));
+            self.path.pop();
+        }
+    }
+
+}
+
+fn find_attribute<'a>(tag: &'a ParsedTaggedValue, key: &str) -> Option<&'a Option<SharedStr>> {
+    tag.attributes.iter().find(|ParsedAttribute(k, _)| k.as_ref() == key).map(|ParsedAttribute(_, v)| v)
+}
+
+/// Find the indices of the matching elements of a longest common subsequence.
+fn longest_common_subsequence(x: &[ParsedValue], y: &[ParsedValue]) -> Vec<(usize, usize)> {
+    let n = x.len();
+    let m = y.len();
+    // table[i][j] is the length of the LCS of x[i..] and y[j..].
+    let mut table = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[i * (m + 1) + j] = if equal(&x[i], &y[j]) {
+                table[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                table[(i + 1) * (m + 1) + j].max(table[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    let mut matches = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if equal(&x[i], &y[j]) {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * (m + 1) + j] >= table[i * (m + 1) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+//// Equality
+
+/// Check if two values are structurally equal.
+///
+/// Positions are ignored.
+pub fn equal(a: &ParsedValue, b: &ParsedValue) -> bool {
+    match (a, b) {
+        (ParsedValue::Nil(..), ParsedValue::Nil(..)) => true,
+        (ParsedValue::Text(x, ..), ParsedValue::Text(y, ..)) => x.str == y.str,
+        (ParsedValue::Tagged(x, ..), ParsedValue::Tagged(y, ..)) => {
+            x.name == y.name
+                && x.attributes.len() == y.attributes.len()
+                && x.attributes.iter().zip(y.attributes.iter()).all(|(ParsedAttribute(k, v), ParsedAttribute(l, w))| k == l && v == w)
+                && equal(x.get(), y.get())
+        }
+        (ParsedValue::Tuple(x, ..), ParsedValue::Tuple(y, ..)) => {
+            match (x, y) {
+                (ParsedTuple::Unit, ParsedTuple::Unit) => true,
+                (ParsedTuple::Single(x), ParsedTuple::Single(y)) => equal(x, y),
+                (ParsedTuple::Multiple(x), ParsedTuple::Multiple(y)) => {
+                    x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| equal(x, y))
+                }
+                _ => false,
+            }
+        }
+        (ParsedValue::Dictionary(x, ..), ParsedValue::Dictionary(y, ..)) => equal_dictionaries(x, y),
+        (ParsedValue::List(x, ..), ParsedValue::List(y, ..)) => {
+            x.elements.len() == y.elements.len() && x.elements.iter().zip(y.elements.iter()).all(|(x, y)| equal(x, y))
+        }
+        (ParsedValue::Compound(x, ..), ParsedValue::Compound(y, ..)) => {
+            x.whitespace == y.whitespace
+                && x.components.len() == y.components.len()
+                && x.components.iter().zip(y.components.iter()).all(|(x, y)| equal(x, y))
+        }
+        _ => false,
+    }
+}
+
+/// Check if two dictionaries are structurally equal.
+pub fn equal_dictionaries(a: &ParsedDictionary, b: &ParsedDictionary) -> bool {
+    a.len() == b.len() && a.iter().all(|(k, x)| b.get(k).map_or(false, |y| equal(x, y)))
+}
+
+//// Rendering
+
+/// Render changes in a human-readable form, one change per line.
+pub fn render_diff(changes: &[Change]) -> String {
+    let mut string = String::new();
+    for change in changes {
+        string.push_str(&change_to_string(change));
+        string.push('\n');
+    }
+    string
+}
+
+/// Render a change in a human-readable form.
+pub fn change_to_string(change: &Change) -> String {
+    let path = path_to_string(&change.path);
+    let spans = match (&change.before, &change.after) {
+        (Some((a, _)), Some((b, _))) => format!("{}:{} -> {}:{}", a.line, a.column, b.line, b.column),
+        (Some((a, _)), None) => format!("{}:{}", a.line, a.column),
+        (None, Some((b, _))) => format!("{}:{}", b.line, b.column),
+        (None, None) => String::new(),
+    };
+    match &change.kind {
+        ChangeKind::Added(value) => {
+            format!("+ {}: {} ({})", path, excerpt(value), spans)
+        }
+        ChangeKind::Removed(value) => {
+            format!("- {}: {} ({})", path, excerpt(value), spans)
+        }
+        ChangeKind::Changed(old, new) => {
+            format!("~ {}: {} -> {} ({})", path, excerpt(old), excerpt(new), spans)
+        }
+        ChangeKind::Inserted(value) => {
+            format!("+ {}: {} ({})", path, excerpt(value), spans)
+        }
+        ChangeKind::Deleted(index, value) => {
+            let mut path = change.path.clone();
+            path.pop();
+            path.push(Step::Index(*index));
+            format!("- {}: {} ({})", path_to_string(&path), excerpt(value), spans)
+        }
+        ChangeKind::Renamed(old, new) => {
+            format!("~ {}: tag <{}> renamed to <{}> ({})", path, old, new, spans)
+        }
+        ChangeKind::AttributeAdded(key, value) => {
+            format!("+ {}: attribute {} ({})", path, attribute_to_string(key, value), spans)
+        }
+        ChangeKind::AttributeRemoved(key, value) => {
+            format!("- {}: attribute {} ({})", path, attribute_to_string(key, value), spans)
+        }
+        ChangeKind::AttributeChanged(key, old, new) => {
+            format!("~ {}: attribute {} -> {} ({})", path, attribute_to_string(key, old), attribute_to_string(key, new), spans)
+        }
+    }
+}
+
+fn attribute_to_string(key: &str, value: &Option<SharedStr>) -> String {
+    match value {
+        Some(value) => format!("{}:{}", key, value),
+        None => key.to_string(),
+    }
+}
+
+/// Format a value, shortened to fit on a line.
+fn excerpt(value: &ParsedValue) -> String {
+    const MAX: usize = 60;
+    let string = format_value(value);
+    if string.chars().count() > MAX {
+        let mut short: String = string.chars().take(MAX - 1).collect();
+        short.push('…');
+        short
+    } else {
+        string
+    }
+}
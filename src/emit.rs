@@ -0,0 +1,337 @@
+//! Streaming emitter. Writes Khi documents incrementally.
+//!
+//! An [Emitter] writes a document as a sequence of calls, without building a
+//! tree first. Dictionaries and lists are opened with
+//! [begin_dictionary](Emitter::begin_dictionary) and
+//! [begin_list](Emitter::begin_list), tagged values with [tag](Emitter::tag),
+//! and each is closed with [end](Emitter::end). Every value in a dictionary
+//! is preceded by a [key](Emitter::key).
+//!
+//! Text is written as words if possible, otherwise as a transcription.
+//! Multi-line text is written as a raw text block.
+
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::Write;
+use crate::format::{is_single_string, write_argument, write_string, write_text, write_value, write_word};
+use crate::pdm::ParsedValue;
+
+/// Notation of dictionaries and lists.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum Notation {
+    /// Entries on one line, separated by semicolons.
+    Delimited,
+    /// One entry per line, indented. List elements are bulleted.
+    Aligned,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+enum Kind {
+    ValueDocument,
+    DictionaryDocument,
+    ListDocument,
+    Dictionary,
+    List,
+    Tag,
+}
+
+struct Frame {
+    kind: Kind,
+    /// Number of values written.
+    count: usize,
+    /// Whether a key is waiting for its value.
+    key: bool,
+}
+
+/// Writes a document incrementally.
+pub struct Emitter<W: Write> {
+    writer: W,
+    notation: Notation,
+    frames: Vec<Frame>,
+    buffer: String,
+}
+
+impl<W: Write> Emitter<W> {
+
+    /// Emit a value document.
+    pub fn value_document(writer: W, notation: Notation) -> Self {
+        Self::new(writer, notation, Kind::ValueDocument)
+    }
+
+    /// Emit a dictionary document.
+    pub fn dictionary_document(writer: W, notation: Notation) -> Self {
+        Self::new(writer, notation, Kind::DictionaryDocument)
+    }
+
+    /// Emit a list document.
+    pub fn list_document(writer: W, notation: Notation) -> Self {
+        Self::new(writer, notation, Kind::ListDocument)
+    }
+
+    fn new(writer: W, notation: Notation, kind: Kind) -> Self {
+        Emitter { writer, notation, frames: vec![Frame { kind, count: 0, key: false }], buffer: String::new() }
+    }
+
+    /// Write the key of the next value of a dictionary.
+    pub fn key(&mut self, key: &str) -> Result<(), EmitError> {
+        let depth = self.depth();
+        let notation = self.notation;
+        let frame = self.frames.last_mut().unwrap();
+        if !matches!(frame.kind, Kind::Dictionary | Kind::DictionaryDocument) || frame.key {
+            return Err(EmitError::UnexpectedKey);
+        }
+        frame.key = true;
+        self.buffer.clear();
+        if frame.count != 0 || frame.kind == Kind::Dictionary {
+            match (notation, frame.kind) {
+                (Notation::Aligned, _) => new_line(&mut self.buffer, depth),
+                (Notation::Delimited, Kind::DictionaryDocument) => self.buffer.push_str(";\n"),
+                (Notation::Delimited, _) if frame.count != 0 => self.buffer.push_str("; "),
+                _ => {}
+            }
+        }
+        write_string(&mut self.buffer, key);
+        self.buffer.push_str(": ");
+        self.flush_buffer()
+    }
+
+    /// Write text.
+    pub fn text(&mut self, text: &str) -> Result<(), EmitError> {
+        let tag = self.begin_value()?;
+        if text.contains('\n') && !text.contains('\r') { // Carriage returns are skipped in text blocks.
+            if tag {
+                self.buffer.push(':');
+            }
+            write_text_block(&mut self.buffer, text);
+        } else if tag && !is_single_string(text) {
+            self.buffer.push_str(":{");
+            write_text(&mut self.buffer, text);
+            self.buffer.push('}');
+        } else {
+            if tag {
+                self.buffer.push(':');
+            }
+            write_text(&mut self.buffer, text);
+        }
+        self.flush_buffer()
+    }
+
+    /// Write a parsed value.
+    pub fn value(&mut self, value: &ParsedValue) -> Result<(), EmitError> {
+        if let ParsedValue::Text(text, ..) = value {
+            return self.text(&text.str);
+        }
+        let tag = self.begin_value()?;
+        if tag {
+            self.buffer.push(':');
+            write_argument(&mut self.buffer, value, true);
+        } else {
+            write_value(&mut self.buffer, value);
+        }
+        self.flush_buffer()
+    }
+
+    /// Begin a dictionary.
+    pub fn begin_dictionary(&mut self) -> Result<(), EmitError> {
+        if self.begin_value()? {
+            self.buffer.push(':');
+        }
+        self.buffer.push('{');
+        self.frames.push(Frame { kind: Kind::Dictionary, count: 0, key: false });
+        self.flush_buffer()
+    }
+
+    /// Begin a list.
+    pub fn begin_list(&mut self) -> Result<(), EmitError> {
+        if self.begin_value()? {
+            self.buffer.push(':');
+        }
+        self.buffer.push('[');
+        self.frames.push(Frame { kind: Kind::List, count: 0, key: false });
+        self.flush_buffer()
+    }
+
+    /// Begin a tagged value. The tagged value takes at most one value before
+    /// it is ended.
+    pub fn tag(&mut self, name: &str, attributes: &[(&str, Option<&str>)]) -> Result<(), EmitError> {
+        if self.begin_value()? {
+            self.buffer.push(':');
+        }
+        self.buffer.push('<');
+        write_word(&mut self.buffer, name);
+        for (key, value) in attributes {
+            self.buffer.push(' ');
+            write_word(&mut self.buffer, key);
+            if let Some(value) = value {
+                self.buffer.push(':');
+                write_string(&mut self.buffer, value);
+            }
+        }
+        self.buffer.push('>');
+        self.frames.push(Frame { kind: Kind::Tag, count: 0, key: false });
+        self.flush_buffer()
+    }
+
+    /// End the last begun dictionary, list or tagged value.
+    pub fn end(&mut self) -> Result<(), EmitError> {
+        let frame = self.frames.last().unwrap();
+        if matches!(frame.kind, Kind::ValueDocument | Kind::DictionaryDocument | Kind::ListDocument) || frame.key {
+            return Err(EmitError::UnexpectedEnd);
+        }
+        let frame = self.frames.pop().unwrap();
+        self.buffer.clear();
+        if self.notation == Notation::Aligned && frame.count != 0 && frame.kind != Kind::Tag {
+            let depth = self.depth();
+            new_line(&mut self.buffer, depth);
+        }
+        match frame.kind {
+            Kind::Dictionary => self.buffer.push('}'),
+            Kind::List => self.buffer.push(']'),
+            _ => {}
+        }
+        self.flush_buffer()
+    }
+
+    /// Finish the document and flush the writer.
+    pub fn finish(mut self) -> Result<W, EmitError> {
+        if self.frames.len() != 1 || self.frames[0].key {
+            return Err(EmitError::Unfinished);
+        }
+        let frame = &self.frames[0];
+        if frame.kind != Kind::ValueDocument && frame.count != 0 {
+            if let Err(error) = self.writer.write_all(b"\n") {
+                return Err(EmitError::IoError(error));
+            }
+        }
+        match self.writer.flush() {
+            Ok(..) => Ok(self.writer),
+            Err(error) => Err(EmitError::IoError(error)),
+        }
+    }
+
+    /// Write the separator before a value into the buffer. Returns whether the
+    /// value is the argument of a tag.
+    fn begin_value(&mut self) -> Result<bool, EmitError> {
+        let depth = self.depth();
+        let notation = self.notation;
+        let frame = self.frames.last_mut().unwrap();
+        self.buffer.clear();
+        match frame.kind {
+            Kind::ValueDocument | Kind::Tag => {
+                if frame.count != 0 {
+                    return Err(EmitError::UnexpectedValue);
+                }
+            }
+            Kind::DictionaryDocument | Kind::Dictionary => {
+                if !frame.key {
+                    return Err(EmitError::ExpectedKey);
+                }
+                frame.key = false;
+            }
+            Kind::ListDocument => {
+                match notation {
+                    Notation::Aligned => {
+                        if frame.count != 0 {
+                            self.buffer.push('\n');
+                        }
+                        self.buffer.push_str("> ");
+                    }
+                    Notation::Delimited => {
+                        if frame.count != 0 {
+                            self.buffer.push_str(";\n");
+                        }
+                    }
+                }
+            }
+            Kind::List => {
+                match notation {
+                    Notation::Aligned => {
+                        new_line(&mut self.buffer, depth);
+                        self.buffer.push_str("> ");
+                    }
+                    Notation::Delimited => {
+                        if frame.count != 0 {
+                            self.buffer.push_str("; ");
+                        }
+                    }
+                }
+            }
+        }
+        frame.count += 1;
+        Ok(frame.kind == Kind::Tag)
+    }
+
+    /// Number of open dictionaries and lists.
+    fn depth(&self) -> usize {
+        self.frames.iter().filter(|f| matches!(f.kind, Kind::Dictionary | Kind::List)).count()
+    }
+
+    fn flush_buffer(&mut self) -> Result<(), EmitError> {
+        let result = self.writer.write_all(self.buffer.as_bytes());
+        self.buffer.clear();
+        match result {
+            Ok(..) => Ok(()),
+            Err(error) => Err(EmitError::IoError(error)),
+        }
+    }
+
+}
+
+fn new_line(output: &mut String, depth: usize) {
+    output.push('\n');
+    for _ in 0..depth {
+        output.push_str("  ");
+    }
+}
+
+/// Write a raw text block, choosing a closing tag not found in the text.
+fn write_text_block(output: &mut String, text: &str) {
+    let mut name = String::new();
+    let mut i = 0;
+    while text.contains(&format!("<#{}>", name)) {
+        i += 1;
+        name = i.to_string();
+    }
+    output.push_str("<#");
+    output.push_str(&name);
+    output.push_str(" r>");
+    output.push_str(text);
+    output.push_str("<#");
+    output.push_str(&name);
+    output.push('>');
+}
+
+//// Errors
+
+/// Error emitting a document.
+pub enum EmitError {
+    /// Error writing the output.
+    IoError(io::Error),
+    /// A value was written in a dictionary without a key.
+    ExpectedKey,
+    /// A key was written outside of a dictionary or before a value.
+    UnexpectedKey,
+    /// A second value was written in a value document or tagged value.
+    UnexpectedValue,
+    /// End was called without a construct to end, or before a value.
+    UnexpectedEnd,
+    /// The document was finished before all constructs were ended.
+    Unfinished,
+}
+
+impl Debug for EmitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", emit_error_to_string(self))
+    }
+}
+
+pub fn emit_error_to_string(error: &EmitError) -> String {
+    match error {
+        EmitError::IoError(error) => format!("Could not write output: {}", error),
+        EmitError::ExpectedKey => "Expected a key before the value.".to_string(),
+        EmitError::UnexpectedKey => "A key is not allowed here.".to_string(),
+        EmitError::UnexpectedValue => "Another value is not allowed here.".to_string(),
+        EmitError::UnexpectedEnd => "Nothing to end here.".to_string(),
+        EmitError::Unfinished => "Document was finished before all constructs were ended.".to_string(),
+    }
+}
@@ -0,0 +1,1478 @@
+//! Event parser.
+//!
+//! Parses a document into a flat sequence of events rather than a tree, for
+//! consumers that only need some of the document or that map it into their own
+//! structures. The grammar is the same as for the tree parser. The borrowed
+//! and arena trees are built from its events.
+//!
+//! A value is either a single event, or a start event followed by the events
+//! of its contents and a closing [Event::End]:
+//!
+//! * Text is [Event::Text].
+//! * A dictionary starts with [Event::StartDictionary]. Each entry is a
+//!   [Event::Key] followed by its value.
+//! * A list starts with [Event::StartList], followed by its elements.
+//! * A tuple starts with [Event::StartTuple], followed by its elements.
+//! * A compound starts with [Event::StartCompound], followed by its components.
+//!   [Event::Whitespace] separates components with whitespace between them.
+//! * A tagged value starts with [Event::StartTag], followed by its value.
+//!
+//! A key path such as `a:b: value` is reported as nested dictionaries in source
+//! order. Entries with the same key are reported as they are; merging them is
+//! up to the consumer.
+//!
+//! Events are parsed as they are pulled. The top-level tokens of the document
+//! are lexed and reduced on demand, and dictionaries and lists at the top level
+//! are parsed one entry or element at a time, so memory is bounded by the size
+//! of the largest entry or element rather than the size of the document.
+//!
+//! Keys, text, tag names and attributes are borrowed from the document unless
+//! escape sequences, text block trimming or joining words across lines make
+//! them differ from it.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::mem;
+use std::vec;
+use crate::lex::Lexer;
+use crate::parse::{DocumentKind, ParseOptions, reduce_error_to_parse_error, Strictness};
+use crate::parse::parser::{after_open, ParseError, Rule, token_to_rule};
+use crate::parse::reducer::{Reduced, Reducer, StringType};
+use crate::pdm::Position;
+
+/// Parse event.
+#[derive(PartialEq, Eq, Clone)]
+pub enum Event<'a> {
+    /// Start of a dictionary at X.
+    StartDictionary(Position),
+    /// Key from X to Y of the following value.
+    Key(Position, Position, Cow<'a, str>),
+    /// Start of a list at X.
+    StartList(Position),
+    /// Start of a tuple at X.
+    StartTuple(Position),
+    /// Start of a compound at X.
+    StartCompound(Position),
+    /// Whitespace between components of a compound.
+    Whitespace,
+    /// Text from X to Y.
+    Text(Position, Position, Cow<'a, str>),
+    /// Start of a tagged value at X with name Y and attributes Z.
+    StartTag(Position, Cow<'a, str>, Attributes<'a>),
+    /// Empty value from X to Y.
+    Nil(Position, Position),
+    /// End of the last started construct at X.
+    End(Position),
+}
+
+/// Iterator over the events of a document.
+///
+/// Yields an error for each problem found in the document. Parsing goes on
+/// after errors in the whitespace around constructs, and stops after any other
+/// error. A document is valid only if no error is yielded.
+pub struct Events<'a> {
+    parser: EventParser<'a>,
+    /// Top-level constructs being parsed, innermost last.
+    frames: Vec<Frame>,
+    /// Index of the next event to yield in the buffer of the parser.
+    next: usize,
+}
+
+impl<'a> Iterator for Events<'a> {
+
+    type Item = Result<Event<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(error) = self.parser.failure.take() {
+                // The rest of the document cannot be read.
+                self.frames.clear();
+                self.parser.events.clear();
+                self.next = 0;
+                self.parser.errors.push_back(error);
+            }
+            if let Some(error) = self.parser.errors.pop_front() {
+                return Some(Err(error));
+            }
+            if let Some(event) = self.parser.events.get_mut(self.next) {
+                self.next += 1;
+                match event.take() {
+                    Some(event) => return Some(Ok(event)),
+                    None => continue,
+                }
+            }
+            self.parser.events.clear();
+            self.next = 0;
+            if self.frames.is_empty() {
+                return None;
+            }
+            if let Err(error) = self.step() {
+                self.frames.clear();
+                self.parser.events.clear();
+                if self.parser.failure.is_none() {
+                    self.parser.errors.push_back(error);
+                    self.parser.skip_rest();
+                }
+            }
+        }
+    }
+
+}
+
+impl<'a> Events<'a> {
+
+    /// Parse the next entry or element at the top level, or the next step
+    /// around them.
+    fn step(&mut self) -> Result<(), ParseError> {
+        let parser = &mut self.parser;
+        match self.frames.last_mut() {
+            Some(Frame::Value) => {
+                self.frames.pop();
+                parser.parse_value_document()?;
+            }
+            Some(Frame::Dictionary(dictionary)) => match parser.parse_dictionary_step(dictionary)? {
+                Step::Continue => {}
+                Step::Done => {
+                    self.frames.pop();
+                }
+                Step::List(depth) => {
+                    self.frames.push(Frame::Section(depth));
+                    self.frames.push(Frame::List(List::Start));
+                }
+            },
+            Some(Frame::List(list)) => {
+                let done = parser.parse_list_step(list)?;
+                if done {
+                    self.frames.pop();
+                }
+            }
+            Some(Frame::Section(depth)) => {
+                let depth = *depth;
+                self.frames.pop();
+                parser.end_section(depth);
+            }
+            Some(Frame::Close) => {
+                self.frames.pop();
+                let to = parser.at();
+                parser.push(Event::End(to));
+            }
+            Some(Frame::End) => {
+                self.frames.pop();
+                if !parser.is_end() {
+                    let error = ParseError::ExpectedEnd(parser.t0.to_type(), parser.at());
+                    parser.errors.push_back(error);
+                    parser.skip_rest();
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+}
+
+/// Parse a value document string into events.
+pub fn parse_value_events(document: &str) -> Events<'_> {
+    parse_value_events_with(document, &ParseOptions::default())
+}
+
+/// Parse a value document string into events within the limits of the options.
+pub fn parse_value_events_with<'a>(document: &'a str, options: &ParseOptions) -> Events<'a> {
+    document_events(document, DocumentKind::Value, options)
+}
+
+/// Parse a dictionary document string into events.
+pub fn parse_dictionary_events(document: &str) -> Events<'_> {
+    parse_dictionary_events_with(document, &ParseOptions::default())
+}
+
+/// Parse a dictionary document string into events within the limits of the options.
+pub fn parse_dictionary_events_with<'a>(document: &'a str, options: &ParseOptions) -> Events<'a> {
+    document_events(document, DocumentKind::Dictionary, options)
+}
+
+/// Parse a list document string into events.
+pub fn parse_list_events(document: &str) -> Events<'_> {
+    parse_list_events_with(document, &ParseOptions::default())
+}
+
+/// Parse a list document string into events within the limits of the options.
+pub fn parse_list_events_with<'a>(document: &'a str, options: &ParseOptions) -> Events<'a> {
+    document_events(document, DocumentKind::List, options)
+}
+
+/// Parse a document string of a kind into events, reducing its tokens as they
+/// are read.
+pub(crate) fn document_events<'a>(document: &'a str, kind: DocumentKind, options: &ParseOptions) -> Events<'a> {
+    if document.len() > options.max_document_size {
+        let mut events = events(document, Tokens::Reduced(vec![].into_iter()), kind, options);
+        events.frames.clear();
+        events.parser.errors.push_back(ParseError::DocumentTooLarge(document.len(), options.max_document_size));
+        return events;
+    };
+    let mut reducer = Reducer::with_options(Lexer::new(document), *options);
+    let tokens = Tokens::Stream(Box::new(move || reducer.reduce_next().map_err(reduce_error_to_parse_error)));
+    events(document, tokens, kind, options)
+}
+
+fn events<'a>(source: &'a str, tokens: Tokens<'a>, kind: DocumentKind, options: &ParseOptions) -> Events<'a> {
+    let mut parser = EventParser::new(source, tokens, options);
+    let frames = match kind {
+        DocumentKind::Value => vec![Frame::End, Frame::Value],
+        DocumentKind::Dictionary => {
+            let from = parser.at();
+            parser.push(Event::StartDictionary(from));
+            let dictionary = Dictionary::new(&parser);
+            vec![Frame::End, Frame::Close, Frame::Dictionary(dictionary)]
+        }
+        DocumentKind::List => {
+            let from = parser.at();
+            parser.push(Event::StartList(from));
+            if matches!(parser.t0, Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..) | Reduced::Bar(..) | Reduced::Bullet(..) | Reduced::TaggedValueHeader(..)) {
+                vec![Frame::End, Frame::Close, Frame::List(List::Start)]
+            } else {
+                vec![Frame::End, Frame::Close]
+            }
+        }
+    };
+    Events { parser, frames, next: 0 }
+}
+
+/// Events with the errors set aside, for building trees.
+pub(crate) struct Reader<'a> {
+    events: Events<'a>,
+    peeked: Option<Event<'a>>,
+    pub(crate) errors: Vec<ParseError>,
+}
+
+impl<'a> Reader<'a> {
+
+    pub(crate) fn new(events: Events<'a>) -> Self {
+        Reader { events, peeked: None, errors: vec![] }
+    }
+
+    /// Get the next event, or `None` at the end of the events.
+    pub(crate) fn next(&mut self) -> Option<Event<'a>> {
+        if let Some(event) = self.peeked.take() {
+            return Some(event);
+        }
+        for event in self.events.by_ref() {
+            match event {
+                Ok(event) => return Some(event),
+                Err(error) => self.errors.push(error),
+            }
+        }
+        None
+    }
+
+    pub(crate) fn peek(&mut self) -> Option<&Event<'a>> {
+        if self.peeked.is_none() {
+            self.peeked = self.next();
+        }
+        self.peeked.as_ref()
+    }
+
+    /// Take an end event. Returns its position.
+    ///
+    /// The events of a document with errors may end early, in which case there
+    /// is no end event and the position is arbitrary.
+    pub(crate) fn end(&mut self) -> Position {
+        match self.next() {
+            Some(Event::End(at)) => at,
+            _ => Position { index: 0, line: 0, column: 0 },
+        }
+    }
+
+    /// Check if the next event is an end event or there are no more events.
+    pub(crate) fn at_end(&mut self) -> bool {
+        matches!(self.peek(), Some(Event::End(..)) | None)
+    }
+
+    /// Read the remaining events, collecting the errors that follow the value.
+    pub(crate) fn finish(&mut self) {
+        while self.next().is_some() {}
+    }
+
+}
+
+//// Parser
+
+type Key<'a> = Vec<(Position, Position, Cow<'a, str>)>;
+
+/// Attributes of a tag.
+pub type Attributes<'a> = Vec<(Cow<'a, str>, Option<Cow<'a, str>>)>;
+
+/// A construct at the top level of a document.
+enum Frame {
+    /// A value document, parsed at once.
+    Value,
+    /// The rest of a dictionary.
+    Dictionary(Dictionary),
+    /// The rest of a list.
+    List(List),
+    /// The end of the list of a square section, and of the dictionaries of its
+    /// key path.
+    Section(usize),
+    /// The end of the dictionary or list of the document.
+    Close,
+    /// The end of the document.
+    End,
+}
+
+/// Progress through a dictionary parsed one entry or section at a time.
+struct Dictionary {
+    from: Position,
+    /// The construct before the next section.
+    previous: Rule,
+    entries: Entries,
+    /// The dictionaries to close after the entries of a section.
+    section: Option<usize>,
+}
+
+impl Dictionary {
+
+    fn new(parser: &EventParser) -> Self {
+        let entries = if matches!(parser.t0, Reduced::AssignmentHeader(..)) { Entries::First } else { Entries::None };
+        Dictionary { from: parser.at(), previous: Rule::Dictionary, entries, section: None }
+    }
+
+}
+
+/// Progress through the entries of an inner dictionary.
+enum Entries {
+    First,
+    Delimited,
+    /// Aligned entries from X.
+    Aligned(Position),
+    None,
+}
+
+/// Progress through a list parsed one element at a time.
+enum List {
+    Start,
+    Delimited,
+    /// Aligned elements from X.
+    Aligned(Position),
+    Tabular,
+    /// Tagged elements from X.
+    Tagged(Position),
+}
+
+/// Result of a step through a dictionary.
+enum Step {
+    Continue,
+    Done,
+    /// A square section starts a list. Close the list and X dictionaries
+    /// after it.
+    List(usize),
+}
+
+/// Tokens of a scope.
+enum Tokens<'a> {
+    /// The top-level tokens of a document, reduced as they are read.
+    Stream(Box<dyn FnMut() -> Result<Reduced<'a>, ParseError> + 'a>),
+    /// Tokens reduced in advance, such as the tokens within a bracket.
+    Reduced(vec::IntoIter<Reduced<'a>>),
+}
+
+/// Position of the parser in a scope enclosing the current one.
+struct Cursor<'a> {
+    tokens: Tokens<'a>,
+    t0: Reduced<'a>,
+    t1: Reduced<'a>,
+    whitespace_before: bool,
+    line_break_before: bool,
+    last_position: Position,
+    last_end: Position,
+}
+
+/// Parser emitting events.
+///
+/// An O(n) predictive and recursive parser over reduced tokens. The tokens of
+/// a bracket are parsed in a scope entered at the bracket and left at its end.
+/// Where the kind of a construct is known only after its contents are parsed,
+/// a slot is reserved for its start event.
+struct EventParser<'a> {
+    /// The document the tokens are lexed from.
+    source: &'a str,
+    tokens: Tokens<'a>,
+    t0: Reduced<'a>,
+    t1: Reduced<'a>,
+    whitespace_before: bool,
+    /// Whether the previous token is a transcription ended by a line break.
+    line_break_before: bool,
+    last_position: Position,
+    /// Position right after the previous token, where whitespace before the
+    /// current token starts.
+    last_end: Position,
+    /// Events of the construct being parsed.
+    events: Vec<Option<Event<'a>>>,
+    errors: VecDeque<ParseError>,
+    /// Error reading the tokens. The tokens end at the first error.
+    failure: Option<ParseError>,
+    /// Number of enclosing brackets, tags and key path segments.
+    depth: usize,
+    max_depth: usize,
+    /// Depth of the content of the innermost tag, if no bracket is opened in
+    /// it yet. A tag and the bracket of its content count as one level.
+    free_bracket: Option<usize>,
+    strictness: Strictness,
+}
+
+impl<'a> EventParser<'a> {
+
+    fn new(source: &'a str, tokens: Tokens<'a>, options: &ParseOptions) -> Self {
+        const START: Position = Position { index: 0, line: 0, column: 0 };
+        let mut parser = EventParser {
+            source,
+            tokens,
+            t0: Reduced::End(START), t1: Reduced::End(START),
+            whitespace_before: false,
+            line_break_before: false,
+            last_position: START,
+            last_end: START,
+            events: vec![],
+            errors: VecDeque::new(),
+            failure: None,
+            depth: 0,
+            max_depth: options.max_depth,
+            free_bracket: None,
+            strictness: options.strictness,
+        };
+        parser.shift();
+        parser.shift();
+        parser.whitespace_before = false;
+        parser.line_break_before = false;
+        parser.last_position = START;
+        parser.last_end = START;
+        parser
+    }
+
+    fn shift(&mut self) {
+        self.whitespace_before = self.t0.has_whitespace_after();
+        self.line_break_before = self.t0.is_line_transcription();
+        self.last_position = self.t0.to();
+        self.last_end = self.t0.end();
+        let next = match &mut self.tokens {
+            Tokens::Stream(..) if self.failure.is_some() => None,
+            Tokens::Stream(next) => match next() {
+                Ok(token) => Some(token),
+                Err(error) => {
+                    self.failure = Some(error);
+                    None
+                }
+            },
+            Tokens::Reduced(tokens) => tokens.next(),
+        };
+        let next = next.unwrap_or_else(|| Reduced::End(self.t1.to()));
+        self.t0 = mem::replace(&mut self.t1, next);
+    }
+
+    /// Parse the tokens within a bracket, whose first token has whitespace
+    /// before it if whitespace_before is set.
+    fn nested<T>(&mut self, tokens: Vec<Reduced<'a>>, whitespace_before: bool, open_position: Position, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        let free = self.free_bracket.take() == Some(self.depth);
+        if !free && self.depth >= self.max_depth {
+            return Err(ParseError::TooDeep(open_position, self.max_depth));
+        };
+        let mut tokens = tokens.into_iter();
+        let t0 = tokens.next().unwrap_or(Reduced::End(open_position));
+        let t1 = tokens.next().unwrap_or_else(|| Reduced::End(t0.to()));
+        let outer = Cursor {
+            tokens: mem::replace(&mut self.tokens, Tokens::Reduced(tokens)),
+            t0: mem::replace(&mut self.t0, t0),
+            t1: mem::replace(&mut self.t1, t1),
+            whitespace_before: mem::replace(&mut self.whitespace_before, whitespace_before),
+            line_break_before: mem::replace(&mut self.line_break_before, false),
+            last_position: mem::replace(&mut self.last_position, open_position),
+            last_end: mem::replace(&mut self.last_end, open_position),
+        };
+        let depth = self.depth;
+        if !free {
+            self.depth += 1;
+        }
+        let result = parse(self);
+        self.depth = depth;
+        self.tokens = outer.tokens;
+        self.t0 = outer.t0;
+        self.t1 = outer.t1;
+        self.whitespace_before = outer.whitespace_before;
+        self.line_break_before = outer.line_break_before;
+        self.last_position = outer.last_position;
+        self.last_end = outer.last_end;
+        result
+    }
+
+    /// Parse the content of a tag.
+    fn nest<T>(&mut self, at: Position, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::TooDeep(at, self.max_depth));
+        };
+        self.depth += 1;
+        self.free_bracket = Some(self.depth);
+        let result = parse(self);
+        self.free_bracket = None;
+        self.depth -= 1;
+        result
+    }
+
+    fn at(&self) -> Position {
+        self.t0.at()
+    }
+
+    fn at_last(&self) -> Position {
+        self.last_position
+    }
+
+    fn is_end(&self) -> bool {
+        matches!(self.t0, Reduced::End(..))
+    }
+
+    /// Skip the rest of the document, so that an error in its tokens is still
+    /// found.
+    fn skip_rest(&mut self) {
+        while !self.is_end() {
+            self.shift();
+        }
+    }
+
+    /// Take the text of the current token if it is a string or key.
+    fn take_str(&mut self) -> Cow<'a, str> {
+        match &mut self.t0 {
+            Reduced::String(.., string) | Reduced::AssignmentHeader(.., string) => mem::take(string),
+            _ => Cow::Borrowed(""),
+        }
+    }
+
+    /// Take the tokens within the current token if it is a bracket or header.
+    fn take_scope(&mut self) -> Vec<Reduced<'a>> {
+        match &mut self.t0 {
+            Reduced::CurlyBracket(.., scope) | Reduced::SquareBracket(.., scope) | Reduced::AngleBracket(.., scope) => mem::take(scope),
+            Reduced::CurlyHeader(.., scope) | Reduced::SquareHeader(.., scope) | Reduced::TaggedValueHeader(.., scope) => mem::take(scope),
+            _ => vec![],
+        }
+    }
+
+    fn push(&mut self, event: Event<'a>) {
+        self.events.push(Some(event));
+    }
+
+    /// Reserve a slot for an event.
+    fn slot(&mut self) -> usize {
+        self.events.push(None);
+        self.events.len() - 1
+    }
+
+    /// Whether the value whose events start at index is a tuple.
+    fn is_tuple_at(&self, start: usize) -> bool {
+        matches!(self.events[start..].iter().flatten().next(), Some(Event::StartTuple(..)))
+    }
+
+    /// Push the events of a key path. Returns the number of dictionaries to
+    /// close after the value.
+    ///
+    /// The dictionaries of a key path count towards the depth like brackets.
+    fn push_key(&mut self, key: Key<'a>) -> Result<usize, ParseError> {
+        let depth = key.len() - 1;
+        if self.max_depth - self.depth < depth {
+            return Err(ParseError::TooDeep(key[0].0, self.max_depth));
+        };
+        self.depth += depth;
+        for (i, (from, to, key)) in key.into_iter().enumerate() {
+            self.push(Event::Key(from, to, key));
+            if i < depth {
+                self.push(Event::StartDictionary(to));
+            }
+        }
+        Ok(depth)
+    }
+
+    /// Close the dictionaries of a key path.
+    fn close(&mut self, depth: usize) {
+        self.depth -= depth;
+        for _ in 0..depth {
+            let at = self.at_last();
+            self.push(Event::End(at));
+        }
+    }
+
+    /// Parse a value document.
+    ///
+    /// ```text
+    /// <value-document> → *
+    ///                  | *<value>*
+    /// ```
+    fn parse_value_document(&mut self) -> Result<(), ParseError> {
+        if matches!(self.t0, Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..) | Reduced::Bar(..) | Reduced::TaggedValueHeader(..)) {
+            self.parse_value()
+        } else {
+            let (from, to) = (self.t0.at(), self.t1.at());
+            self.push(Event::StartTuple(from));
+            self.push(Event::End(to));
+            Ok(())
+        }
+    }
+
+    /// Parse a value.
+    ///
+    /// ```text
+    /// <value> → <inner-value>
+    ///         | "|" <inner-value>
+    ///         | <tagged-value>
+    /// ```
+    fn parse_value(&mut self) -> Result<(), ParseError> {
+        match self.t0 {
+            Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..) => self.parse_inner_value(),
+            Reduced::Bar(..) => {
+                self.shift();
+                self.parse_inner_value()
+            }
+            Reduced::TaggedValueHeader(..) => self.parse_tagged_value(),
+            _ => expected(&[Rule::InnerValue, Rule::BarredInnerValue, Rule::TaggedValue], &self.t0, Rule::Value, self.t0.at()),
+        }
+    }
+
+    /// Parse a block.
+    ///
+    /// ```text
+    /// <block> → <term>
+    ///         | <term> <block>
+    ///         | "~"
+    ///         | "~" <block>
+    ///
+    /// <term> → <text>
+    ///        | <bracketed-value>
+    ///        | <bracketed-dictionary>
+    ///        | <bracketed-list>
+    ///        | <tagged-arguments>
+    /// ```
+    fn parse_block(&mut self) -> Result<(), ParseError> {
+        let from = self.at();
+        let slot = self.slot();
+        let mut terms = 0;
+        loop {
+            let space_before = self.whitespace_before;
+            if matches!(self.t0, Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..)) {
+                if terms != 0 && space_before {
+                    self.push(Event::Whitespace);
+                }
+                terms += 1;
+            }
+            match self.t0 {
+                Reduced::String(..) => self.parse_text()?,
+                Reduced::CurlyBracket(..) => self.parse_bracketed_construct()?,
+                Reduced::SquareBracket(..) => self.parse_bracketed_list()?,
+                Reduced::AngleBracket(..) => self.parse_tagged_arguments()?,
+                Reduced::Tilde(..) => self.shift(),
+                _ => break,
+            }
+        }
+        let to = self.at();
+        match terms {
+            0 => self.events[slot] = Some(Event::Nil(from, to)),
+            1 => {}
+            _ => {
+                self.events[slot] = Some(Event::StartCompound(from));
+                self.push(Event::End(to));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse text.
+    ///
+    /// ```text
+    /// <text>  → <string>
+    ///         | <string> <text'>
+    /// <text'> → <string>
+    ///         | <string> <text'>
+    ///         | "~" <text'>
+    /// ```
+    fn parse_text(&mut self) -> Result<(), ParseError> {
+        let mut text: Option<Cow<'a, str>> = None;
+        let mut space_before = false;
+        let from = self.at();
+        if !matches!(self.t0, Reduced::String(..)) {
+            return expected(&[Rule::String], &self.t0, Rule::Text, self.t0.at());
+        }
+        loop {
+            match self.t0 {
+                Reduced::String(.., b, _, _) => {
+                    let string = self.take_str();
+                    text = Some(match text {
+                        None => string,
+                        Some(text) => join(self.source, text, &string, space_before),
+                    });
+                    space_before = b;
+                    self.shift();
+                }
+                Reduced::Tilde(..) => {
+                    space_before = false;
+                    self.shift();
+                }
+                _ => break,
+            }
+            if !(matches!(self.t0, Reduced::String(..)) || matches!(self.t0, Reduced::Tilde(..)) && matches!(self.t1, Reduced::String(..))) {
+                break;
+            }
+        }
+        let to = self.at();
+        self.push(Event::Text(from, to, text.unwrap_or_default()));
+        Ok(())
+    }
+
+    /// Parse an inner value.
+    ///
+    /// ```text
+    /// <inner-value> → <tuple-element>
+    ///               | <tuple>
+    ///
+    /// <tuple> → <tuple-element> "|" <tuple-element>
+    ///         | <tuple-element> "|" <tuple>
+    ///
+    /// <tuple-element> → <block>
+    ///                 | <mapped-key>
+    /// ```
+    fn parse_inner_value(&mut self) -> Result<(), ParseError> {
+        let from = self.at();
+        let slot = self.slot();
+        let mut elements = 0;
+        let mut mapped_keys = vec![];
+        loop {
+            if !matches!(self.t0, Reduced::String(..) | Reduced::AssignmentHeader(..)) || !matches!(self.t1, Reduced::Colon(..) | Reduced::MapArrow(..)) {
+                self.parse_block()?;
+                elements += 1;
+            } else {
+                let start = self.events.len();
+                self.parse_mapped_key()?;
+                mapped_keys.extend(self.events.drain(start..));
+            }
+            if !matches!(self.t0, Reduced::Bar(..)) || !matches!(self.t1, Reduced::String(..) | Reduced::AssignmentHeader(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..)) {
+                break;
+            }
+            self.shift();
+        }
+        let to = self.at_last();
+        if !mapped_keys.is_empty() {
+            // Mapped keys form a dictionary before the other elements.
+            let mut dictionary = vec![Some(Event::StartDictionary(from))];
+            dictionary.append(&mut mapped_keys);
+            dictionary.push(Some(Event::End(to)));
+            self.events.splice(slot + 1..slot + 1, dictionary);
+            elements += 1;
+        }
+        if elements > 1 {
+            self.events[slot] = Some(Event::StartTuple(from));
+            self.push(Event::End(to));
+        }
+        Ok(())
+    }
+
+    /// Parse a mapped key.
+    ///
+    /// ```text
+    /// <mapped-key> → <key> "=>" <block>
+    /// ```
+    fn parse_mapped_key(&mut self) -> Result<(), ParseError> {
+        let from = self.t0.at();
+        if !matches!(self.t0, Reduced::AssignmentHeader(..) | Reduced::String(..)) {
+            return expected(&[Rule::Key], &self.t0, Rule::MappedKey, from);
+        }
+        let key = self.parse_key()?;
+        if !matches!(self.t0, Reduced::MapArrow(..)) {
+            return expected(&[Rule::MapArrow], &self.t0, Rule::MappedKey, from);
+        }
+        self.shift();
+        let depth = self.push_key(key)?;
+        self.parse_block()?;
+        self.close(depth);
+        Ok(())
+    }
+
+    /// Parse a tagged value.
+    ///
+    /// ```text
+    /// <tagged-value> → <tag>":"_<value>
+    /// ```
+    fn parse_tagged_value(&mut self) -> Result<(), ParseError> {
+        let from = self.at();
+        let slot = self.slot();
+        let tag = self.parse_tag()?;
+        if !matches!(self.t0, Reduced::Colon(..)) {
+            return expected(&[Rule::Colon], &self.t0, Rule::TaggedValue, from);
+        };
+        self.require_whitespace_after(Strictness::Lenient, Rule::Colon, Rule::Value, Rule::TaggedValue, from);
+        self.shift();
+        let start = self.events.len();
+        self.nest(from, |parser| parser.parse_value())?;
+        let to = self.at();
+        match tag {
+            Some((name, attributes)) => {
+                self.events[slot] = Some(Event::StartTag(from, name, attributes));
+                self.push(Event::End(to));
+            }
+            None => {
+                if self.is_tuple_at(start) {
+                    self.events[slot] = Some(Event::StartTuple(from));
+                    self.push(Event::End(to));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a dictionary.
+    ///
+    /// ```text
+    /// <dictionary> → <delimited-dictionary>
+    ///              | <aligned-dictionary>
+    ///              | <absolute-dictionary>
+    ///
+    /// <absolute-dictionary>  → <absolute-dictionary'>
+    ///                        | <inner-dictionary>_<absolute-dictionary'>
+    /// <absolute-dictionary'> → <section>
+    ///                        | <section>_<absolute-dictionary>
+    /// ```
+    fn parse_dictionary(&mut self) -> Result<(), ParseError> {
+        let mut dictionary = Dictionary::new(self);
+        loop {
+            match self.parse_dictionary_step(&mut dictionary)? {
+                Step::Continue => {}
+                Step::Done => return Ok(()),
+                Step::List(depth) => {
+                    self.parse_list()?;
+                    self.end_section(depth);
+                }
+            }
+        }
+    }
+
+    /// Parse the next entry or section of a dictionary.
+    ///
+    /// ```text
+    /// <inner-dictionary> → <delimited-dictionary>
+    ///                    | <aligned-dictionary>
+    ///
+    /// <delimited-dictionary> → <entry>
+    ///                        | <entry> ";"
+    ///                        | <entry> ";" <delimited-dictionary>
+    ///
+    /// <aligned-dictionary> → <entry>
+    ///                      | <entry>_<aligned-dictionary>
+    /// ```
+    fn parse_dictionary_step(&mut self, dictionary: &mut Dictionary) -> Result<Step, ParseError> {
+        match dictionary.entries {
+            Entries::First => {
+                self.parse_entry()?;
+                dictionary.entries = match self.t0 {
+                    Reduced::Semicolon(..) => {
+                        self.shift();
+                        if matches!(self.t0, Reduced::AssignmentHeader(..)) { Entries::Delimited } else { Entries::None }
+                    }
+                    Reduced::AssignmentHeader(..) => Entries::Aligned(self.at()),
+                    _ => Entries::None,
+                };
+            }
+            Entries::Delimited => {
+                self.parse_entry()?;
+                dictionary.entries = Entries::None;
+                if matches!(self.t0, Reduced::Semicolon(..)) {
+                    self.shift();
+                    if matches!(self.t0, Reduced::AssignmentHeader(..)) {
+                        dictionary.entries = Entries::Delimited;
+                    }
+                }
+            }
+            Entries::Aligned(from) => {
+                self.require_whitespace_before(Strictness::Standard, Rule::Entry, Rule::Entry, Rule::AlignedDictionary, from);
+                self.parse_entry()?;
+                if !matches!(self.t0, Reduced::AssignmentHeader(..)) {
+                    dictionary.entries = Entries::None;
+                }
+            }
+            Entries::None => return self.parse_section(dictionary),
+        }
+        if matches!(dictionary.entries, Entries::None) {
+            if let Some(depth) = dictionary.section.take() {
+                self.end_section(depth);
+            }
+        }
+        Ok(Step::Continue)
+    }
+
+    /// Parse a section.
+    ///
+    /// ```text
+    /// <section> → <square-header>":"
+    ///           | <square-header>":"_<list>
+    ///           | <curly-header>":"
+    ///           | <curly-header>":"_<inner-dictionary>
+    ///           | <curly-header>":"_<value>
+    /// ```
+    fn parse_section(&mut self, dictionary: &mut Dictionary) -> Result<Step, ParseError> {
+        let from = dictionary.from;
+        let section_from = self.at();
+        if !matches!(self.t0, Reduced::CurlyHeader(..) | Reduced::SquareHeader(..)) {
+            return Ok(Step::Done);
+        }
+        let previous = mem::replace(&mut dictionary.previous, Rule::Section);
+        if section_from != from {
+            self.require_whitespace_before(Strictness::Strict, previous, Rule::Section, Rule::AbsoluteDictionary, from);
+        }
+        if matches!(self.t0, Reduced::CurlyHeader(..)) {
+            let header = self.parse_header()?;
+            if !matches!(self.t0, Reduced::Colon(..)) {
+                return expected(&[Rule::Colon], &self.t0, Rule::AbsoluteDictionary, from);
+            }
+            self.require_no_whitespace_before(Strictness::Lenient, Rule::BracketHeader, Rule::Colon, Rule::Section, section_from);
+            self.shift();
+            let content_from = self.at();
+            let depth = self.push_key(header)?;
+            match self.t0 {
+                Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..) | Reduced::Bar(..) | Reduced::TaggedValueHeader(..) => {
+                    self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Value, Rule::Section, section_from);
+                    self.parse_value()?;
+                    self.close(depth);
+                }
+                Reduced::AssignmentHeader(..) => {
+                    self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Dictionary, Rule::Section, section_from);
+                    self.push(Event::StartDictionary(content_from));
+                    dictionary.entries = Entries::First;
+                    dictionary.section = Some(depth);
+                }
+                _ => {
+                    self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Section, Rule::Section, section_from);
+                    self.push(Event::StartDictionary(content_from));
+                    self.push(Event::End(content_from));
+                    self.close(depth);
+                }
+            }
+            Ok(Step::Continue)
+        } else {
+            let header = self.parse_header()?;
+            if !matches!(self.t0, Reduced::Colon(..)) {
+                return expected(&[Rule::Colon], &self.t0, Rule::AbsoluteDictionary, from);
+            }
+            self.require_no_whitespace_before(Strictness::Lenient, Rule::SquareHeader, Rule::Colon, Rule::Section, section_from);
+            self.shift();
+            let table_from = self.at();
+            let depth = self.push_key(header)?;
+            self.push(Event::StartList(table_from));
+            if matches!(self.t0, Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..) | Reduced::Bar(..) | Reduced::TaggedValueHeader(..)) {
+                self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::List, Rule::Section, section_from);
+                Ok(Step::List(depth))
+            } else {
+                self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Section, Rule::Section, section_from);
+                self.end_section(depth);
+                Ok(Step::Continue)
+            }
+        }
+    }
+
+    /// End the dictionary or list of a section, and the dictionaries of its
+    /// key path.
+    fn end_section(&mut self, depth: usize) {
+        let to = self.at_last();
+        self.push(Event::End(to));
+        self.close(depth);
+    }
+
+    /// Parse a dictionary header.
+    ///
+    /// ```text
+    /// <curly-header> → "{"<key>"}"
+    ///
+    /// <square-header> → "["<key>"]"
+    /// ```
+    fn parse_header(&mut self) -> Result<Key<'a>, ParseError> {
+        let at = self.at();
+        match self.t0 {
+            Reduced::CurlyHeader(_, _, fw, _) | Reduced::SquareHeader(_, _, fw, _) => {
+                let open = if matches!(self.t0, Reduced::CurlyHeader(..)) { Rule::BracketOpen } else { Rule::SquareOpen };
+                let scope = self.take_scope();
+                self.shift();
+                self.nested(scope, fw, after_open(at), |parser| {
+                    parser.require_no_whitespace_before(Strictness::Lenient, open, Rule::Key, Rule::Header, at);
+                    let key = parser.parse_key()?;
+                    parser.require_no_whitespace_before(Strictness::Standard, Rule::Key, Rule::Close, Rule::Header, at);
+                    parser.require_no_whitespace_after(Strictness::Lenient, Rule::Key, Rule::Close, Rule::Header, at);
+                    Ok(key)
+                })
+            }
+            _ => expected(&[Rule::Key], &self.t0, Rule::Header, at),
+        }
+    }
+
+    /// Parse an entry.
+    ///
+    /// ```text
+    /// <entry> → <key>":" <value>
+    ///
+    /// <key> → <string>
+    ///       | <string>":"<key>
+    /// ```
+    fn parse_entry(&mut self) -> Result<(), ParseError> {
+        let at = self.at();
+        if !matches!(self.t0, Reduced::AssignmentHeader(..) | Reduced::String(..)) {
+            return expected(&[Rule::Key], &self.t0, Rule::Entry, at);
+        }
+        let key = self.parse_entry_key()?;
+        let depth = self.push_key(key)?;
+        self.parse_value()?;
+        self.close(depth);
+        Ok(())
+    }
+
+    /// Parse a key and a colon.
+    fn parse_entry_key(&mut self) -> Result<Key<'a>, ParseError> {
+        let mut key = vec![];
+        let from = self.at();
+        loop {
+            match self.t0 {
+                Reduced::String(from, to, ..) | Reduced::AssignmentHeader(from, to, ..) => key.push((from, to, self.take_str())),
+                _ => return expected(&[Rule::String], &self.t0, Rule::Key, self.t0.at()),
+            };
+            self.shift();
+            if !matches!(self.t0, Reduced::Colon(..)) {
+                return expected(&[Rule::Colon], &self.t0, Rule::Key, self.t0.at());
+            }
+            self.require_no_whitespace_before(Strictness::Lenient, Rule::String, Rule::Colon, Rule::Key, from);
+            self.shift();
+            if !matches!(self.t0, Reduced::String(..) | Reduced::AssignmentHeader(..)) || !matches!(self.t1, Reduced::Colon(..)) {
+                break;
+            }
+            self.require_no_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::String, Rule::Key, from);
+        }
+        Ok(key)
+    }
+
+    /// Parse a key.
+    ///
+    /// ```text
+    /// <key> → <string>
+    ///       | <string>":"<key>
+    /// ```
+    fn parse_key(&mut self) -> Result<Key<'a>, ParseError> {
+        let mut key = vec![];
+        let from = self.at();
+        loop {
+            match self.t0 {
+                Reduced::String(from, to, ..) | Reduced::AssignmentHeader(from, to, ..) => key.push((from, to, self.take_str())),
+                _ => return expected(&[Rule::String], &self.t0, Rule::Key, self.t0.at()),
+            }
+            self.shift();
+            if !matches!(self.t0, Reduced::Colon(..)) {
+                break;
+            }
+            self.require_no_whitespace_before(Strictness::Lenient, Rule::String, Rule::Colon, Rule::Key, from);
+            self.require_no_whitespace_after(Strictness::Lenient, Rule::Colon, Rule::String, Rule::Key, from);
+            self.shift();
+        }
+        Ok(key)
+    }
+
+    /// Parse a list.
+    ///
+    /// ```text
+    /// <list> → <delimited-list>
+    ///        | <aligned-list>
+    ///        | <tabular-list>
+    ///        | <tagged-list>
+    /// ```
+    fn parse_list(&mut self) -> Result<(), ParseError> {
+        let mut list = List::Start;
+        while !self.parse_list_step(&mut list)? {}
+        Ok(())
+    }
+
+    /// Parse the next element of a list. Returns whether the list is done.
+    ///
+    /// ```text
+    /// <delimited-list> → <value>
+    ///                  | <value> ";"
+    ///                  | <value> ";" <delimited-list>
+    ///
+    /// <aligned-list> → ">"_<value>
+    ///                | ">"_<value>_<aligned-list>
+    ///
+    /// <tabular-list> → "|" <inner-value> "|"
+    ///                | "|" <inner-value> "|"_<tabular-list>
+    ///
+    /// <tagged-list> → <tagged-value>
+    ///               | <tagged-value>_<tagged-list>
+    /// ```
+    fn parse_list_step(&mut self, list: &mut List) -> Result<bool, ParseError> {
+        match *list {
+            List::Start => match self.t0 {
+                Reduced::Bullet(..) => {
+                    *list = List::Aligned(self.at());
+                    Ok(false)
+                }
+                Reduced::Bar(..) => {
+                    self.shift();
+                    self.parse_inner_value()?;
+                    if matches!(self.t0, Reduced::Bar(..)) {
+                        self.shift();
+                        if matches!(self.t0, Reduced::Bar(..)) {
+                            *list = List::Tabular;
+                            return Ok(false);
+                        }
+                    } else if matches!(self.t0, Reduced::Semicolon(..)) {
+                        self.shift();
+                        *list = List::Delimited;
+                        return Ok(false);
+                    }
+                    Ok(true)
+                }
+                Reduced::String(..) | Reduced::Tilde(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) => {
+                    *list = List::Delimited;
+                    Ok(false)
+                }
+                Reduced::TaggedValueHeader(..) => {
+                    self.parse_tagged_value()?;
+                    if matches!(self.t0, Reduced::TaggedValueHeader(..)) {
+                        *list = List::Tagged(self.at());
+                        Ok(false)
+                    } else if matches!(self.t0, Reduced::Semicolon(..)) {
+                        self.shift();
+                        *list = List::Delimited;
+                        Ok(false)
+                    } else {
+                        Ok(true)
+                    }
+                }
+                _ => expected(&[Rule::DelimitedList, Rule::AlignedList, Rule::TabularList, Rule::TaggedList], &self.t0, Rule::List, self.t0.at()),
+            },
+            List::Delimited => {
+                self.parse_value()?;
+                if !matches!(self.t0, Reduced::Semicolon(..)) {
+                    return Ok(true);
+                }
+                self.shift();
+                Ok(!matches!(self.t0, Reduced::String(..) | Reduced::Tilde(..) | Reduced::Bar(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::TaggedValueHeader(..)))
+            }
+            List::Aligned(at) => {
+                self.require_whitespace_after(Strictness::Lenient, Rule::RightAngle, Rule::Value, Rule::AlignedList, at);
+                self.shift();
+                self.parse_value()?;
+                if !matches!(self.t0, Reduced::Bullet(..)) {
+                    return Ok(true);
+                }
+                self.require_whitespace_before(Strictness::Lenient, Rule::Value, Rule::RightAngle, Rule::AlignedList, at);
+                Ok(false)
+            }
+            List::Tabular => {
+                self.shift();
+                self.parse_inner_value()?;
+                if !matches!(self.t0, Reduced::Bar(..)) {
+                    return expected(&[Rule::Bar], &self.t0, Rule::TabularList, self.t0.at());
+                }
+                self.shift();
+                Ok(!matches!(self.t0, Reduced::Bar(..)))
+            }
+            List::Tagged(from) => {
+                self.require_whitespace_before(Strictness::Strict, Rule::TaggedValue, Rule::TaggedValue, Rule::TaggedList, from);
+                self.parse_tagged_value()?;
+                Ok(!matches!(self.t0, Reduced::TaggedValueHeader(..)))
+            }
+        }
+    }
+
+    /// Parse tagged arguments.
+    ///
+    /// ```text
+    /// <tagged-arguments> → <tag>
+    ///                    | <tag><arguments>
+    /// ```
+    fn parse_tagged_arguments(&mut self) -> Result<(), ParseError> {
+        let from = self.at();
+        let slot = self.slot();
+        let tag = self.parse_tag()?;
+        let tuple_slot = self.slot();
+        let start = self.events.len();
+        let arguments = if matches!(self.t0, Reduced::Colon(..)) {
+            self.nest(from, |parser| parser.parse_arguments())?
+        } else {
+            0
+        };
+        let to = self.at();
+        if arguments != 1 || self.is_tuple_at(start) {
+            self.events[tuple_slot] = Some(Event::StartTuple(from));
+            self.push(Event::End(to));
+        }
+        if let Some((name, attributes)) = tag {
+            self.events[slot] = Some(Event::StartTag(from, name, attributes));
+            self.push(Event::End(to));
+        }
+        Ok(())
+    }
+
+    /// Parse arguments. Returns the number of arguments.
+    ///
+    /// ```text
+    /// <arguments> → ":"<argument>
+    ///             | ":"<argument><arguments>
+    ///
+    /// <argument> → <string>
+    ///            | <bracketed-value>
+    ///            | <bracketed-dictionary>
+    ///            | <bracketed-list>
+    ///            | <tagged-arguments>
+    /// ```
+    fn parse_arguments(&mut self) -> Result<usize, ParseError> {
+        let mut arguments = 0;
+        if !matches!(self.t0, Reduced::Colon(..)) {
+            return expected(&[Rule::Colon], &self.t0, Rule::Arguments, self.t0.at());
+        }
+        let from = self.at();
+        loop {
+            self.require_no_whitespace_after(Strictness::Lenient, Rule::Colon, Rule::Argument, Rule::Arguments, from);
+            self.shift();
+            match self.t0 {
+                Reduced::String(..) => {
+                    let from = self.at();
+                    let text = self.take_str();
+                    self.shift();
+                    let to = self.at();
+                    self.push(Event::Text(from, to, text));
+                }
+                Reduced::CurlyBracket(..) => self.parse_bracketed_construct()?,
+                Reduced::SquareBracket(..) => self.parse_bracketed_list()?,
+                Reduced::AngleBracket(..) => {
+                    self.parse_tagged_arguments()?;
+                    arguments += 1;
+                    break;
+                }
+                _ => return expected(&[Rule::String, Rule::BracketedValue, Rule::BracketedDictionary, Rule::BracketedList, Rule::TaggedArguments], &self.t0, Rule::Argument, self.t0.at()),
+            }
+            arguments += 1;
+            if !matches!(self.t0, Reduced::Colon(..)) {
+                break;
+            }
+        }
+        Ok(arguments)
+    }
+
+    /// Parse a tag.
+    ///
+    /// ```text
+    /// <tag> → "<"<word>">"
+    ///       | "<"<word>_<attributes> ">"
+    ///       | "<"">"
+    /// ```
+    fn parse_tag(&mut self) -> Result<Option<(Cow<'a, str>, Attributes<'a>)>, ParseError> {
+        if let Reduced::AngleBracket(from, _, fw, _, _) | Reduced::TaggedValueHeader(from, _, fw, _) = self.t0 {
+            let scope = self.take_scope();
+            self.shift();
+            self.nested(scope, fw, after_open(from), |parser| {
+                parser.require_no_whitespace_before(Strictness::Lenient, Rule::AngularBracket, Rule::Name, Rule::Tag, from);
+                if parser.is_end() {
+                    return Ok(None);
+                }
+                let name = match parser.t0 {
+                    Reduced::String(at, _, _, ref t, _) | Reduced::AssignmentHeader(at, _, ref t, _) => {
+                        if *t != StringType::Word {
+                            parser.errors.push_back(ParseError::TagNameMustBeWord(at, parser.t0.to_type()));
+                        }
+                        parser.take_str()
+                    }
+                    _ => return expected(&[Rule::Name], &parser.t0, Rule::Tag, from),
+                };
+                parser.shift();
+                let attributes = if matches!(parser.t0, Reduced::String(..) | Reduced::AssignmentHeader(..)) {
+                    parser.require_whitespace_before(Strictness::Standard, Rule::Name, Rule::Attributes, Rule::Tag, from);
+                    parser.parse_attributes()?
+                } else {
+                    parser.require_no_whitespace_before(Strictness::Standard, Rule::Name, Rule::Close, Rule::Tag, from);
+                    vec![]
+                };
+                parser.require_no_whitespace_after(Strictness::Lenient, Rule::Attributes, Rule::Close, Rule::Tag, from);
+                Ok(Some((name, attributes)))
+            })
+        } else {
+            expected(&[Rule::AngularBracket], &self.t0, Rule::Tag, self.at())
+        }
+    }
+
+    /// Parse attributes.
+    ///
+    /// ```text
+    /// <attributes> → <attribute>
+    ///              | <attribute>_<attributes>
+    ///
+    /// <attribute> → <word>
+    ///             | <word>":"<string>
+    /// ```
+    fn parse_attributes(&mut self) -> Result<Attributes<'a>, ParseError> {
+        let mut attributes = vec![];
+        let from = self.at();
+        if !matches!(self.t0, Reduced::AssignmentHeader(..) | Reduced::String(..)) {
+            return expected(&[Rule::Attribute], &self.t0, Rule::Attributes, from);
+        }
+        while matches!(self.t0, Reduced::String(..) | Reduced::AssignmentHeader(..)) {
+            if !attributes.is_empty() {
+                self.require_whitespace_before(Strictness::Standard, Rule::Attribute, Rule::Attribute, Rule::Attributes, from);
+            }
+            if !matches!(self.t0, Reduced::String(_, _, _, StringType::Word, _) | Reduced::AssignmentHeader(_, _, StringType::Word, _)) {
+                return Err(ParseError::AttributeMustBeWord(self.at(), self.t0.to_type()));
+            }
+            let key = self.take_str();
+            self.shift();
+            if matches!(self.t0, Reduced::Colon(..)) {
+                let at = self.at_last();
+                self.require_no_whitespace_before(Strictness::Standard, Rule::Word, Rule::Colon, Rule::Attribute, at);
+                self.require_no_whitespace_after(Strictness::Standard, Rule::Colon, Rule::String, Rule::Attribute, at);
+                self.shift();
+                let value = self.parse_string()?;
+                attributes.push((key, Some(value)));
+            } else {
+                attributes.push((key, None));
+            }
+        }
+        Ok(attributes)
+    }
+
+    /// Parse bracketed construct.
+    ///
+    /// ```text
+    /// <bracketed-value> → "{" <value> "}"
+    ///
+    /// <bracketed-dictionary> → "{" "}"
+    ///                        | "{" <dictionary> "}"
+    /// ```
+    fn parse_bracketed_construct(&mut self) -> Result<(), ParseError> {
+        if let Reduced::CurlyBracket(from, to, wi, _, _) = self.t0 {
+            let scope = self.take_scope();
+            self.shift();
+            self.nested(scope, wi, to, |parser| {
+                match parser.t0 {
+                    Reduced::AssignmentHeader(..) | Reduced::CurlyHeader(..) | Reduced::SquareHeader(..) => {
+                        parser.push(Event::StartDictionary(from));
+                        parser.parse_dictionary()?;
+                        parser.push(Event::End(to));
+                    }
+                    Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Bar(..) | Reduced::Tilde(..) | Reduced::TaggedValueHeader(..) => {
+                        parser.parse_value()?;
+                    }
+                    Reduced::End(..) => {
+                        parser.push(Event::StartDictionary(from));
+                        parser.push(Event::End(to));
+                    }
+                    _ => return expected(&[Rule::Value, Rule::Dictionary], &parser.t0, Rule::Bracket, from),
+                };
+                parser.require_no_whitespace_after(Strictness::Lenient, Rule::Value, Rule::Close, Rule::Bracket, from);
+                Ok(())
+            })
+        } else {
+            expected(&[Rule::BracketOpen], &self.t0, Rule::Bracket, self.at())
+        }
+    }
+
+    /// Parse bracketed list.
+    ///
+    /// ```text
+    /// <bracketed-list> → "[" "]"
+    ///                  | "[" <list> "]"
+    /// ```
+    fn parse_bracketed_list(&mut self) -> Result<(), ParseError> {
+        if let Reduced::SquareBracket(from, to, fw, _, _) = self.t0 {
+            let scope = self.take_scope();
+            self.shift();
+            self.nested(scope, fw, to, |parser| {
+                parser.push(Event::StartList(from));
+                if !parser.is_end() {
+                    parser.parse_list()?;
+                }
+                parser.require_no_whitespace_after(Strictness::Lenient, Rule::List, Rule::Close, Rule::Square, from);
+                parser.push(Event::End(to));
+                Ok(())
+            })
+        } else {
+            expected(&[Rule::SquareOpen], &self.t0, Rule::Square, self.at())
+        }
+    }
+
+    /// Parse a string.
+    ///
+    /// ```text
+    /// <string> → <word>
+    ///          | <transcription>
+    ///          | <text-block>
+    /// ```
+    fn parse_string(&mut self) -> Result<Cow<'a, str>, ParseError> {
+        match self.t0 {
+            Reduced::String(..) => {
+                let text = self.take_str();
+                self.shift();
+                Ok(text)
+            }
+            _ => expected(&[Rule::Word, Rule::Transcription, Rule::TextBlock], &self.t0, Rule::String, self.at()),
+        }
+    }
+
+    /// Require whitespace between the current token and the next.
+    fn require_whitespace_after(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+        if self.strictness >= strictness && !self.t0.has_whitespace_after() {
+            self.errors.push_back(ParseError::ExpectedWhitespace(self.t0.end(), before, after, within, within_at))
+        }
+    }
+
+    /// Forbid whitespace between the current token and the next.
+    fn require_no_whitespace_after(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+        if self.strictness >= strictness && self.t0.has_whitespace_after() {
+            self.errors.push_back(ParseError::UnexpectedWhitespace(self.t0.end(), before, after, within, within_at))
+        }
+    }
+
+    /// Require whitespace between the previous token and the current.
+    fn require_whitespace_before(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+        if self.strictness >= strictness && !self.whitespace_before && !self.line_break_before {
+            self.errors.push_back(ParseError::ExpectedWhitespace(self.last_end, before, after, within, within_at))
+        }
+    }
+
+    /// Forbid whitespace between the previous token and the current.
+    fn require_no_whitespace_before(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+        if self.strictness >= strictness && self.whitespace_before {
+            self.errors.push_back(ParseError::UnexpectedWhitespace(self.last_end, before, after, within, within_at))
+        }
+    }
+
+}
+
+/// Append a string to text, separated by a space if space is set.
+///
+/// If both are borrowed and the source has the same text between them, the
+/// result is borrowed from the source too.
+fn join<'a>(source: &'a str, text: Cow<'a, str>, string: &Cow<'a, str>, space: bool) -> Cow<'a, str> {
+    if let (Cow::Borrowed(a), Cow::Borrowed(b)) = (&text, string) {
+        if let (Some(start), Some(next)) = (offset_in(source, a), offset_in(source, b)) {
+            let end = start + a.len();
+            let separator = if space { " " } else { "" };
+            if end + separator.len() == next && &source[end..next] == separator {
+                return Cow::Borrowed(&source[start..next + b.len()]);
+            }
+        }
+    }
+    let mut text = text.into_owned();
+    if space {
+        text.push(' ');
+    }
+    text.push_str(string);
+    Cow::Owned(text)
+}
+
+/// Byte offset of a string borrowed from the source.
+fn offset_in(source: &str, str: &str) -> Option<usize> {
+    let start = source.as_ptr() as usize;
+    let at = str.as_ptr() as usize;
+    if at >= start && at + str.len() <= start + source.len() {
+        Some(at - start)
+    } else {
+        None
+    }
+}
+
+fn expected<T>(expected: &'static [Rule], found: &Reduced, found_in: Rule, found_in_at: Position) -> Result<T, ParseError> {
+    Err(ParseError::Expected(expected, token_to_rule(found), found.at(), found_in, found_in_at))
+}
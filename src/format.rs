@@ -0,0 +1,293 @@
+//! Khi formatter. Writes parsed values to Khi strings.
+//!
+//! The output uses compact delimited notation and parses back to a structurally
+//! equal value. Dictionary entries are written in key order.
+
+use crate::{Dictionary, Tagged, Value};
+use crate::pdm::{ParsedAttribute, ParsedCompound, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedTuple, ParsedValue};
+
+/// Format a value document.
+pub fn format_value(value: &ParsedValue) -> String {
+    let mut output = String::new();
+    write_value(&mut output, value);
+    output
+}
+
+/// Format a dictionary document.
+pub fn format_dictionary(dictionary: &ParsedDictionary) -> String {
+    let mut output = String::new();
+    write_entries(&mut output, dictionary);
+    output
+}
+
+/// Format a list document.
+pub fn format_list(list: &ParsedList) -> String {
+    let mut output = String::new();
+    write_elements(&mut output, list);
+    output
+}
+
+/// Format text as a string.
+///
+/// Text is written as words if possible, otherwise as a transcription.
+pub fn format_text(str: &str) -> String {
+    let mut output = String::new();
+    write_text(&mut output, str);
+    output
+}
+
+/// Format a key.
+pub fn format_key(str: &str) -> String {
+    let mut output = String::new();
+    write_string(&mut output, str);
+    output
+}
+
+//// Values
+
+pub(crate) fn write_value(output: &mut String, value: &ParsedValue) {
+    match value {
+        ParsedValue::Nil(..) => output.push('~'),
+        ParsedValue::Text(text, ..) => write_text(output, &text.str),
+        ParsedValue::Tagged(tag, ..) => write_tagged(output, tag),
+        ParsedValue::Tuple(tuple, ..) => write_tuple(output, tuple),
+        ParsedValue::Dictionary(dictionary, ..) => {
+            output.push('{');
+            write_entries(output, dictionary);
+            output.push('}');
+        }
+        ParsedValue::List(list, ..) => {
+            output.push('[');
+            write_elements(output, list);
+            output.push(']');
+        }
+        ParsedValue::Compound(compound, ..) => write_compound(output, compound),
+    }
+}
+
+fn write_tuple(output: &mut String, tuple: &ParsedTuple) {
+    match tuple {
+        ParsedTuple::Unit => output.push_str("<>"),
+        ParsedTuple::Single(value) => {
+            output.push_str("<>:{");
+            write_value(output, value);
+            output.push('}');
+        }
+        ParsedTuple::Multiple(values) => {
+            let mut first = true;
+            for value in values.iter() {
+                if !first {
+                    output.push_str(" | ");
+                }
+                first = false;
+                if value.is_tuple() {
+                    output.push('{');
+                    write_value(output, value);
+                    output.push('}');
+                } else {
+                    write_value(output, value);
+                }
+            }
+        }
+    }
+}
+
+fn write_entries(output: &mut String, dictionary: &ParsedDictionary) {
+    let mut entries: Vec<_> = dictionary.iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    let mut first = true;
+    for (key, value) in entries {
+        if !first {
+            output.push_str("; ");
+        }
+        first = false;
+        write_string(output, key);
+        output.push_str(": ");
+        write_value(output, value);
+    }
+}
+
+fn write_elements(output: &mut String, list: &ParsedList) {
+    let mut first = true;
+    for element in &list.elements {
+        if !first {
+            output.push_str("; ");
+        }
+        first = false;
+        write_value(output, element);
+    }
+}
+
+fn write_compound(output: &mut String, compound: &ParsedCompound) {
+    let components = &compound.components;
+    for (i, component) in components.iter().enumerate() {
+        if i > 0 {
+            if compound.whitespace[i - 1] {
+                output.push(' ');
+            } else {
+                output.push('~');
+            }
+        }
+        // Adjacent text terms would be joined, so they are bracketed.
+        let text_before = i > 0 && components[i - 1].is_text();
+        let text_after = i + 1 < components.len() && components[i + 1].is_text();
+        if component.is_text() && (text_before || text_after) {
+            output.push('{');
+            write_value(output, component);
+            output.push('}');
+        } else {
+            write_term(output, component);
+        }
+    }
+}
+
+/// Write a component of a compound.
+fn write_term(output: &mut String, value: &ParsedValue) {
+    match value {
+        ParsedValue::Text(text, ..) => write_text(output, &text.str),
+        ParsedValue::Tagged(tag, ..) => write_tagged(output, tag),
+        ParsedValue::List(..) => write_value(output, value),
+        _ => {
+            output.push('{');
+            write_value(output, value);
+            output.push('}');
+        }
+    }
+}
+
+fn write_tagged(output: &mut String, tag: &ParsedTaggedValue) {
+    output.push('<');
+    write_word(output, tag.name());
+    for ParsedAttribute(key, value) in &tag.attributes {
+        output.push(' ');
+        write_word(output, key);
+        if let Some(value) = value {
+            output.push(':');
+            write_string(output, value);
+        }
+    }
+    output.push('>');
+    match tag.get() {
+        ParsedValue::Tuple(ParsedTuple::Unit, ..) => {}
+        ParsedValue::Tuple(ParsedTuple::Single(value), ..) => {
+            output.push_str(":{");
+            write_value(output, value);
+            output.push('}');
+        }
+        ParsedValue::Tuple(ParsedTuple::Multiple(values), ..) => {
+            let last = values.len() - 1;
+            for (i, value) in values.iter().enumerate() {
+                output.push(':');
+                write_argument(output, value, i == last);
+            }
+        }
+        value => {
+            output.push(':');
+            write_argument(output, value, true);
+        }
+    }
+}
+
+/// Write an argument of a tag.
+///
+/// A tag argument consumes all following arguments, so it is bracketed unless
+/// it is the last argument.
+pub(crate) fn write_argument(output: &mut String, value: &ParsedValue, last: bool) {
+    match value {
+        ParsedValue::Text(text, ..) if is_single_string(&text.str) => write_text(output, &text.str),
+        ParsedValue::List(..) => write_value(output, value),
+        ParsedValue::Tagged(..) | ParsedValue::Tuple(ParsedTuple::Unit, ..) if last => write_value(output, value),
+        _ => {
+            output.push('{');
+            write_value(output, value);
+            output.push('}');
+        }
+    }
+}
+
+//// Strings
+
+/// Check if text can be written as a sequence of words.
+fn is_words(str: &str) -> bool {
+    !str.is_empty()
+        && !str.starts_with(' ')
+        && !str.ends_with(' ')
+        && !str.contains("  ")
+        && !str.chars().any(|c| c != ' ' && c.is_whitespace())
+}
+
+/// Check if text is written as a single string token.
+pub(crate) fn is_single_string(str: &str) -> bool {
+    !is_words(str) || !str.contains(' ')
+}
+
+pub(crate) fn write_text(output: &mut String, str: &str) {
+    if is_words(str) {
+        for (i, word) in str.split(' ').enumerate() {
+            if i != 0 {
+                output.push(' ');
+            }
+            write_word(output, word);
+        }
+    } else {
+        write_transcription(output, str);
+    }
+}
+
+/// Write a single string token.
+pub(crate) fn write_string(output: &mut String, str: &str) {
+    if is_words(str) && !str.contains(' ') {
+        write_word(output, str);
+    } else {
+        write_transcription(output, str);
+    }
+}
+
+pub(crate) fn write_word(output: &mut String, str: &str) {
+    for c in str.chars() {
+        if is_invisible(c) {
+            write_invisible(output, c);
+            continue;
+        }
+        if is_reserved(c) {
+            output.push('`');
+        }
+        output.push(c);
+    }
+}
+
+fn write_transcription(output: &mut String, str: &str) {
+    output.push('\\');
+    for c in str.chars() {
+        match c {
+            '\\' => output.push_str("`\\"),
+            '`' => output.push_str("``"),
+            '\n' => output.push_str("`n"),
+            '\t' => output.push_str("`t"),
+            c if is_invisible(c) => write_invisible(output, c),
+            c => output.push(c),
+        }
+    }
+    output.push('\\');
+}
+
+/// Write an invisible character as an escape sequence.
+fn write_invisible(output: &mut String, c: char) {
+    match c {
+        '\r' => output.push_str("`r"),
+        '\0' => output.push_str("`0"),
+        c => output.push_str(&format!("`u{{{:X}}}", c as u32)),
+    }
+}
+
+/// Check if a character would not be visible in source, such as a control
+/// character, a non-breaking space or a zero width joiner.
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || (c.is_whitespace() && c != ' ')
+        || matches!(c, '\u{AD}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}')
+}
+
+fn is_reserved(c: char) -> bool {
+    matches!(c, ':' | ';' | '|' | '~' | '`' | '\\' | '{' | '}' | '[' | ']' | '<' | '>' | '#')
+}
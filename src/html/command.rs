@@ -2,30 +2,52 @@
 //!
 //! Test: cargo run --bin khi-html-cmd --features="html" -- examples/frontpage.html.khi
 //! Test: cargo run --bin khi-html-cmd --features="html" -- examples/fruits.xml.khi
+//!
+//! Use -D name to define a name for conditionals.
 
-use std::env;
+use std::collections::HashSet;
+use std::{env, process};
 use std::fs::File;
 use std::io::Read;
-use khi::html::{PreprocessorError, write_html};
+use khi::html::{preprocessor_error_to_string, write_html};
+use khi::condition::{condition_error_to_string, evaluate_conditionals};
 use khi::parse::{parse_value_str};
 use khi::parse::parser::error_to_string;
 
 fn main() {
     match preprocess() {
         Ok(output) => print!("{}\n\n", output),
-        Err(error) => eprint!("{}\n\n", error),
+        Err(error) => {
+            eprint!("{}\n\n", error);
+            process::exit(1);
+        }
     };
 }
 
 fn preprocess() -> Result<String, String> {
     let mut args = env::args();
     args.next(); // The first arg is the binary. Skip.
+    let mut defines = HashSet::new();
+    let mut paths = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "-D" {
+            match args.next() {
+                Some(name) => defines.insert(name),
+                None => return Err("Specify name to define after -D.".to_string()),
+            };
+        } else if let Some(name) = arg.strip_prefix("-D") {
+            defines.insert(name.to_string());
+        } else {
+            paths.push(arg);
+        }
+    }
+    let mut args = paths.into_iter();
     if let Some(first) = args.next() {
-        let mut file = File::open(first).unwrap();
+        let mut file = File::open(&first).map_err(|e| format!("Could not open {}: {}.", first, e))?;
         let mut source = String::new();
-        file.read_to_string(&mut source).unwrap();
+        file.read_to_string(&mut source).map_err(|e| format!("Could not read {}: {}.", first, e))?;
         eprint!("Preprocessing document of size: {}\n\n", source.len());
-        let document = match parse_value_str(&source) {
+        let mut document = match parse_value_str(&source) {
             Ok(document) => document,
             Err(errors) => {
                 let mut errs = String::new();
@@ -36,23 +58,15 @@ fn preprocess() -> Result<String, String> {
                 return Err(errs);
             },
         };
-        match write_html(&document) {
-            Ok(output) => Ok(output),
-            Err(error) => {
-                Err(match error {
-                    PreprocessorError::IllegalTable(at) => {
-                        format!("Illegal table at {}:{}.", at.line, at.column)
-                    }
-                    PreprocessorError::MacroError(error) => error,
-                    PreprocessorError::TooManyArguments(at) => {
-                        format!("Tag at {}:{} has more than one argument.", at.line, at.column)
-                    }
-                    PreprocessorError::IllegalTuple(at) => {
-                        format!("Illegal tuple at {}:{}.", at.line, at.column)
-                    }
-                })
+        if let Err(errors) = evaluate_conditionals(&mut document, &defines) {
+            let mut errs = String::new();
+            for e in errors {
+                errs.push_str(&condition_error_to_string(&e));
+                errs.push('\n');
             }
+            return Err(errs);
         }
+        write_html(&document).map_err(|error| preprocessor_error_to_string(&error))
     } else {
         Err(format!("Specify source file as first argument."))
     }
@@ -1,11 +1,22 @@
+use std::borrow::Cow;
+use std::fmt::{Debug, Formatter};
 use std::ops::Deref;
 use crate::{Dictionary, Tagged, Value, Text, Element, Attribute, Compound, Tuple};
+use crate::macros::{expand_macros, has_macros, macro_error_to_string, MacroError};
 use crate::pdm::{ParsedDictionary, ParsedTaggedValue, ParsedTuple, ParsedValue, Position};
 
+/// Write a document as XML/HTML. Document macros are expanded first.
 pub fn write_html(value: &ParsedValue) -> Result<String, PreprocessorError> {
+    let value = if has_macros(value) {
+        let mut value = value.clone();
+        expand_macros(&mut value).map_err(PreprocessorError::MacroExpansion)?;
+        Cow::Owned(value)
+    } else {
+        Cow::Borrowed(value)
+    };
     let mut output = String::new();
     let mut writer = XmlWriter { output: &mut output, column: 1, newline: 60, last: LastType::Whitespace, };
-    writer.write_xml_compound(value)?;
+    writer.write_xml_compound(&value)?;
     Ok(output)
 }
 
@@ -110,15 +121,13 @@ impl XmlWriter<'_> {
         if name.ends_with('!') {
             if name.deref() == "doctype!" {
                 if tag.has_attributes() {
-                    return Err(PreprocessorError::MacroError(format!("doctype! macro cannot have attributes.")))
+                    return Err(PreprocessorError::MacroError(at, format!("doctype! macro cannot have attributes.")))
                 }
-                if !inner_value.is_text() {
-                    if inner_value.is_tuple() {eprintln!("{}", inner_value.as_tuple().unwrap().len())};
-                    return Err(PreprocessorError::MacroError(format!("doctype! must have 1 text argument.")))
-                }
-                let doctype = inner_value;
+                let Some(doctype) = inner_value.as_text() else {
+                    return Err(PreprocessorError::MacroError(at, format!("doctype! must have 1 text argument.")))
+                };
                 self.push_str_non_breaking("<!DOCTYPE ");
-                self.push_str_non_breaking(doctype.as_text().unwrap().as_str());
+                self.push_str_non_breaking(doctype.as_str());
                 self.push_str_non_breaking(">");
                 Ok(())
             } else if name.deref() == "raw!" {
@@ -126,10 +135,10 @@ impl XmlWriter<'_> {
                     self.output.push_str(text.as_str());
                     Ok(())
                 } else {
-                    Err(PreprocessorError::MacroError(format!("raw! can only take a text argument.")))
+                    Err(PreprocessorError::MacroError(at, format!("raw! can only take a text argument.")))
                 }
             } else {
-                Err(PreprocessorError::MacroError(format!("Unknown macro {}.", name)))
+                Err(PreprocessorError::MacroError(at, format!("Unknown macro {}.", name)))
             }
         } else {
             self.push_non_breaking('<');
@@ -150,8 +159,8 @@ impl XmlWriter<'_> {
                 };
             }
             self.push_non_breaking('>');
-            if inner_value.is_tuple() {//todo
-                match inner_value.as_tuple().unwrap() {
+            if let Some(tuple) = inner_value.as_tuple() {//todo
+                match tuple {
                     ParsedTuple::Unit => return Ok(()), // Self closing tag
                     ParsedTuple::Single(s) => {
                         if s.is_unit() {
@@ -187,9 +196,42 @@ impl XmlWriter<'_> {
 
 }
 
+/// Error writing XML/HTML.
 pub enum PreprocessorError {
+    /// Table at X cannot be written.
     IllegalTable(Position),
-    MacroError(String),
+    /// Macro at X is malformed, as described by Y.
+    MacroError(Position, String),
+    /// Tag at X has more than one argument.
     TooManyArguments(Position),
+    /// Tuple at X cannot be written.
     IllegalTuple(Position),
+    /// Document macros could not be expanded.
+    MacroExpansion(Vec<MacroError>),
+}
+
+impl Debug for PreprocessorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", preprocessor_error_to_string(self))
+    }
+}
+
+pub fn preprocessor_error_to_string(error: &PreprocessorError) -> String {
+    match error {
+        PreprocessorError::IllegalTable(at) => {
+            format!("Illegal table at {}:{}.", at.line, at.column)
+        }
+        PreprocessorError::MacroError(at, message) => {
+            format!("Macro error at {}:{}: {}", at.line, at.column, message)
+        }
+        PreprocessorError::TooManyArguments(at) => {
+            format!("Tag at {}:{} has more than one argument.", at.line, at.column)
+        }
+        PreprocessorError::IllegalTuple(at) => {
+            format!("Illegal tuple at {}:{}.", at.line, at.column)
+        }
+        PreprocessorError::MacroExpansion(errors) => {
+            errors.iter().map(macro_error_to_string).collect::<Vec<String>>().join("\n")
+        }
+    }
 }
@@ -0,0 +1,465 @@
+//! Inclusion of other documents.
+//!
+//! Documents loaded with this module may include other documents with the
+//! reserved `include!` tag. The argument is a path, resolved relative to the
+//! including document by a [Resolver].
+//!
+//! * `<include!>:path` is replaced by the value document at path.
+//! * `<include! dictionary>:path` is replaced by the dictionary document at path.
+//! * `<include! list>:path` is replaced by the list document at path.
+//! * `<include! list splice>:path` as a list element is replaced by the elements
+//!   of the list document at path.
+//! * An entry `include!: path` or `include!: [path; ...]` of a dictionary is
+//!   replaced by the entries of the dictionary documents.
+//!
+//! Positions of included values are relative to their own document. The
+//! [SourceMap] tells which document a value came from.
+//!
+//! The limits of [ParseOptions] apply to each document separately, so an
+//! included document is held to the same limits as the document including it.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use crate::{Tagged, Value};
+use crate::diff::Step;
+use crate::parse::{Interner, parse_dictionary_str_with, parse_list_str_with, parse_value_str_with, ParseOptions};
+use crate::parse::parser::{error_to_string, ParseError};
+use crate::pdm::{ParsedAttribute, ParsedCompound, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedTuple, ParsedValue, Position, SharedStr};
+
+/// Name of the include tag.
+pub const INCLUDE_TAG: &str = "include!";
+
+/// Key of the dictionary entry that includes entries.
+pub const INCLUDE_KEY: &str = "include!";
+
+//// Resolver
+
+/// Resolves include paths to documents.
+pub trait Resolver {
+    /// Resolve a path relative to the including document, or relative to the
+    /// resolver if there is none.
+    ///
+    /// Returns a name that uniquely identifies the document, and its source.
+    fn resolve(&mut self, path: &str, from: Option<&str>) -> Result<(SharedStr, String), String>;
+}
+
+/// Resolves include paths to files.
+///
+/// Relative paths are resolved from the directory of the including file.
+pub struct FileResolver {
+    root: PathBuf,
+}
+
+impl FileResolver {
+
+    /// Resolve top-level paths relative to the working directory.
+    pub fn new() -> Self {
+        FileResolver { root: PathBuf::from(".") }
+    }
+
+    /// Resolve top-level paths relative to a directory.
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Self {
+        FileResolver { root: root.into() }
+    }
+
+}
+
+impl Default for FileResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver for FileResolver {
+    fn resolve(&mut self, path: &str, from: Option<&str>) -> Result<(SharedStr, String), String> {
+        let directory = match from.and_then(|f| PathBuf::from(f).parent().map(|p| p.to_path_buf())) {
+            Some(directory) => directory,
+            None => self.root.clone(),
+        };
+        let file = directory.join(path);
+        let file = file.canonicalize().map_err(|e| format!("{}: {}", file.display(), e))?;
+        let source = std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+        Ok((SharedStr::from(file.to_string_lossy().as_ref()), source))
+    }
+}
+
+//// Source map
+
+/// Maps values of a document to the documents they came from.
+pub struct SourceMap {
+    /// Path of each included value and its document, root first.
+    entries: Vec<(Vec<Step>, SharedStr)>,
+}
+
+impl SourceMap {
+
+    /// Get the document the value at a path came from.
+    pub fn document(&self, path: &[Step]) -> &str {
+        let mut document = &self.entries[0].1;
+        let mut length = 0;
+        for (prefix, name) in &self.entries {
+            if prefix.len() >= length && path.starts_with(prefix) {
+                document = name;
+                length = prefix.len();
+            }
+        }
+        document
+    }
+
+    /// Get the included documents, the root first.
+    pub fn documents(&self) -> Vec<&str> {
+        let mut documents: Vec<&str> = vec![];
+        for (_, name) in &self.entries {
+            if !documents.contains(&name.as_ref()) {
+                documents.push(name);
+            }
+        }
+        documents
+    }
+
+}
+
+/// A document with its includes expanded.
+pub struct Included<T> {
+    pub document: T,
+    pub sources: SourceMap,
+}
+
+//// Load
+
+/// Load a value document and expand its includes.
+pub fn load_value<R: Resolver>(resolver: &mut R, path: &str) -> Result<Included<ParsedValue>, Vec<IncludeError>> {
+    load_value_with(resolver, path, &ParseOptions::default())
+}
+
+/// Load a value document and expand its includes, parsing each document
+/// within the limits of the options.
+pub fn load_value_with<R: Resolver>(resolver: &mut R, path: &str, options: &ParseOptions) -> Result<Included<ParsedValue>, Vec<IncludeError>> {
+    load(resolver, path, Kind::Value, options).map(|(v, sources)| Included { document: v, sources })
+}
+
+/// Load a dictionary document and expand its includes.
+pub fn load_dictionary<R: Resolver>(resolver: &mut R, path: &str) -> Result<Included<ParsedDictionary>, Vec<IncludeError>> {
+    load_dictionary_with(resolver, path, &ParseOptions::default())
+}
+
+/// Load a dictionary document and expand its includes, parsing each document
+/// within the limits of the options.
+pub fn load_dictionary_with<R: Resolver>(resolver: &mut R, path: &str, options: &ParseOptions) -> Result<Included<ParsedDictionary>, Vec<IncludeError>> {
+    load(resolver, path, Kind::Dictionary, options).map(|(v, sources)| match v {
+        ParsedValue::Dictionary(document, ..) => Included { document, sources },
+        _ => unreachable!(),
+    })
+}
+
+/// Load a list document and expand its includes.
+pub fn load_list<R: Resolver>(resolver: &mut R, path: &str) -> Result<Included<ParsedList>, Vec<IncludeError>> {
+    load_list_with(resolver, path, &ParseOptions::default())
+}
+
+/// Load a list document and expand its includes, parsing each document within
+/// the limits of the options.
+pub fn load_list_with<R: Resolver>(resolver: &mut R, path: &str, options: &ParseOptions) -> Result<Included<ParsedList>, Vec<IncludeError>> {
+    load(resolver, path, Kind::List, options).map(|(v, sources)| match v {
+        ParsedValue::List(document, ..) => Included { document, sources },
+        _ => unreachable!(),
+    })
+}
+
+fn load<R: Resolver>(resolver: &mut R, path: &str, kind: Kind, options: &ParseOptions) -> Result<(ParsedValue, SourceMap), Vec<IncludeError>> {
+    let (name, source) = match resolver.resolve(path, None) {
+        Ok(resolved) => resolved,
+        Err(message) => return Err(vec![IncludeError::Unresolved(SharedStr::from(path), None, message)]),
+    };
+    let mut includer = Includer { resolver, options, interner: Interner::new(), stack: vec![], path: vec![], sources: vec![], errors: vec![] };
+    let value = includer.include(name, &source, kind, None);
+    if includer.errors.is_empty() {
+        Ok((value.unwrap(), SourceMap { entries: includer.sources }))
+    } else {
+        Err(includer.errors)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Value,
+    Dictionary,
+    List,
+}
+
+struct Includer<'a, R: Resolver> {
+    resolver: &'a mut R,
+    /// Limits applied to each document.
+    options: &'a ParseOptions,
+    /// Strings shared by the included documents.
+    interner: Interner,
+    /// Documents being included.
+    stack: Vec<SharedStr>,
+    /// Path of the current value in the expanded document.
+    path: Vec<Step>,
+    sources: Vec<(Vec<Step>, SharedStr)>,
+    errors: Vec<IncludeError>,
+}
+
+impl<'a, R: Resolver> Includer<'a, R> {
+
+    /// Parse and expand a document.
+    fn include(&mut self, name: SharedStr, source: &str, kind: Kind, at: Option<(&SharedStr, Position)>) -> Option<ParsedValue> {
+        if self.stack.contains(&name) {
+            let (document, at) = at.unwrap();
+            let mut cycle = self.stack.clone();
+            cycle.push(name);
+            self.errors.push(IncludeError::Cycle(document.clone(), at, cycle));
+            return None;
+        }
+        const AT: Position = Position { index: 0, line: 1, column: 1 };
+        let parsed = match kind {
+            Kind::Value => parse_value_str_with(source, self.options, &mut self.interner),
+            Kind::Dictionary => parse_dictionary_str_with(source, self.options, &mut self.interner).map(|d| ParsedValue::Dictionary(d, AT, AT)),
+            Kind::List => parse_list_str_with(source, self.options, &mut self.interner).map(|l| ParsedValue::List(l, AT, AT)),
+        };
+        let value = match parsed {
+            Ok(value) => value,
+            Err(errors) => {
+                self.errors.push(IncludeError::ParseError(name, errors));
+                return None;
+            }
+        };
+        self.sources.push((self.path.clone(), name.clone()));
+        self.stack.push(name.clone());
+        let value = self.expand_value(value, &name);
+        self.stack.pop();
+        Some(value)
+    }
+
+    /// Resolve and include a document.
+    fn resolve(&mut self, path: &str, kind: Kind, document: &SharedStr, at: Position) -> Option<ParsedValue> {
+        match self.resolver.resolve(path, Some(document)) {
+            Ok((name, source)) => self.include(name, &source, kind, Some((document, at))),
+            Err(message) => {
+                self.errors.push(IncludeError::Unresolved(document.clone(), Some(at), message));
+                None
+            }
+        }
+    }
+
+    fn expand_value(&mut self, value: ParsedValue, document: &SharedStr) -> ParsedValue {
+        match value {
+            ParsedValue::Tagged(tag, from, to) if tag.name() == INCLUDE_TAG => {
+                let (kind, splice) = match include_arguments(&tag) {
+                    Some(arguments) => arguments,
+                    None => {
+                        self.errors.push(IncludeError::IllegalInclude(document.clone(), from));
+                        return ParsedValue::Tagged(tag, from, to);
+                    }
+                };
+                if splice {
+                    self.errors.push(IncludeError::IllegalSplice(document.clone(), from));
+                    return ParsedValue::Tagged(tag, from, to);
+                }
+                let path = tag.get().as_text().unwrap().str.clone();
+                match self.resolve(&path, kind, document, from) {
+                    Some(value) => value,
+                    None => ParsedValue::Tagged(tag, from, to),
+                }
+            }
+            ParsedValue::Tagged(mut tag, from, to) => {
+                self.path.push(Step::Tag(tag.name.clone()));
+                let value = std::mem::replace(&mut *tag.value, ParsedValue::nil(from, to));
+                *tag.value = self.expand_value(value, document);
+                self.path.pop();
+                ParsedValue::Tagged(tag, from, to)
+            }
+            ParsedValue::Dictionary(dictionary, from, to) => {
+                ParsedValue::Dictionary(self.expand_dictionary(dictionary, document), from, to)
+            }
+            ParsedValue::List(list, from, to) => {
+                ParsedValue::List(self.expand_list(list, document), from, to)
+            }
+            ParsedValue::Tuple(ParsedTuple::Single(value), from, to) => {
+                self.path.push(Step::Element(0));
+                let value = self.expand_value(*value, document);
+                self.path.pop();
+                ParsedValue::Tuple(ParsedTuple::Single(Box::new(value)), from, to)
+            }
+            ParsedValue::Tuple(ParsedTuple::Multiple(values), from, to) => {
+                let mut expanded = vec![];
+                for (i, value) in values.into_vec().into_iter().enumerate() {
+                    self.path.push(Step::Element(i));
+                    expanded.push(self.expand_value(value, document));
+                    self.path.pop();
+                }
+                ParsedValue::Tuple(ParsedTuple::Multiple(expanded.into_boxed_slice()), from, to)
+            }
+            ParsedValue::Compound(compound, from, to) => {
+                let components = compound.components.into_iter().map(|c| self.expand_value(c, document)).collect();
+                ParsedValue::Compound(ParsedCompound { components, whitespace: compound.whitespace }, from, to)
+            }
+            value => value,
+        }
+    }
+
+    fn expand_dictionary(&mut self, dictionary: ParsedDictionary, document: &SharedStr) -> ParsedDictionary {
+        let mut entries = HashMap::new();
+        let mut includes = None;
+        for (key, value) in dictionary.entries {
+            if key.as_ref() == INCLUDE_KEY {
+                includes = Some(value);
+                continue;
+            }
+            self.path.push(Step::Key(key.clone()));
+            let value = self.expand_value(value, document);
+            self.path.pop();
+            entries.insert(key, value);
+        }
+        if let Some(includes) = includes {
+            let paths = match &includes {
+                ParsedValue::Text(..) => vec![&includes],
+                ParsedValue::List(list, ..) => list.elements.iter().collect(),
+                _ => vec![&includes],
+            };
+            for path in paths {
+                let at = path.from();
+                let path = match path.as_text() {
+                    Some(text) => text.str.clone(),
+                    None => {
+                        self.errors.push(IncludeError::IllegalInclude(document.clone(), at));
+                        continue;
+                    }
+                };
+                let sources = self.sources.len();
+                if let Some(ParsedValue::Dictionary(included, ..)) = self.resolve(&path, Kind::Dictionary, document, at) {
+                    // The included document is mapped to each of its entries.
+                    let (_, name) = self.sources.remove(sources);
+                    for (key, value) in included.entries {
+                        if entries.contains_key(&key) {
+                            self.errors.push(IncludeError::DuplicateKey(document.clone(), at, key));
+                            continue;
+                        }
+                        let mut path = self.path.clone();
+                        path.push(Step::Key(key.clone()));
+                        self.sources.insert(sources, (path, name.clone()));
+                        entries.insert(key, value);
+                    }
+                }
+            }
+        }
+        ParsedDictionary { entries }
+    }
+
+    fn expand_list(&mut self, list: ParsedList, document: &SharedStr) -> ParsedList {
+        let mut elements = vec![];
+        for element in list.elements {
+            if let ParsedValue::Tagged(tag, from, _) = &element {
+                if tag.name() == INCLUDE_TAG {
+                    if let Some((Kind::List, true)) = include_arguments(tag) {
+                        let path = tag.get().as_text().unwrap().str.clone();
+                        let sources = self.sources.len();
+                        if let Some(ParsedValue::List(included, ..)) = self.resolve(&path, Kind::List, document, *from) {
+                            // Spliced elements are remapped to their new indices.
+                            let name = self.sources[sources].1.clone();
+                            let depth = self.path.len();
+                            for (prefix, _) in &mut self.sources[sources..] {
+                                if let Some(Step::Index(i)) = prefix.get_mut(depth) {
+                                    *i += elements.len();
+                                }
+                            }
+                            self.sources.remove(sources);
+                            for element in included.elements {
+                                let mut path = self.path.clone();
+                                path.push(Step::Index(elements.len()));
+                                self.sources.insert(sources, (path, name.clone()));
+                                elements.push(element);
+                            }
+                        }
+                        continue;
+                    }
+                }
+            }
+            self.path.push(Step::Index(elements.len()));
+            elements.push(self.expand_value(element, document));
+            self.path.pop();
+        }
+        ParsedList { elements }
+    }
+
+}
+
+/// Read the kind and splice flag of an include tag.
+fn include_arguments(tag: &ParsedTaggedValue) -> Option<(Kind, bool)> {
+    tag.get().as_text()?;
+    let mut kind = Kind::Value;
+    let mut splice = false;
+    for ParsedAttribute(key, value) in &tag.attributes {
+        if value.is_some() {
+            return None;
+        }
+        match key.as_ref() {
+            "dictionary" => kind = Kind::Dictionary,
+            "list" => kind = Kind::List,
+            "splice" => splice = true,
+            _ => return None,
+        }
+    }
+    if splice && kind != Kind::List {
+        return None;
+    }
+    Some((kind, splice))
+}
+
+//// Errors
+
+/// Error loading a document with includes.
+pub enum IncludeError {
+    /// Include in document X at Y could not be resolved.
+    Unresolved(SharedStr, Option<Position>, String),
+    /// Document X could not be parsed.
+    ParseError(SharedStr, Vec<ParseError>),
+    /// Include in document X at Y forms a cycle.
+    Cycle(SharedStr, Position, Vec<SharedStr>),
+    /// Include in document X at Y is malformed.
+    IllegalInclude(SharedStr, Position),
+    /// Splicing include in document X at Y is not a list element.
+    IllegalSplice(SharedStr, Position),
+    /// Included entry Z conflicts with an entry of document X included at Y.
+    DuplicateKey(SharedStr, Position, SharedStr),
+}
+
+impl Debug for IncludeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", include_error_to_string(self))
+    }
+}
+
+pub fn include_error_to_string(error: &IncludeError) -> String {
+    match error {
+        IncludeError::Unresolved(document, Some(at), message) => {
+            format!("Could not resolve include in {} at {}:{}: {}", document, at.line, at.column, message)
+        }
+        IncludeError::Unresolved(document, None, message) => {
+            format!("Could not resolve {}: {}", document, message)
+        }
+        IncludeError::ParseError(document, errors) => {
+            let mut string = format!("Could not parse {}:", document);
+            for error in errors {
+                string.push(' ');
+                string.push_str(&error_to_string(error));
+            }
+            string
+        }
+        IncludeError::Cycle(document, at, cycle) => {
+            let cycle: Vec<&str> = cycle.iter().map(|d| d.as_ref()).collect();
+            format!("Include in {} at {}:{} forms a cycle: {}", document, at.line, at.column, cycle.join(" -> "))
+        }
+        IncludeError::IllegalInclude(document, at) => {
+            format!("Illegal include in {} at {}:{}.", document, at.line, at.column)
+        }
+        IncludeError::IllegalSplice(document, at) => {
+            format!("Splicing include in {} at {}:{} must be a list element.", document, at.line, at.column)
+        }
+        IncludeError::DuplicateKey(document, at, key) => {
+            format!("Included key {} is already defined, in {} at {}:{}.", key, document, at.line, at.column)
+        }
+    }
+}
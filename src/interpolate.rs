@@ -0,0 +1,265 @@
+//! Variable interpolation in text.
+//!
+//! Expands placeholders in the text of a parsed document:
+//!
+//! * `${NAME}` is replaced by the variable `NAME`.
+//! * `${NAME:-default}` is replaced by the variable, or by the default if the
+//!   variable is not set.
+//! * `$$` is replaced by `$`. A `$` not followed by `{` or `$` is kept.
+//! * `<env!>:NAME` and `<env!>:NAME:default` are replaced by the text of the
+//!   variable.
+//!
+//! Since brackets are reserved characters, placeholders are written in
+//! transcriptions and text blocks, as in `\${HOME}/data\`, or escaped.
+//! Dictionary keys and tag names are not interpolated.
+//!
+//! Errors are positioned at the dollar sign of the placeholder in the
+//! document, as found by the lexer.
+
+use std::fmt::{Debug, Formatter};
+use crate::{Tagged, Tuple, Value};
+use crate::lex::text_positions;
+use crate::pdm::{ParsedCompound, ParsedDictionary, ParsedText, ParsedTuple, ParsedValue, Position, SharedStr};
+
+/// Name of the variable tag.
+pub const VARIABLE_TAG: &str = "env!";
+
+/// Look up a variable in the environment of the process.
+pub fn environment(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Interpolate the text of a value parsed from a document.
+pub fn interpolate_value<F: Fn(&str) -> Option<String>>(value: &mut ParsedValue, document: &str, variables: F) -> Result<(), Vec<InterpolationError>> {
+    let mut errors = vec![];
+    let dollars = text_positions(document, '$').unwrap_or_default();
+    interpolate(value, &dollars, &variables, &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Interpolate the text of a dictionary parsed from a document.
+pub fn interpolate_dictionary<F: Fn(&str) -> Option<String>>(dictionary: &mut ParsedDictionary, document: &str, variables: F) -> Result<(), Vec<InterpolationError>> {
+    let mut errors = vec![];
+    let dollars = text_positions(document, '$').unwrap_or_default();
+    for value in dictionary.entries.values_mut() {
+        interpolate(value, &dollars, &variables, &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Interpolate a value, given the source positions of the dollar signs in
+/// the text of the document.
+fn interpolate<F: Fn(&str) -> Option<String>>(value: &mut ParsedValue, dollars: &[Position], variables: &F, errors: &mut Vec<InterpolationError>) {
+    match value {
+        ParsedValue::Text(text, from, to) => {
+            if text.str.contains('$') {
+                let positions = text_dollars(dollars, &text.str, *from, *to);
+                if let Some(str) = expand(&text.str, *from, positions, variables, errors) {
+                    text.str = SharedStr::from(str);
+                }
+            }
+        }
+        ParsedValue::Tagged(tag, from, to) if tag.name() == VARIABLE_TAG => {
+            let (name, default) = match variable_arguments(tag.get()) {
+                Some(arguments) => arguments,
+                None => {
+                    errors.push(InterpolationError::IllegalVariable(*from));
+                    return;
+                }
+            };
+            let str = match (variables(&name), default) {
+                (Some(str), _) => SharedStr::from(str),
+                (None, Some(default)) => default,
+                (None, None) => {
+                    errors.push(InterpolationError::MissingVariable(*from, name.to_string()));
+                    return;
+                }
+            };
+            *value = ParsedValue::Text(ParsedText { str }, *from, *to);
+        }
+        ParsedValue::Tagged(tag, ..) => interpolate(&mut tag.value, dollars, variables, errors),
+        ParsedValue::Tuple(ParsedTuple::Single(value), ..) => interpolate(value, dollars, variables, errors),
+        ParsedValue::Tuple(ParsedTuple::Multiple(values), ..) => {
+            for value in values.iter_mut() {
+                interpolate(value, dollars, variables, errors);
+            }
+        }
+        ParsedValue::Dictionary(dictionary, ..) => {
+            for value in dictionary.entries.values_mut() {
+                interpolate(value, dollars, variables, errors);
+            }
+        }
+        ParsedValue::List(list, ..) => {
+            for value in list.elements.iter_mut() {
+                interpolate(value, dollars, variables, errors);
+            }
+        }
+        ParsedValue::Compound(compound, from, to) => {
+            for value in compound.components.iter_mut() {
+                interpolate(value, dollars, variables, errors);
+            }
+            if let Some(joined) = join_text(compound, *from, *to) {
+                *value = joined;
+            }
+        }
+        ParsedValue::Tuple(ParsedTuple::Unit, ..) | ParsedValue::Nil(..) => {}
+    }
+}
+
+/// Read the name and default of a variable tag.
+fn variable_arguments(value: &ParsedValue) -> Option<(SharedStr, Option<SharedStr>)> {
+    match value {
+        ParsedValue::Text(name, ..) => Some((name.str.clone(), None)),
+        ParsedValue::Tuple(tuple, ..) if tuple.len() == 2 => {
+            let name = tuple.get(0)?.as_text()?.str.clone();
+            let default = tuple.get(1)?.as_text()?.str.clone();
+            Some((name, Some(default)))
+        }
+        _ => None,
+    }
+}
+
+/// Join adjacent text components of a compound.
+///
+/// Returns a text value if all components are text.
+fn join_text(compound: &mut ParsedCompound, from: Position, to: Position) -> Option<ParsedValue> {
+    let mut components: Vec<ParsedValue> = vec![];
+    let mut whitespace = vec![];
+    for (i, component) in std::mem::take(&mut compound.components).into_iter().enumerate() {
+        let space = i > 0 && compound.whitespace[i - 1];
+        if let (Some(ParsedValue::Text(last, ..)), ParsedValue::Text(text, ..)) = (components.last_mut(), &component) {
+            let mut str = String::from(last.str.as_ref());
+            if space {
+                str.push(' ');
+            }
+            str.push_str(&text.str);
+            last.str = SharedStr::from(str);
+            continue;
+        }
+        if i > 0 {
+            whitespace.push(space);
+        }
+        components.push(component);
+    }
+    if components.len() == 1 {
+        let mut component = components.pop().unwrap();
+        if let ParsedValue::Text(text, ..) = component {
+            component = ParsedValue::Text(text, from, to);
+        }
+        return Some(component);
+    }
+    compound.components = components;
+    compound.whitespace = whitespace;
+    None
+}
+
+/// Source positions of the dollar signs of a text from X to Y.
+///
+/// None if the document does not have as many dollar signs there as the
+/// text, as for text that was not parsed from it.
+fn text_dollars<'a>(dollars: &'a [Position], str: &str, from: Position, to: Position) -> Option<&'a [Position]> {
+    let start = dollars.partition_point(|at| at.index < from.index);
+    let end = dollars.partition_point(|at| at.index < to.index);
+    let dollars = &dollars[start..end.max(start)];
+    if dollars.len() == str.matches('$').count() {
+        Some(dollars)
+    } else {
+        None
+    }
+}
+
+/// Expand the placeholders of a string starting at X.
+///
+/// Errors are positioned at the dollar sign of the placeholder if its source
+/// position is known, and at the start of the text otherwise.
+fn expand<F: Fn(&str) -> Option<String>>(str: &str, from: Position, dollars: Option<&[Position]>, variables: &F, errors: &mut Vec<InterpolationError>) -> Option<String> {
+    let mut output = String::with_capacity(str.len());
+    let mut rest = str;
+    let mut ok = true;
+    while let Some(i) = rest.find('$') {
+        output.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let before = &str[..str.len() - rest.len()];
+        let at = dollars.map_or(from, |dollars| dollars[before.matches('$').count()]);
+        if let Some(after) = rest.strip_prefix("$$") {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = match after.find('}') {
+                Some(end) => end,
+                None => {
+                    errors.push(InterpolationError::UnclosedPlaceholder(at));
+                    return None;
+                }
+            };
+            let placeholder = &after[..end];
+            let (name, default) = match placeholder.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (placeholder, None),
+            };
+            if name.is_empty() {
+                errors.push(InterpolationError::IllegalVariable(at));
+                ok = false;
+            } else {
+                match (variables(name), default) {
+                    (Some(value), _) => output.push_str(&value),
+                    (None, Some(default)) => output.push_str(default),
+                    (None, None) => {
+                        errors.push(InterpolationError::MissingVariable(at, name.to_string()));
+                        ok = false;
+                    }
+                }
+            }
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+    if ok {
+        Some(output)
+    } else {
+        None
+    }
+}
+
+//// Errors
+
+/// Error interpolating variables.
+pub enum InterpolationError {
+    /// Variable Y at X is not set.
+    MissingVariable(Position, String),
+    /// Placeholder at X is not closed.
+    UnclosedPlaceholder(Position),
+    /// Placeholder or variable tag at X is malformed.
+    IllegalVariable(Position),
+}
+
+impl Debug for InterpolationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", interpolation_error_to_string(self))
+    }
+}
+
+pub fn interpolation_error_to_string(error: &InterpolationError) -> String {
+    match error {
+        InterpolationError::MissingVariable(at, name) => {
+            format!("Variable {} at {}:{} is not set.", name, at.line, at.column)
+        }
+        InterpolationError::UnclosedPlaceholder(at) => {
+            format!("Unclosed placeholder at {}:{}.", at.line, at.column)
+        }
+        InterpolationError::IllegalVariable(at) => {
+            format!("Illegal variable at {}:{}.", at.line, at.column)
+        }
+    }
+}
@@ -1,17 +1,21 @@
 //! Khi lexer reference implementation.
 
+use std::borrow::Cow;
 use std::ops::Deref;
+use std::str::Chars;
 use crate::pdm::Position;
-use crate::translate_escape_character;
+use crate::{translate_code_point, translate_escape_character};
 
 //// Token
 
+/// The text of a word, transcription or text block is borrowed from the source
+/// if it is lexed from a string and does not differ from it.
 #[derive(PartialEq, Eq, Clone)]
-pub enum Token {
+pub enum Token<'a> {
     Whitespace(Position),
-    Word(Position, String),
-    Transcription(Position, String),
-    TextBlock(Position, String),
+    Word(Position, Cow<'a, str>),
+    Transcription(Position, Cow<'a, str>),
+    TextBlock(Position, Cow<'a, str>),
     Colon(Position),
     Semicolon(Position),
     Bar(Position),
@@ -26,7 +30,7 @@ pub enum Token {
     End(Position),
 }
 
-impl Token {
+impl Token<'_> {
 
     pub fn at(&self) -> Position {
         match self {
@@ -53,20 +57,37 @@ impl Token {
 
 //// Char iterator
 
-pub struct CharIter<It: Iterator<Item = char>> {
+pub struct CharIter<'a, It: Iterator<Item = char>> {
     chars: It,
+    /// The string the characters are read from, if any.
+    source: Option<&'a str>,
     c: Option<char>, // Current character
     d: Option<char>, // Next character
     e: Option<char>,
     index: usize,
     line: usize,
     column: usize,
+    /// Byte offsets of the current, next and following character.
+    c_byte: usize,
+    d_byte: usize,
+    e_byte: usize,
+    /// Number of bytes read from the characters.
+    read: usize,
+    /// Byte offset after the last consumed character.
+    consumed: usize,
+    /// Source positions of a character in the text of string tokens, if they
+    /// are recorded.
+    marked: Option<(char, Vec<Position>)>,
 }
 
-impl <'a, It: Iterator<Item = char>> CharIter<It> {
+impl <'a, It: Iterator<Item = char>> CharIter<'a, It> {
 
     pub fn new(chars: It) -> Self {
-        let mut iter = CharIter { chars, c: None, d: None, e: None, index: 0, line: 1, column: 1 };
+        Self::with_source(chars, None)
+    }
+
+    fn with_source(chars: It, source: Option<&'a str>) -> Self {
+        let mut iter = CharIter { chars, source, c: None, d: None, e: None, index: 0, line: 1, column: 1, c_byte: 0, d_byte: 0, e_byte: 0, read: 0, consumed: 0, marked: None };
         iter.next();
         iter.next();
         iter.next();
@@ -82,11 +103,18 @@ impl <'a, It: Iterator<Item = char>> CharIter<It> {
                 self.column += 1;
             };
             self.index += 1;
+            self.consumed = self.c_byte + c.len_utf8();
         };
         self.c = self.d;
+        self.c_byte = self.d_byte;
         self.d = self.e;
+        self.d_byte = self.e_byte;
         loop {
+            self.e_byte = self.read;
             self.e = self.chars.next();
+            if let Some(e) = self.e {
+                self.read += e.len_utf8();
+            }
             if self.e != Some('\r') {
                 break;
             }
@@ -106,21 +134,120 @@ impl <'a, It: Iterator<Item = char>> CharIter<It> {
         }
     }
 
+    /// Byte offset of the current character in the source.
+    fn byte(&self) -> usize {
+        self.c_byte
+    }
+
+    /// Byte offset after the last consumed character in the source.
+    fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// The underlying character iterator.
+    pub fn source_mut(&mut self) -> &mut It {
+        &mut self.chars
+    }
+
+    /// Record the source position of a character of the text of a string
+    /// token, if the character is recorded.
+    fn mark(&mut self, c: char, at: Position) {
+        if let Some((marked, positions)) = &mut self.marked {
+            if c == *marked {
+                positions.push(at);
+            }
+        }
+    }
+
+    /// Forget the recorded positions within the closing tag of a text block,
+    /// which was just read.
+    fn unmark_closing_tag(&mut self, closing_tag: &str) {
+        let from = self.index - closing_tag.chars().count();
+        if let Some((_, positions)) = &mut self.marked {
+            while positions.last().map_or(false, |at| at.index >= from) {
+                positions.pop();
+            }
+        }
+    }
+
     fn skip_line(&mut self) {
-        loop {
-            if let Some(c) = self.c {
-                self.next();
-                if c == '\n' {
-                    break;
-                };
-            } else {
+        while let Some(c) = self.c {
+            self.next();
+            if c == '\n' {
                 break;
+            };
+        }
+    }
+
+}
+
+impl <'a> CharIter<'a, Chars<'a>> {
+
+    /// Iterate over the characters of a string, borrowing the text of tokens
+    /// from it.
+    pub fn from_source(source: &'a str) -> Self {
+        Self::with_source(source.chars(), Some(source))
+    }
+
+}
+
+/// Text of a string token.
+///
+/// Borrowed from the source until an escape sequence makes it differ, after
+/// which it is copied.
+struct TokenText<'a> {
+    source: Option<&'a str>,
+    start: usize,
+    string: Option<String>,
+}
+
+impl <'a> TokenText<'a> {
+
+    fn new<It: Iterator<Item = char>>(iter: &CharIter<'a, It>) -> Self {
+        let string = if iter.source.is_some() { None } else { Some(String::new()) };
+        TokenText { source: iter.source, start: iter.consumed(), string }
+    }
+
+    /// Push a character as it appears in the source.
+    fn push(&mut self, c: char) {
+        if let Some(string) = &mut self.string {
+            string.push(c);
+        }
+    }
+
+    /// Push a character translated from an escape sequence starting at byte.
+    fn push_escaped(&mut self, c: char, byte: usize) {
+        self.copy(byte).push(c);
+    }
+
+    /// Copy the text up to byte, if it is still borrowed.
+    fn copy(&mut self, byte: usize) -> &mut String {
+        let (source, start) = (self.source, self.start);
+        self.string.get_or_insert_with(|| source.map(|source| strip_carriage_returns(&source[start..byte])).unwrap_or_default())
+    }
+
+    fn finish(self, byte: usize) -> Cow<'a, str> {
+        match (self.string, self.source) {
+            (Some(string), _) => Cow::Owned(string),
+            (None, Some(source)) => {
+                let str = &source[self.start..byte];
+                // Carriage returns are skipped by the lexer.
+                if str.contains('\r') {
+                    Cow::Owned(strip_carriage_returns(str))
+                } else {
+                    Cow::Borrowed(str)
+                }
             }
+            (None, None) => Cow::Borrowed(""),
         }
     }
 
 }
 
+fn strip_carriage_returns(str: &str) -> String {
+    str.replace('\r', "")
+}
+
 fn is_whitespace(c: char) -> bool {
     c == ' ' || c == '\t' || c == '\n'
 }
@@ -128,137 +255,595 @@ fn is_whitespace(c: char) -> bool {
 //// Lex
 
 /// Iterates over characters and produces tokens.
-pub fn lex<It: Iterator<Item = char>>(chars: It) -> Result<Vec<Token>, LexError> {
-    let mut iter = CharIter::new(chars);
+pub fn lex<It: Iterator<Item = char>>(chars: It) -> Result<Vec<Token<'static>>, LexError> {
+    lex_iter(CharIter::new(chars))
+}
+
+/// Lex a string. The text of tokens is borrowed from the string where possible.
+pub fn lex_str(source: &str) -> Result<Vec<Token<'_>>, LexError> {
+    Lexer::new(source).collect()
+}
+
+/// Lex a string and get the source positions of a character in the text of
+/// its words, transcriptions and text blocks, in order.
+///
+/// A character written as an escape sequence is at the start of the sequence.
+pub fn text_positions(source: &str, c: char) -> Result<Vec<Position>, LexError> {
+    let mut iter = CharIter::from_source(source);
+    iter.marked = Some((c, vec![]));
+    while !matches!(lex_token(&mut iter)?, Token::End(..)) {}
+    Ok(iter.marked.map(|(_, positions)| positions).unwrap_or_default())
+}
+
+fn lex_iter<'a, It: Iterator<Item = char>>(mut iter: CharIter<'a, It>) -> Result<Vec<Token<'a>>, LexError> {
     let mut tokens = vec![];
     loop {
-        if let Some(c) = iter.c {
-            if is_whitespace(c) { // Whitespace
-                let whitespace = lex_whitespace(&mut iter)?;
-                tokens.push(whitespace);
-            } else if c == ':' {
-                if let Some(':') = iter.d { // Colon glyph
-                    let word = lex_word(&mut iter)?;
-                    tokens.push(word);
-                } else { // Colon
-                    tokens.push(Token::Colon(iter.position()));
-                    iter.next();
-                }
-            } else if c == ';' { // Semicolon
-                if let Some(';') = iter.d { // Semicolon glyph
-                    let word = lex_word(&mut iter)?;
-                    tokens.push(word);
-                } else { // Semicolon
-                    tokens.push(Token::Semicolon(iter.position()));
-                    iter.next();
-                }
-            } else if c == '|' {
-                if let Some('|') = iter.d { // Bar glyph
-                    let word = lex_word(&mut iter)?;
-                    tokens.push(word);
-                } else { // Bar
-                    tokens.push(Token::Bar(iter.position()));
-                    iter.next();
-                }
-            } else if c == '~' {
-                if let Some('~') = iter.d { // Tilde glyph
-                    let word = lex_word(&mut iter)?;
-                    tokens.push(word);
-                } else { // Tilde
-                    tokens.push(Token::Tilde(iter.position()));
-                    iter.next();
-                }
-            } else if c == '`' { // Illegal escape character
-                let word = lex_word(&mut iter)?;
-                tokens.push(word);
-            } else if c == '\\' { // Transcription
-                let transcription = lex_transcription(&mut iter)?;
-                tokens.push(transcription);
-            } else if c == '{' { // Left bracket
-                tokens.push(Token::LeftBracket(iter.position()));
+        let token = lex_token(&mut iter)?;
+        let end = matches!(token, Token::End(..));
+        tokens.push(token);
+        if end {
+            break;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Lex the next token.
+///
+/// Returns an end token at the end of the stream.
+pub fn lex_token<'a, It: Iterator<Item = char>>(iter: &mut CharIter<'a, It>) -> Result<Token<'a>, LexError> {
+    let token;
+    if let Some(c) = iter.c {
+        if is_whitespace(c) { // Whitespace
+            token = lex_whitespace(iter)?;
+        } else if c == ':' {
+            if let Some(':') = iter.d { // Colon glyph
+                token = lex_word(iter)?;
+            } else { // Colon
+                token = Token::Colon(iter.position());
                 iter.next();
-            } else if c == '}' { // Right bracket
-                tokens.push(Token::RightBracket(iter.position()));
+            }
+        } else if c == ';' { // Semicolon
+            if let Some(';') = iter.d { // Semicolon glyph
+                token = lex_word(iter)?;
+            } else { // Semicolon
+                token = Token::Semicolon(iter.position());
                 iter.next();
-            } else if c == '[' { // Left square
-                tokens.push(Token::LeftSquare(iter.position()));
+            }
+        } else if c == '|' {
+            if let Some('|') = iter.d { // Bar glyph
+                token = lex_word(iter)?;
+            } else { // Bar
+                token = Token::Bar(iter.position());
                 iter.next();
-            } else if c == ']' { // Right square
-                tokens.push(Token::RightSquare(iter.position()));
+            }
+        } else if c == '~' {
+            if let Some('~') = iter.d { // Tilde glyph
+                token = lex_word(iter)?;
+            } else { // Tilde
+                token = Token::Tilde(iter.position());
                 iter.next();
-            } else if c == '<' {
-                if let Some(d) = iter.d {
-                    if d == '<' { // Left angle glyph
-                        let token = lex_word(&mut iter)?;
-                        tokens.push(token);
-                    } else if d == '#' { // Text block
-                        let text_block = lex_text_block(&mut iter)?;
-                        tokens.push(text_block);
-                    } else { // Left angle
-                        tokens.push(Token::LeftAngle(iter.position()));
-                        iter.next();
-                    };
+            }
+        } else if c == '`' { // Illegal escape character
+            token = lex_word(iter)?;
+        } else if c == '\\' { // Transcription
+            token = lex_transcription(iter)?;
+        } else if c == '{' { // Left bracket
+            token = Token::LeftBracket(iter.position());
+            iter.next();
+        } else if c == '}' { // Right bracket
+            token = Token::RightBracket(iter.position());
+            iter.next();
+        } else if c == '[' { // Left square
+            token = Token::LeftSquare(iter.position());
+            iter.next();
+        } else if c == ']' { // Right square
+            token = Token::RightSquare(iter.position());
+            iter.next();
+        } else if c == '<' {
+            if let Some(d) = iter.d {
+                if d == '<' { // Left angle glyph
+                    token = lex_word(iter)?;
+                } else if d == '#' { // Text block
+                    token = lex_text_block(iter)?;
                 } else { // Left angle
-                    tokens.push(Token::LeftAngle(iter.position()));
+                    token = Token::LeftAngle(iter.position());
                     iter.next();
+                };
+            } else { // Left angle
+                token = Token::LeftAngle(iter.position());
+                iter.next();
+            }
+        } else if c == '>' {
+            if let Some('>') = iter.d { // Right angle glyph
+                token = lex_word(iter)?;
+            } else { // Right angle
+                token = Token::RightAngle(iter.position());
+                iter.next();
+            }
+        } else if c == '#' {
+            if let Some(d) = iter.d {
+                if d == '#' || is_whitespace(d) { // Comment
+                    token = lex_whitespace(iter)?;
+                } else { // Hash glyph: handle illegal cases in word
+                    token = lex_word(iter)?;
                 }
-            } else if c == '>' {
-                if let Some('>') = iter.d { // Right angle glyph
-                    let token = lex_word(&mut iter)?;
-                    tokens.push(token);
-                } else { // Right angle
-                    tokens.push(Token::RightAngle(iter.position()));
-                    iter.next();
+            } else { // Comment before end
+                token = lex_whitespace(iter)?;
+            }
+        } else if c == '=' && iter.d == Some('>') && iter.e != Some('>') {
+            token = Token::DoubleArrow(iter.position());
+            iter.next(); iter.next();
+        } else { // Text glyph
+            token = lex_word(iter)?;
+        }
+    } else {
+        token = Token::End(iter.position());
+    }
+    Ok(token)
+}
+
+//// String lexer
+
+/// Bytes that end a run of plain characters in a word.
+static WORD_SPECIAL: [bool; 256] = special_bytes(b" \t\n\r\\{}[]:;|~<>`#=");
+
+/// Bytes that end a run of plain characters in a transcription.
+static TRANSCRIPTION_SPECIAL: [bool; 256] = special_bytes(b"\n\r\\`");
+
+/// Bytes that end a line.
+static LINE_SPECIAL: [bool; 256] = special_bytes(b"\n\r");
+
+const fn special_bytes(bytes: &[u8]) -> [bool; 256] {
+    let mut table = [false; 256];
+    let mut i = 0;
+    while i < bytes.len() {
+        table[bytes[i] as usize] = true;
+        i += 1;
+    }
+    table
+}
+
+/// Lexes a string in a single pass over its bytes.
+///
+/// Yields tokens up to and including the end token, or up to the first error.
+/// Runs of plain characters are skipped with a table lookup per byte, and the
+/// text of tokens is borrowed from the string unless it contains escape
+/// sequences. Carriage returns are skipped, as by the character lexer.
+pub struct Lexer<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    /// Byte offset of the current character.
+    i: usize,
+    index: usize,
+    line: usize,
+    column: usize,
+    done: bool,
+}
+
+impl <'a> Lexer<'a> {
+
+    pub fn new(source: &'a str) -> Self {
+        Lexer { source, bytes: source.as_bytes(), i: 0, index: 0, line: 1, column: 1, done: false }
+    }
+
+    fn current_position(&self) -> Position {
+        Position { index: self.index, line: self.line, column: self.column }
+    }
+
+    /// The byte n bytes after the current character.
+    ///
+    /// Reserved characters are ASCII, and an ASCII byte is always a whole
+    /// character in UTF-8, so they can be compared as bytes.
+    fn byte(&self, n: usize) -> Option<u8> {
+        self.bytes.get(self.i + n).copied()
+    }
+
+    fn c(&self) -> Option<u8> {
+        self.byte(0)
+    }
+
+    fn d(&self) -> Option<u8> {
+        self.byte(1)
+    }
+
+    fn e(&self) -> Option<u8> {
+        self.byte(2)
+    }
+
+    /// The character starting n bytes after the current character.
+    fn char_at(&self, n: usize) -> Option<char> {
+        self.source.get(self.i + n..)?.chars().next()
+    }
+
+    /// Consume the current character.
+    ///
+    /// Carriage returns are not counted in positions.
+    fn next_char(&mut self) {
+        if let Some(b) = self.c() {
+            if b == b'\r' {
+                self.i += 1;
+                return;
+            }
+            self.index += 1;
+            if b == b'\n' {
+                self.i += 1;
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.i += utf8_len(b);
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Consume a run of characters up to the next special byte. Returns the
+    /// consumed text.
+    ///
+    /// Newlines must be special bytes.
+    fn skip_run(&mut self, special: &[bool; 256]) -> &'a str {
+        let start = self.i;
+        let mut i = start;
+        let mut chars = 0;
+        while let Some(b) = self.bytes.get(i) {
+            if special[*b as usize] {
+                break;
+            }
+            chars += (*b & 0xC0 != 0x80) as usize;
+            i += 1;
+        }
+        self.i = i;
+        self.index += chars;
+        self.column += chars;
+        &self.source[start..i]
+    }
+
+    /// Consume the next len bytes.
+    fn advance(&mut self, len: usize) {
+        for b in &self.bytes[self.i..self.i + len] {
+            if *b == b'\n' {
+                self.line += 1;
+                self.column = 1;
+                self.index += 1;
+            } else if *b & 0xC0 != 0x80 && *b != b'\r' {
+                self.column += 1;
+                self.index += 1;
+            }
+        }
+        self.i += len;
+    }
+
+    fn lex_token(&mut self) -> Result<Token<'a>, LexError> {
+        while self.c() == Some(b'\r') && self.d() != Some(b'\n') { // Carriage return not ending a line
+            self.i += 1;
+        }
+        let at = self.current_position();
+        let c = match self.c() {
+            Some(c) => c,
+            None => return Ok(Token::End(at)),
+        };
+        let d = self.d();
+        let token = match c {
+            b' ' | b'\t' | b'\n' | b'\r' => return self.lex_whitespace(),
+            b':' if d != Some(b':') => Token::Colon(at),
+            b';' if d != Some(b';') => Token::Semicolon(at),
+            b'|' if d != Some(b'|') => Token::Bar(at),
+            b'~' if d != Some(b'~') => Token::Tilde(at),
+            b'\\' => return self.lex_transcription(),
+            b'{' => Token::LeftBracket(at),
+            b'}' => Token::RightBracket(at),
+            b'[' => Token::LeftSquare(at),
+            b']' => Token::RightSquare(at),
+            b'<' if d == Some(b'#') => return self.lex_text_block(),
+            b'<' if d != Some(b'<') => Token::LeftAngle(at),
+            b'>' if d != Some(b'>') => Token::RightAngle(at),
+            b'#' if d.map_or(true, |d| d == b'#' || is_whitespace_byte(d)) => return self.lex_whitespace(),
+            b'=' if d == Some(b'>') && self.e() != Some(b'>') => {
+                self.skip_ascii(2);
+                return Ok(Token::DoubleArrow(at));
+            }
+            _ => return self.lex_word(),
+        };
+        self.skip_ascii(1);
+        Ok(token)
+    }
+
+    fn lex_whitespace(&mut self) -> Result<Token<'a>, LexError> {
+        let at = self.current_position();
+        while let Some(c) = self.c() {
+            if is_whitespace_byte(c) {
+                self.next_char();
+            } else if c == b'#' {
+                match self.d() {
+                    Some(d) if is_whitespace_byte(d) || d == b'#' => { // Comment
+                        loop {
+                            self.skip_run(&LINE_SPECIAL);
+                            if self.c() != Some(b'\r') {
+                                break;
+                            }
+                            self.next_char();
+                        }
+                        self.next_char();
+                    }
+                    Some(..) => break,
+                    None => {
+                        self.next_char();
+                        break;
+                    }
                 }
-            } else if c == '#' {
-                if let Some(d) = iter.d {
-                    if d == '#' || is_whitespace(d) { // Comment
-                        let whitespace = lex_whitespace(&mut iter)?;
-                        tokens.push(whitespace);
-                    } else { // Hash glyph: handle illegal cases in word
-                        let word = lex_word(&mut iter)?;
-                        tokens.push(word);
+            } else {
+                break;
+            }
+        }
+        Ok(Token::Whitespace(at))
+    }
+
+    fn lex_word(&mut self) -> Result<Token<'a>, LexError> {
+        let at = self.current_position();
+        let start = self.i;
+        let mut string: Option<String> = None;
+        loop {
+            let run = self.skip_run(&WORD_SPECIAL);
+            if let Some(string) = &mut string {
+                string.push_str(run);
+            }
+            let c = match self.c() {
+                Some(c) => c,
+                None => break,
+            };
+            match c {
+                b'\r' if self.d() != Some(b'\n') => { // Carriage return not ending a line
+                    copy(&mut string, &self.source[start..self.i]);
+                    self.i += 1;
+                }
+                b' ' | b'\t' | b'\n' | b'\r' | b'\\' | b'{' | b'}' | b'[' | b']' => break,
+                b':' | b';' | b'|' | b'~' | b'<' | b'>' => {
+                    if self.d() != Some(c) {
+                        break;
+                    }
+                    copy(&mut string, &self.source[start..self.i]).push(c as char); // Repeated escape sequence
+                    self.skip_ascii(2);
+                }
+                b'`' => {
+                    let (x, len) = self.lex_escape_sequence()?;
+                    copy(&mut string, &self.source[start..self.i]).push(x);
+                    self.skip_ascii(len);
+                }
+                b'#' => match self.d() {
+                    Some(d) if d == b'#' || is_whitespace_byte(d) => break, // Comment
+                    Some(b'\\' | b'{' | b'}' | b'[' | b']') => return Err(LexError::InvalidHashSequence(self.current_position())),
+                    Some(d @ (b':' | b';' | b'|' | b'~' | b'<' | b'>')) if self.e() != Some(d) => return Err(LexError::InvalidHashSequence(self.current_position())),
+                    Some(..) => {
+                        if let Some(string) = &mut string {
+                            string.push('#');
+                        }
+                        self.skip_ascii(1);
+                    }
+                    None => break,
+                },
+                b'=' if self.d() == Some(b'>') && self.e() != Some(b'>') => break,
+                _ => { // Glyph ending a run
+                    if let Some(string) = &mut string {
+                        string.push(c as char);
                     }
-                } else { // Comment before end
-                    let whitespace = lex_whitespace(&mut iter)?;
-                    tokens.push(whitespace);
+                    self.skip_ascii(1);
                 }
-            } else if c == '=' && iter.d == Some('>') && iter.e != Some('>') {
-                tokens.push(Token::DoubleArrow(iter.position()));
-                iter.next(); iter.next();
-            } else { // Text glyph
-                let word = lex_word(&mut iter)?;
-                tokens.push(word);
             }
-        } else {
-            tokens.push(Token::End(iter.position()));
-            break;
         }
+        let text = match string {
+            Some(string) => Cow::Owned(string),
+            None => Cow::Borrowed(&self.source[start..self.i]),
+        };
+        Ok(Token::Word(at, text))
     }
-    Ok(tokens)
+
+    fn lex_transcription(&mut self) -> Result<Token<'a>, LexError> {
+        let at = self.current_position();
+        self.next_char();
+        let start = self.i;
+        let mut string: Option<String> = None;
+        let end = loop {
+            let run = self.skip_run(&TRANSCRIPTION_SPECIAL);
+            if let Some(string) = &mut string {
+                string.push_str(run);
+            }
+            match self.c() {
+                Some(b'\\' | b'\n') => {
+                    let end = self.i;
+                    self.next_char();
+                    break end;
+                }
+                Some(b'\r') if self.d() == Some(b'\n') => {
+                    let end = self.i;
+                    self.next_char();
+                    self.next_char();
+                    break end;
+                }
+                Some(b'\r') => {
+                    copy(&mut string, &self.source[start..self.i]);
+                    self.i += 1;
+                }
+                Some(..) => {
+                    let (x, len) = self.lex_escape_sequence()?;
+                    copy(&mut string, &self.source[start..self.i]).push(x);
+                    self.skip_ascii(len);
+                }
+                None => break self.i,
+            }
+        };
+        let text = match string {
+            Some(string) => Cow::Owned(string),
+            None => Cow::Borrowed(&self.source[start..end]),
+        };
+        Ok(Token::Transcription(at, text))
+    }
+
+    fn lex_text_block(&mut self) -> Result<Token<'a>, LexError> {
+        let at = self.current_position();
+        let mut closing_tag = String::from("<#");
+        let mut configuration = vec![Flag::Footer, Flag::Header, Flag::Excess];
+        self.skip_ascii(2);
+        'tag: loop {
+            match self.c() {
+                Some(b'>') => {
+                    self.next_char();
+                    closing_tag.push('>');
+                    break;
+                }
+                Some(c) if is_whitespace_byte(c) => {
+                    closing_tag.push('>');
+                    self.skip_whitespace_in_tag(at)?;
+                    loop { // Read configuration
+                        let flag = match self.c() {
+                            Some(b'f') => Flag::Footer,
+                            Some(b'h') => Flag::Header,
+                            Some(b'x') => Flag::Excess,
+                            Some(b't') => Flag::Trailing,
+                            Some(b'l') => Flag::Leading,
+                            Some(b'n') => Flag::Newline,
+                            Some(b'r') => {
+                                self.next_char();
+                                configuration.clear();
+                                continue;
+                            }
+                            Some(b'>') => {
+                                self.next_char();
+                                break 'tag;
+                            }
+                            Some(c) if is_whitespace_byte(c) => break,
+                            Some(..) => return Err(LexError::InvalidTextBlockConfiguration(self.current_position())),
+                            None => return Err(LexError::UnclosedTextBlock(self.current_position())),
+                        };
+                        self.next_char();
+                        configuration.push(flag);
+                    }
+                    self.skip_whitespace_in_tag(at)?;
+                    if self.c() == Some(b'>') {
+                        self.next_char();
+                        break 'tag;
+                    }
+                    return Err(LexError::UnclosedTextBlock(self.current_position()));
+                }
+                Some(..) => {
+                    closing_tag.extend(self.char_at(0));
+                    self.next_char();
+                }
+                None => return Err(LexError::UnclosedTextBlock(self.current_position())),
+            }
+        }
+        let rest = &self.source[self.i..];
+        let len = match rest.find(closing_tag.as_str()) {
+            Some(len) => len,
+            None => {
+                self.advance(rest.len());
+                return Err(LexError::UnclosedTextBlock(self.current_position()));
+            }
+        };
+        self.advance(len + closing_tag.len());
+        let content = &rest[..len];
+        let content = if content.contains('\r') { Cow::Owned(content.replace('\r', "")) } else { Cow::Borrowed(content) };
+        if configuration.is_empty() {
+            return Ok(Token::TextBlock(at, content));
+        }
+        Ok(Token::TextBlock(at, Cow::Owned(configure_text_block(content.into_owned(), configuration))))
+    }
+
+    fn skip_whitespace_in_tag(&mut self, at: Position) -> Result<(), LexError> {
+        loop {
+            self.next_char();
+            match self.c() {
+                Some(c) if !is_whitespace_byte(c) => return Ok(()),
+                Some(..) => {}
+                None => return Err(LexError::UnclosedTextBlock(at)),
+            }
+        }
+    }
+
+    /// Consume n ASCII characters that are not newlines.
+    fn skip_ascii(&mut self, n: usize) {
+        self.i += n;
+        self.index += n;
+        self.column += n;
+    }
+
+    /// Translate the escape sequence at the current character. Returns the
+    /// character and the length of the sequence, which is ASCII.
+    fn lex_escape_sequence(&self) -> Result<(char, usize), LexError> {
+        let invalid = LexError::InvalidEscapeSequence(self.current_position());
+        match self.d() {
+            None => Err(LexError::EscapeEos),
+            Some(b'u') => {
+                if self.e() != Some(b'{') {
+                    return Err(invalid);
+                }
+                let start = self.i + 3;
+                let len = match self.bytes[start..].iter().take(7).position(|b| *b == b'}') {
+                    Some(len) => len,
+                    None => return Err(invalid),
+                };
+                let x = translate_code_point(&self.source[start..start + len]).ok_or(invalid)?;
+                Ok((x, len + 4))
+            }
+            Some(d) if d.is_ascii() => {
+                let x = translate_escape_character(d as char).map_err(|_| invalid)?;
+                Ok((x, 2))
+            }
+            Some(..) => Err(invalid),
+        }
+    }
+
+}
+
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0x00..=0x7F => 1,
+        0x80..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    }
+}
+
+fn is_whitespace_byte(b: u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\n' || b == b'\r'
+}
+
+/// Copy the text of a token up to an escape sequence, if it is not yet copied.
+fn copy<'s>(string: &'s mut Option<String>, text: &str) -> &'s mut String {
+    string.get_or_insert_with(|| text.to_string())
+}
+
+impl <'a> Iterator for Lexer<'a> {
+
+    type Item = Result<Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let token = self.lex_token();
+        self.done = matches!(token, Ok(Token::End(..)) | Err(..));
+        Some(token)
+    }
+
 }
 
 /// Lex whitespace, including comments
 ///
 /// Assumes that the current character is whitespace or a hash opening a comment.
-fn lex_whitespace<It: Iterator<Item = char>>(iter: &mut CharIter<It>) -> Result<Token, LexError> {
+fn lex_whitespace<'a, It: Iterator<Item = char>>(iter: &mut CharIter<'a, It>) -> Result<Token<'a>, LexError> {
     let at = iter.position();
-    loop {
-        if let Some(c) = iter.c {
-            if is_whitespace(c) {
-                iter.next();
-            } else if c == '#' {
-                if let Some(d) = iter.d {
-                    if is_whitespace(d) || d == '#' {
-                        iter.skip_line();
-                    } else {
-                        break;
-                    };
-                } else { // EOS
-                    iter.next();
+    while let Some(c) = iter.c {
+        if is_whitespace(c) {
+            iter.next();
+        } else if c == '#' {
+            if let Some(d) = iter.d {
+                if is_whitespace(d) || d == '#' {
+                    iter.skip_line();
+                } else {
                     break;
                 };
-            } else {
+            } else { // EOS
+                iter.next();
                 break;
             };
         } else {
@@ -271,108 +856,129 @@ fn lex_whitespace<It: Iterator<Item = char>>(iter: &mut CharIter<It>) -> Result<
 /// Lex a word.
 ///
 /// Assumes that the current character is a glyph.
-fn lex_word<It: Iterator<Item = char>>(iter: &mut CharIter<It>) -> Result<Token, LexError> {
+fn lex_word<'a, It: Iterator<Item = char>>(iter: &mut CharIter<'a, It>) -> Result<Token<'a>, LexError> {
     let at = iter.position();
-    let mut string = String::new();
-    loop {
-        if let Some(c) = iter.c {
-            if is_whitespace(c) { // Whitespace
-                break;
-            } else if c == '\\' || c == '{' || c == '}' || c == '[' || c == ']' { // Reserved
-                break;
-            } else if c == ':' || c == ';' || c == '|' || c == '~' || c == '<' || c == '>' {
-                if let Some(d) = iter.d {
-                    if d == c { // Repeated escape sequence
-                        iter.next(); iter.next();
-                        string.push(c);
-                    } else { // Reserved
-                        break;
-                    }
+    let mut string = TokenText::new(iter);
+    while let Some(c) = iter.c {
+        if is_whitespace(c) { // Whitespace
+            break;
+        } else if c == '\\' || c == '{' || c == '}' || c == '[' || c == ']' { // Reserved
+            break;
+        } else if c == ':' || c == ';' || c == '|' || c == '~' || c == '<' || c == '>' {
+            if let Some(d) = iter.d {
+                if d == c { // Repeated escape sequence
+                    iter.mark(c, iter.position());
+                    string.push_escaped(c, iter.byte());
+                    iter.next(); iter.next();
                 } else { // Reserved
                     break;
                 }
-            } else if c == '`' { // Character escape character
-                if let Some(d) = iter.d { // Escape sequence
-                    let x = match translate_escape_character(d) {
-                        Ok(x) => x,
-                        Err(..) => return Err(LexError::InvalidEscapeSequence(iter.position())),
-                    };
-                    iter.next_two();
-                    string.push(x);
-                } else {
-                    return Err(LexError::EscapeEos);
-                }
-            } else if c == '#' {
-                if let Some(d) = iter.d {
-                    if d == '#' || is_whitespace(d) { // Comment
-                        break;
-                    } else if d == '\\' || d == '{' || d == '}' || d == '[' || d == ']' { // Following reserved character
-                        return Err(LexError::InvalidHashSequence(iter.position()));
-                    } else if d == ':' || d == ';' || d == '|' || d == '~' || d == '<' || d == '>' {
-                        if iter.e == Some(d) { // Following repeated escape sequence
-                            iter.next();
-                            string.push('#');
-                        } else { // Following reserved character
-                            return Err(LexError::InvalidHashSequence(iter.position()));
-                        }
-                    } else { // # Hash glyph
+            } else { // Reserved
+                break;
+            }
+        } else if c == '`' { // Character escape character
+            let (byte, at) = (iter.byte(), iter.position());
+            let x = lex_escape_sequence(iter)?;
+            iter.mark(x, at);
+            string.push_escaped(x, byte);
+        } else if c == '#' {
+            if let Some(d) = iter.d {
+                if d == '#' || is_whitespace(d) { // Comment
+                    break;
+                } else if d == '\\' || d == '{' || d == '}' || d == '[' || d == ']' { // Following reserved character
+                    return Err(LexError::InvalidHashSequence(iter.position()));
+                } else if d == ':' || d == ';' || d == '|' || d == '~' || d == '<' || d == '>' {
+                    if iter.e == Some(d) { // Following repeated escape sequence
+                        iter.mark('#', iter.position());
                         iter.next();
                         string.push('#');
+                    } else { // Following reserved character
+                        return Err(LexError::InvalidHashSequence(iter.position()));
                     }
-                } else {
-                    break;
-                };
-            } else if c == '=' && iter.d == Some('>') && iter.e != Some('>') { // =>
+                } else { // # Hash glyph
+                    iter.mark('#', iter.position());
+                    iter.next();
+                    string.push('#');
+                }
+            } else {
                 break;
-            } else { // Glyph
-                iter.next();
-                string.push(c);
             };
-        } else { // End
+        } else if c == '=' && iter.d == Some('>') && iter.e != Some('>') { // =>
             break;
+        } else { // Glyph
+            iter.mark(c, iter.position());
+            iter.next();
+            string.push(c);
         };
     };
-    Ok(Token::Word(at, string))
+    Ok(Token::Word(at, string.finish(iter.consumed())))
 }
 
 /// Lex a transcription.
 ///
 /// Assumes that the current character is `\ `.
-fn lex_transcription<It: Iterator<Item = char>>(iter: &mut CharIter<It>) -> Result<Token, LexError> {
+fn lex_transcription<'a, It: Iterator<Item = char>>(iter: &mut CharIter<'a, It>) -> Result<Token<'a>, LexError> {
     let at = iter.position();
-    let mut string = String::new();
     iter.next();
+    let mut string = TokenText::new(iter);
+    let end;
     loop {
         if let Some(c) = iter.c {
             if c == '\\' || c == '\n' {
+                end = iter.consumed();
                 iter.next();
                 break;
             } else if c == '`' {
-                if let Some(d) = iter.d {
-                    let e = match translate_escape_character(d) {
-                        Ok(e) => e,
-                        Err(..) => return Err(LexError::InvalidEscapeSequence(iter.position())),
-                    };
-                    iter.next_two();
-                    string.push(e);
-                } else {
-                    return Err(LexError::EscapeEos);
-                };
+                let (byte, at) = (iter.byte(), iter.position());
+                let e = lex_escape_sequence(iter)?;
+                iter.mark(e, at);
+                string.push_escaped(e, byte);
             } else {
+                iter.mark(c, iter.position());
                 iter.next();
                 string.push(c);
             };
         } else {
+            end = iter.consumed();
             break;
         };
     };
-    Ok(Token::Transcription(at, string))
+    Ok(Token::Transcription(at, string.finish(end)))
+}
+
+/// Lex an escape sequence.
+///
+/// Assumes that the current character is `` ` ``.
+fn lex_escape_sequence<It: Iterator<Item = char>>(iter: &mut CharIter<'_, It>) -> Result<char, LexError> {
+    let at = iter.position();
+    let d = iter.d.ok_or(LexError::EscapeEos)?;
+    if d != 'u' {
+        let x = translate_escape_character(d).map_err(|_| LexError::InvalidEscapeSequence(at))?;
+        iter.next_two();
+        return Ok(x);
+    }
+    if iter.e != Some('{') {
+        return Err(LexError::InvalidEscapeSequence(at));
+    }
+    iter.next_two();
+    iter.next();
+    let mut hex = String::new();
+    loop {
+        match iter.c {
+            Some('}') => break,
+            Some(c) if hex.len() < 6 => hex.push(c),
+            _ => return Err(LexError::InvalidEscapeSequence(at)),
+        }
+        iter.next();
+    }
+    iter.next();
+    translate_code_point(&hex).ok_or(LexError::InvalidEscapeSequence(at))
 }
 
 /// Lex a text block.
 ///
 /// Assumes that the current characters are `<#`.
-fn lex_text_block<It: Iterator<Item = char>>(iter: &mut CharIter<It>) -> Result<Token, LexError> {
+fn lex_text_block<'a, It: Iterator<Item = char>>(iter: &mut CharIter<'a, It>) -> Result<Token<'a>, LexError> {
     let at = iter.position();
     let mut closing_tag = String::new();
     let mut configuration = vec![Flag::Footer, Flag::Header, Flag::Excess];
@@ -434,34 +1040,49 @@ fn lex_text_block<It: Iterator<Item = char>>(iter: &mut CharIter<It>) -> Result<
             return Err(LexError::UnclosedTextBlock(iter.position()));
         }
     }
-    loop { // Read content.
-        if let Some(c) = iter.c {
-            content.push(c);
-            iter.next();
-            if content.ends_with(closing_tag.deref()) {
-                content = content.replace(closing_tag.deref(), "");
-                break;
+    if let Some(source) = iter.source {
+        let start = iter.byte();
+        loop { // Read content.
+            if let Some(c) = iter.c {
+                iter.mark(c, iter.position());
+                iter.next();
+                let read = &source[start..iter.byte()];
+                if read.ends_with(closing_tag.deref()) {
+                    iter.unmark_closing_tag(&closing_tag);
+                    let str = &read[..read.len() - closing_tag.len()];
+                    // Raw content is borrowed.
+                    if configuration.is_empty() && !str.contains('\r') {
+                        return Ok(Token::TextBlock(at, Cow::Borrowed(str)));
+                    }
+                    content = strip_carriage_returns(str);
+                    break;
+                }
+            } else {
+                return Err(LexError::UnclosedTextBlock(iter.position()));
             }
-        } else {
-            return Err(LexError::UnclosedTextBlock(iter.position()));
         }
-    }
-    for flag in configuration {
-        content = match flag {
-            Flag::Footer => delete_blank_footer(content),
-            Flag::Header => delete_blank_header(content),
-            Flag::Excess => delete_excess_indentation(content),
-            Flag::Trailing => delete_trailing_whitespace(content),
-            Flag::Leading => delete_leading_whitespace(content),
-            Flag::Newline => delete_newlines(content),
+    } else {
+        loop { // Read content.
+            if let Some(c) = iter.c {
+                iter.mark(c, iter.position());
+                content.push(c);
+                iter.next();
+                if content.ends_with(closing_tag.deref()) { // Stop at the first closing tag.
+                    iter.unmark_closing_tag(&closing_tag);
+                    content.truncate(content.len() - closing_tag.len());
+                    break;
+                }
+            } else {
+                return Err(LexError::UnclosedTextBlock(iter.position()));
+            }
         }
     }
-    Ok(Token::TextBlock(at, content))
+    Ok(Token::TextBlock(at, Cow::Owned(configure_text_block(content, configuration))))
 }
 
 enum Flag { Footer, Header, Excess, Trailing, Leading, Newline }
 
-fn skip_whitespace_in_text_block_tag<It: Iterator<Item = char>>(iter: &mut CharIter<It>, at: Position) -> Result<(), LexError> {
+fn skip_whitespace_in_text_block_tag<It: Iterator<Item = char>>(iter: &mut CharIter<'_, It>, at: Position) -> Result<(), LexError> {
     loop { // Skip whitespace
         iter.next();
         if let Some(c) = iter.c {
@@ -489,34 +1110,54 @@ pub enum LexError {
 
 //// Strings
 
-fn delete_blank_footer(string: String) -> String {
-    let mut string = string.into_bytes();
+/// Apply a text block configuration to its content.
+///
+/// The flags edit the content in place as bytes. Each of them only ever
+/// removes ASCII spaces, tabs and newlines, which are never part of a
+/// multibyte sequence, so the content stays valid UTF-8.
+fn configure_text_block(content: String, configuration: Vec<Flag>) -> String {
+    let mut content = content.into_bytes();
+    for flag in configuration {
+        match flag {
+            Flag::Footer => delete_blank_footer(&mut content),
+            Flag::Header => delete_blank_header(&mut content),
+            Flag::Excess => delete_excess_indentation(&mut content),
+            Flag::Trailing => delete_trailing_whitespace(&mut content),
+            Flag::Leading => delete_leading_whitespace(&mut content),
+            Flag::Newline => delete_newlines(&mut content),
+        }
+    }
+    debug_assert!(std::str::from_utf8(&content).is_ok());
+    String::from_utf8(content).expect("Only ASCII bytes are removed from a text block.")
+}
+
+/// Delete trailing spaces and tabs after the last newline.
+fn delete_blank_footer(string: &mut Vec<u8>) {
     let mut i = string.len();
     while i > 0 {
         i -= 1; let c = string[i];
-        if c == ' ' as u8 || c == '\t' as u8 {
+        if c == b' ' || c == b'\t' {
             continue;
-        } else if c == '\n' as u8 {
+        } else if c == b'\n' {
             string.truncate(i + 1);
             break;
         } else {
             break;
         }
     }
-    String::from_utf8(string).unwrap()
 }
 
-fn delete_blank_header(string: String) -> String {
-    let mut string = string.into_bytes();
+/// Delete the first line if it only has spaces and tabs, including its newline.
+fn delete_blank_header(string: &mut Vec<u8>) {
     let len = string.len();
     let mut i = 0;
     let mut j = 0;
     while i < len {
         let c = string[i]; i += 1;
-        if c == '\n' as u8 {
+        if c == b'\n' {
             j = 0;
             break;
-        } else if c == ' ' as u8 || c == '\t' as u8 {
+        } else if c == b' ' || c == b'\t' {
             string[j] = c; j += 1;
         } else {
             string[j] = c; j += 1;
@@ -528,16 +1169,15 @@ fn delete_blank_header(string: String) -> String {
         string[j] = c; j += 1;
     }
     string.truncate(j);
-    String::from_utf8(string).unwrap()
 }
 
-fn delete_excess_indentation(string: String) -> String {
-    let mut string = string.into_bytes();
+/// Delete the leading spaces and tabs that all lines before the last newline share.
+fn delete_excess_indentation(string: &mut Vec<u8>) {
     let len = string.len();
     let mut r = len;
     while r > 0 {
         r -= 1; let c = string[r];
-        if c == '\n' as u8 {
+        if c == b'\n' {
             break;
         }
     }
@@ -545,11 +1185,11 @@ fn delete_excess_indentation(string: String) -> String {
     let mut i = 0;
     while i < r {
         let c = string[i];
-        if c == ' ' as u8 || c == '\t' as u8 {
+        if c == b' ' || c == b'\t' {
             indentation.push(c);
             i += 1;
         } else {
-            skip_to_next_line(&string, r, &mut i);
+            skip_to_next_line(string, r, &mut i);
             break;
         }
     }
@@ -566,7 +1206,7 @@ fn delete_excess_indentation(string: String) -> String {
                 break;
             }
         }
-        skip_to_next_line(&string, r, &mut i);
+        skip_to_next_line(string, r, &mut i);
     }
     let excess = indentation.len();
     if excess > 0 {
@@ -574,7 +1214,7 @@ fn delete_excess_indentation(string: String) -> String {
         let mut j = 0;
         while i < r {
             i += excess;
-            copy_line(&mut string, len, &mut i, &mut j);
+            copy_line(string, len, &mut i, &mut j);
         }
         while i < len {
             let c = string[i]; i += 1;
@@ -582,84 +1222,80 @@ fn delete_excess_indentation(string: String) -> String {
         }
         string.truncate(j);
     }
-    String::from_utf8(string).unwrap()
 }
 
-fn skip_to_next_line(string: &Vec<u8>, len: usize, i: &mut usize) {
+fn skip_to_next_line(string: &[u8], len: usize, i: &mut usize) {
     while *i < len {
         let c = string[*i]; *i += 1;
-        if c == '\n' as u8 {
+        if c == b'\n' {
             break;
         }
     }
 }
 
-fn delete_trailing_whitespace(string: String) -> String {
+/// Delete the spaces and tabs before each newline and at the end.
+fn delete_trailing_whitespace(string: &mut Vec<u8>) {
     fn backtrack(string: &[u8], j: &mut usize) {
         while *j > 0 {
             let d = string[*j - 1];
-            if d == ' ' as u8 || d == '\t' as u8 {
+            if d == b' ' || d == b'\t' {
                 *j -= 1;
             } else {
                 break;
             }
         }
     }
-    let mut string = string.into_bytes();
     let len = string.len();
     let mut i = 0;
     let mut j = 0;
     while i < len {
         let c = string[i]; i += 1;
-        if c == '\n' as u8 {
-            backtrack(&string, &mut j);
-            string[j] = '\n' as u8; j += 1;
+        if c == b'\n' {
+            backtrack(string, &mut j);
+            string[j] = b'\n'; j += 1;
         } else {
             string[j] = c; j += 1;
         }
     }
-    backtrack(&string, &mut j);
+    backtrack(string, &mut j);
     string.truncate(j);
-    String::from_utf8(string).unwrap()
 }
 
-fn delete_leading_whitespace(string: String) -> String {
-    let mut string = string.into_bytes();
+/// Delete the spaces and tabs at the start of each line.
+fn delete_leading_whitespace(string: &mut Vec<u8>) {
     let len = string.len();
     let mut i = 0;
     let mut j = 0;
     while i < len {
         let c = string[i];
-        if c != ' ' as u8 && c != '\t' as u8 {
-            copy_line(&mut string, len, &mut i, &mut j);
+        if c != b' ' && c != b'\t' {
+            copy_line(string, len, &mut i, &mut j);
         } else {
             i += 1;
         }
     }
     string.truncate(j);
-    String::from_utf8(string).unwrap()
 }
 
-fn delete_newlines(string: String) -> String {
-    let mut string = string.into_bytes();
+/// Delete every newline.
+fn delete_newlines(string: &mut Vec<u8>) {
     let len = string.len();
     let mut i = 0;
     let mut j = 0;
     while i < len {
         let c = string[i]; i += 1;
-        if c != '\n' as u8 {
+        if c != b'\n' {
             string[j] = c; j += 1;
         }
     }
     string.truncate(j);
-    String::from_utf8(string).unwrap()
 }
 
 fn copy_line(string: &mut [u8], len: usize, i: &mut usize, j: &mut usize) {
     while *i < len {
         let d = string[*i]; *i += 1;
         string[*j] = d; *j += 1;
-        if d == '\n' as u8 {
+        if d == b'\n' {
             break;
         }
     }
@@ -1,3 +1,5 @@
+#![allow(clippy::four_forward_slashes)]
+
 extern crate core;
 
 #[cfg(feature = "parse")]
@@ -14,14 +16,42 @@ pub mod html;
 #[cfg(feature = "tex")]
 pub mod tex;
 pub mod pdm;
+pub mod format;
+pub mod diff;
+pub mod patch;
+pub mod merge;
+pub mod reference;
+#[cfg(feature = "parse")]
+pub mod interpolate;
+pub mod macros;
+pub mod condition;
+#[cfg(feature = "parse")]
+pub mod config;
+#[cfg(feature = "parse")]
+pub mod reload;
+#[cfg(feature = "parse")]
+pub mod include;
+#[cfg(feature = "parse")]
+pub mod stream;
+#[cfg(feature = "parse")]
+pub mod event;
+#[cfg(feature = "parse")]
+pub mod borrowed;
+#[cfg(feature = "arena")]
+pub mod arena;
+#[cfg(feature = "parse")]
+pub mod log;
 //#[cfg(feature = "serde")]
 //pub mod ser;
 //#[cfg(feature = "serde")]
 //pub mod de;
 
 //mod fmt;
+pub mod emit;
 //mod model;
 
+pub use diff::diff;
+
 /// A value.
 ///
 /// Corresponds to something that can be an element of a tuple, such as a real data
@@ -249,6 +279,17 @@ pub fn translate_escape_character(char: char) -> Result<char, ()> {
         '#' => Ok('#'),
         'n' => Ok('\n'),
         't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
         _ => Err(()),
     }
 }
+
+/// Get the character with a code point given by 1 to 6 hexadecimal digits, as
+/// in the escape sequence `` `u{1F600} ``.
+pub fn translate_code_point(hex: &str) -> Option<char> {
+    if hex.is_empty() || hex.len() > 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
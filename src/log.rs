@@ -0,0 +1,295 @@
+//! Append-only logs.
+//!
+//! A log is a list document in aligned notation with one entry per line, as in
+//! `examples/server-log.khi`:
+//!
+//! ```text
+//! > 2023-Nov-11 | 18.56.12 | <Start>
+//! > 2023-Nov-13 | 03.00.01 | <Restart>
+//! ```
+//!
+//! A [LogWriter] appends entries to a log. Each entry is written with a single
+//! write to a file opened in append mode, so entries of concurrent writers are
+//! not interleaved. A [LogReader] tails a log and yields entries as they are
+//! appended.
+
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::format::format_value;
+use crate::parse::parse_list_str;
+use crate::parse::parser::{error_to_string, ParseError};
+use crate::pdm::{ParsedValue, Position};
+
+/// Number of bytes at the end of a log that are verified when it is opened.
+const TAIL_LENGTH: u64 = 64 * 1024;
+
+/// When appended entries are synced to disk.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum SyncMode {
+    /// Leave syncing to the operating system.
+    Never,
+    /// Sync the data of the file after each entry.
+    Data,
+    /// Sync the data and metadata of the file after each entry.
+    All,
+}
+
+/// Options of a log writer.
+#[derive(Copy, Clone)]
+pub struct LogOptions {
+    pub sync: SyncMode,
+    /// Check that the entries at the end of an existing log parse.
+    pub verify_tail: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions { sync: SyncMode::Never, verify_tail: false }
+    }
+}
+
+//// Writer
+
+/// Appends entries to a log.
+pub struct LogWriter {
+    path: PathBuf,
+    file: File,
+    sync: SyncMode,
+    /// Whether the log does not end with a newline.
+    needs_newline: bool,
+}
+
+impl LogWriter {
+
+    /// Open a log for appending, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P, options: LogOptions) -> Result<LogWriter, LogError> {
+        let path = path.as_ref().to_path_buf();
+        let io_error = |error| LogError::IoError(path.clone(), error);
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path).map_err(io_error)?;
+        let len = file.metadata().map_err(io_error)?.len();
+        let start = if options.verify_tail { len.saturating_sub(TAIL_LENGTH) } else { len.saturating_sub(1) };
+        let mut tail = vec![];
+        file.seek(SeekFrom::Start(start)).map_err(io_error)?;
+        file.read_to_end(&mut tail).map_err(io_error)?;
+        if options.verify_tail {
+            verify_tail(&tail, start == 0)?;
+        }
+        let needs_newline = !tail.is_empty() && !tail.ends_with(b"\n");
+        Ok(LogWriter { path, file, sync: options.sync, needs_newline })
+    }
+
+    /// Append an entry. The values of the entry are written as a tuple.
+    pub fn append(&mut self, entry: &[ParsedValue]) -> Result<(), LogError> {
+        const P: Position = Position { index: 0, line: 0, column: 0 };
+        let value = ParsedValue::from_tuple(entry.to_vec(), P, P);
+        let mut line = String::new();
+        if self.needs_newline {
+            line.push('\n');
+        }
+        line.push_str("> ");
+        line.push_str(&format_value(&value));
+        line.push('\n');
+        let io_error = |error| LogError::IoError(self.path.clone(), error);
+        self.file.write_all(line.as_bytes()).map_err(io_error)?;
+        self.needs_newline = false;
+        match self.sync {
+            SyncMode::Never => {}
+            SyncMode::Data => self.file.sync_data().map_err(io_error)?,
+            SyncMode::All => self.file.sync_all().map_err(io_error)?,
+        }
+        Ok(())
+    }
+
+    /// Sync all appended entries to disk.
+    pub fn sync(&mut self) -> Result<(), LogError> {
+        self.file.sync_all().map_err(|error| LogError::IoError(self.path.clone(), error))
+    }
+
+}
+
+/// Check that the entries of the tail of a log parse.
+///
+/// Unless the tail is the whole log, it is parsed from the first entry that
+/// starts in it.
+fn verify_tail(tail: &[u8], whole: bool) -> Result<(), LogError> {
+    let tail = String::from_utf8_lossy(tail);
+    let entries = if whole {
+        &tail[..]
+    } else {
+        match tail.find("\n>") {
+            Some(i) => &tail[i + 1..],
+            None => return Ok(()),
+        }
+    };
+    match parse_list_str(entries) {
+        Ok(..) => Ok(()),
+        Err(errors) => Err(LogError::CorruptTail(errors)),
+    }
+}
+
+//// Reader
+
+/// Reads the entries of a log as they are appended.
+///
+/// Positions of entries are relative to the lines of the entry.
+pub struct LogReader {
+    path: PathBuf,
+    /// Number of bytes read.
+    offset: u64,
+    /// Bytes read but not yet parsed.
+    pending: Vec<u8>,
+}
+
+impl LogReader {
+
+    /// Read a log from the start.
+    pub fn open<P: AsRef<Path>>(path: P) -> LogReader {
+        LogReader { path: path.as_ref().to_path_buf(), offset: 0, pending: vec![] }
+    }
+
+    /// Read the entries appended to a log after now.
+    pub fn from_end<P: AsRef<Path>>(path: P) -> Result<LogReader, LogError> {
+        let path = path.as_ref().to_path_buf();
+        let offset = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(LogError::IoError(path, error)),
+        };
+        Ok(LogReader { path, offset, pending: vec![] })
+    }
+
+    /// Read the entries appended since the last poll.
+    ///
+    /// An entry is returned once its line is complete. If the log is
+    /// truncated, it is read again from the start. An entry that does not
+    /// parse is reported and skipped: the entries before it are returned, and
+    /// the next poll reports it.
+    pub fn poll(&mut self) -> Result<Vec<ParsedValue>, LogError> {
+        let io_error = |error| LogError::IoError(self.path.clone(), error);
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(io_error(error)),
+        };
+        let len = file.metadata().map_err(io_error)?.len();
+        if len < self.offset {
+            self.offset = 0;
+            self.pending.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset)).map_err(io_error)?;
+        let read = file.read_to_end(&mut self.pending).map_err(io_error)?;
+        self.offset += read as u64;
+        let end = match self.pending.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None => return Ok(vec![]),
+        };
+        let text = match std::str::from_utf8(&self.pending[..end]) {
+            Ok(text) => text,
+            Err(error) => {
+                self.pending.drain(..end);
+                return Err(io_error(io::Error::new(io::ErrorKind::InvalidData, error)));
+            }
+        };
+        // Parse each entry on its own, so that a bad entry does not hide the
+        // entries around it. Lines before the first entry go with it.
+        let mut starts: Vec<usize> = text.match_indices("\n>").map(|(i, _)| i + 1).collect();
+        starts.insert(0, 0);
+        let mut entries = vec![];
+        let mut error = None;
+        let mut consumed = 0;
+        for (k, start) in starts.iter().enumerate() {
+            let end = starts.get(k + 1).copied().unwrap_or(text.len());
+            match parse_list_str(&text[*start..end]) {
+                Ok(list) => {
+                    entries.extend(list.elements);
+                    consumed = end;
+                }
+                // The last entry may continue on a line not yet written.
+                Err(..) if end == text.len() && text[*start..].trim_start().starts_with('>') => break,
+                Err(errors) if entries.is_empty() => {
+                    error = Some(LogError::ParseError(errors));
+                    consumed = end;
+                    break;
+                }
+                // The bad entry is reported by the next poll.
+                Err(..) => break,
+            }
+        }
+        self.pending.drain(..consumed);
+        match error {
+            Some(error) => Err(error),
+            None => Ok(entries),
+        }
+    }
+
+    /// Follow the log, polling it at an interval. The iterator does not end.
+    pub fn follow(self, interval: Duration) -> Follow {
+        Follow { reader: self, interval, ready: VecDeque::new() }
+    }
+
+}
+
+/// Iterator over the entries of a followed log.
+pub struct Follow {
+    reader: LogReader,
+    interval: Duration,
+    ready: VecDeque<ParsedValue>,
+}
+
+impl Iterator for Follow {
+
+    type Item = Result<ParsedValue, LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.ready.pop_front() {
+                return Some(Ok(entry));
+            }
+            match self.reader.poll() {
+                Ok(entries) if entries.is_empty() => std::thread::sleep(self.interval),
+                Ok(entries) => self.ready.extend(entries),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+
+}
+
+//// Errors
+
+/// Error writing or reading a log.
+pub enum LogError {
+    /// Could not read or write the log at X.
+    IoError(PathBuf, io::Error),
+    /// Entries of the log do not parse.
+    ParseError(Vec<ParseError>),
+    /// The entries at the end of an existing log do not parse.
+    CorruptTail(Vec<ParseError>),
+}
+
+impl Debug for LogError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", log_error_to_string(self))
+    }
+}
+
+pub fn log_error_to_string(error: &LogError) -> String {
+    match error {
+        LogError::IoError(path, error) => {
+            format!("Could not access log {}: {}", path.display(), error)
+        }
+        LogError::ParseError(errors) => {
+            let errors: Vec<String> = errors.iter().map(error_to_string).collect();
+            format!("Could not parse log entries: {}", errors.join(" "))
+        }
+        LogError::CorruptTail(errors) => {
+            let errors: Vec<String> = errors.iter().map(error_to_string).collect();
+            format!("End of log does not parse: {}", errors.join(" "))
+        }
+    }
+}
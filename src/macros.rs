@@ -0,0 +1,438 @@
+//! Document macros.
+//!
+//! A macro is defined with `<macro!>:name:arity:body`. Following uses of the tag
+//! `<name>` with as many arguments as the arity are replaced by the body, in
+//! which the parameters `#1`, `#2`, ... are replaced by the arguments:
+//!
+//! ```text
+//! <macro!>:card:2:{<div class:card>:{<h2>:#1 #2}}
+//! <card>:Title:{Some text.}
+//! ```
+//!
+//! A parameter is a word of text. Macros are expanded on the parsed document,
+//! so they work with any backend. Definitions are removed from the document.
+//!
+//! The definition tag is `macro!` rather than `def!`, because KhiTeX already
+//! uses `<def!>` to define LaTeX commands, and expanding before the TeX writer
+//! would otherwise consume those definitions.
+//!
+//! Expansion is limited in depth and in the total size of the values it
+//! produces. Once a limit is exceeded, expansion is aborted.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use crate::{Tagged, Tuple, Value};
+use crate::pdm::{ParsedAttribute, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedText, ParsedTuple, ParsedValue, Position, SharedStr};
+
+/// Name of the definition tag.
+pub const DEFINITION_TAG: &str = "macro!";
+
+/// Macros calling macros are expanded at most this many levels deep.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// Expansions produce at most this many values and bytes of text in total.
+const MAX_EXPANSION_SIZE: usize = 1 << 20;
+
+/// A macro expansion.
+pub struct Expansion {
+    /// Name of the macro.
+    pub name: SharedStr,
+    /// Span of the call.
+    pub call: (Position, Position),
+    /// Span of the definition.
+    pub definition: (Position, Position),
+}
+
+/// Expand the macros of a value document.
+///
+/// Returns the expansions in the order they were made.
+pub fn expand_macros(value: &mut ParsedValue) -> Result<Vec<Expansion>, Vec<MacroError>> {
+    let mut expander = Expander { definitions: HashMap::new(), expansions: vec![], errors: vec![], depth: 0, size: 0, aborted: false };
+    let (from, to) = (value.from(), value.to());
+    let taken = std::mem::replace(value, ParsedValue::Nil(from, to));
+    *value = expander.expand(taken).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to));
+    if expander.errors.is_empty() {
+        Ok(expander.expansions)
+    } else {
+        Err(expander.errors)
+    }
+}
+
+/// Check if a value contains macro definitions.
+///
+/// Without definitions, expansion leaves a document unchanged.
+pub fn has_macros(value: &ParsedValue) -> bool {
+    match value {
+        ParsedValue::Tagged(tag, ..) => tag.name() == DEFINITION_TAG || has_macros(tag.get()),
+        ParsedValue::Compound(compound, ..) => compound.components.iter().any(has_macros),
+        ParsedValue::List(list, ..) => list.elements.iter().any(has_macros),
+        ParsedValue::Dictionary(dictionary, ..) => dictionary.entries.values().any(has_macros),
+        ParsedValue::Tuple(tuple, ..) => tuple.iter().any(has_macros),
+        ParsedValue::Text(..) | ParsedValue::Nil(..) => false,
+    }
+}
+
+struct Definition {
+    arity: usize,
+    body: ParsedValue,
+    /// Size of the body.
+    size: usize,
+    /// Number of uses of each parameter in the body.
+    uses: Vec<usize>,
+    from: Position,
+    to: Position,
+}
+
+struct Expander {
+    definitions: HashMap<SharedStr, Definition>,
+    expansions: Vec<Expansion>,
+    errors: Vec<MacroError>,
+    depth: usize,
+    /// Total size of the expansions.
+    size: usize,
+    /// Whether a limit was exceeded.
+    aborted: bool,
+}
+
+impl Expander {
+
+    /// Expand a value. Returns `None` if the value is removed.
+    fn expand(&mut self, value: ParsedValue) -> Option<ParsedValue> {
+        if self.aborted {
+            return Some(value);
+        }
+        match value {
+            ParsedValue::Tagged(tag, from, to) if tag.name() == DEFINITION_TAG => {
+                self.define(*tag.value, from, to);
+                None
+            }
+            ParsedValue::Tagged(tag, from, to) if self.definitions.contains_key(tag.name()) => {
+                Some(self.call(tag, from, to))
+            }
+            ParsedValue::Tagged(mut tag, from, to) => {
+                let inner = std::mem::replace(&mut *tag.value, ParsedValue::Nil(from, to));
+                *tag.value = self.expand(inner).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to));
+                Some(ParsedValue::Tagged(tag, from, to))
+            }
+            ParsedValue::Compound(compound, from, to) => {
+                let mut components = vec![];
+                let mut whitespace = vec![];
+                for (i, component) in compound.components.into_iter().enumerate() {
+                    let space = i > 0 && compound.whitespace[i - 1];
+                    if let Some(component) = self.expand(component) {
+                        push_term(&mut components, &mut whitespace, component, space);
+                    }
+                }
+                if components.is_empty() {
+                    None
+                } else {
+                    Some(ParsedValue::from_terms(from, to, components, whitespace))
+                }
+            }
+            ParsedValue::List(list, from, to) => {
+                let elements = list.elements.into_iter().filter_map(|e| self.expand(e)).collect();
+                Some(ParsedValue::List(ParsedList { elements }, from, to))
+            }
+            ParsedValue::Dictionary(dictionary, from, to) => {
+                let mut entries = HashMap::new();
+                for (key, value) in dictionary.entries {
+                    if let Some(value) = self.expand(value) {
+                        entries.insert(key, value);
+                    }
+                }
+                Some(ParsedValue::Dictionary(ParsedDictionary { entries }, from, to))
+            }
+            ParsedValue::Tuple(ParsedTuple::Single(value), from, to) => {
+                let value = self.expand(*value).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to));
+                Some(ParsedValue::Tuple(ParsedTuple::Single(Box::new(value)), from, to))
+            }
+            ParsedValue::Tuple(ParsedTuple::Multiple(values), from, to) => {
+                let values: Vec<ParsedValue> = values.into_vec().into_iter().filter_map(|v| self.expand(v)).collect();
+                Some(ParsedValue::from_tuple(values, from, to))
+            }
+            value => Some(value),
+        }
+    }
+
+    fn define(&mut self, value: ParsedValue, from: Position, to: Position) {
+        let mut arguments = match value {
+            ParsedValue::Tuple(ParsedTuple::Multiple(arguments), ..) if arguments.len() == 3 => arguments.into_vec(),
+            _ => {
+                self.errors.push(MacroError::IllegalDefinition(from));
+                return;
+            }
+        };
+        let body = arguments.pop().unwrap();
+        let arity = match arguments[1].as_text().and_then(|a| a.str.parse::<usize>().ok()) {
+            Some(arity) => arity,
+            None => {
+                self.errors.push(MacroError::IllegalDefinition(from));
+                return;
+            }
+        };
+        let name = match arguments[0].as_text() {
+            Some(name) => name.str.clone(),
+            None => {
+                self.errors.push(MacroError::IllegalDefinition(from));
+                return;
+            }
+        };
+        let mut uses = vec![0; arity];
+        check_parameters(&body, &mut uses, &mut self.errors);
+        let size = body.size();
+        self.definitions.insert(name, Definition { arity, body, size, uses, from, to });
+    }
+
+    fn call(&mut self, tag: ParsedTaggedValue, from: Position, to: Position) -> ParsedValue {
+        if tag.has_attributes() {
+            self.errors.push(MacroError::IllegalCall(from));
+            return ParsedValue::Tagged(tag, from, to);
+        }
+        let ParsedTaggedValue { name, attributes, value } = tag;
+        let arguments: Vec<ParsedValue> = match *value {
+            ParsedValue::Tuple(ParsedTuple::Unit, ..) => vec![],
+            ParsedValue::Tuple(ParsedTuple::Multiple(arguments), ..) => arguments.into_vec(),
+            argument => vec![argument],
+        };
+        let arguments: Vec<ParsedValue> = arguments.into_iter().map(|a| {
+            let (from, to) = (a.from(), a.to());
+            self.expand(a).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to))
+        }).collect();
+        if self.aborted {
+            return restore_call(name, attributes, arguments, from, to);
+        }
+        let definition = self.definitions.get(&name).unwrap();
+        if arguments.len() != definition.arity {
+            self.errors.push(MacroError::ArityMismatch(from, name.clone(), definition.arity, arguments.len(), definition.from));
+            return restore_call(name, attributes, arguments, from, to);
+        }
+        if self.depth == MAX_EXPANSION_DEPTH {
+            self.errors.push(MacroError::TooDeep(from, name.clone()));
+            self.aborted = true;
+            return restore_call(name, attributes, arguments, from, to);
+        }
+        let size = definition.uses.iter().zip(&arguments).fold(definition.size, |size, (uses, argument)| {
+            size.saturating_add(uses.saturating_mul(argument.size()))
+        });
+        self.size = self.size.saturating_add(size);
+        if self.size > MAX_EXPANSION_SIZE {
+            self.errors.push(MacroError::TooLarge(from, name.clone()));
+            self.aborted = true;
+            return restore_call(name, attributes, arguments, from, to);
+        }
+        let body = substitute(&definition.body, &arguments);
+        self.expansions.push(Expansion { name, call: (from, to), definition: (definition.from, definition.to) });
+        self.depth += 1;
+        let expanded = self.expand(body).unwrap_or(ParsedValue::Tuple(ParsedTuple::Unit, from, to));
+        self.depth -= 1;
+        expanded
+    }
+
+}
+
+/// Restore a call that could not be expanded, with its expanded arguments.
+fn restore_call(name: SharedStr, attributes: Vec<ParsedAttribute>, arguments: Vec<ParsedValue>, from: Position, to: Position) -> ParsedValue {
+    let value = if arguments.len() == 1 {
+        arguments.into_iter().next().unwrap()
+    } else {
+        ParsedValue::from_tuple(arguments, from, to)
+    };
+    ParsedValue::Tagged(ParsedTaggedValue { name, attributes, value: Box::new(value) }, from, to)
+}
+
+/// Add a term to a compound, merging nested compounds and text separated by
+/// whitespace.
+fn push_term(components: &mut Vec<ParsedValue>, whitespace: &mut Vec<bool>, term: ParsedValue, space: bool) {
+    match term {
+        ParsedValue::Compound(compound, ..) => {
+            let mut space = space;
+            for (i, component) in compound.components.into_iter().enumerate() {
+                push_term(components, whitespace, component, space);
+                space = compound.whitespace.get(i).copied().unwrap_or(false);
+            }
+        }
+        ParsedValue::Text(text, ..) if space && matches!(components.last(), Some(ParsedValue::Text(..))) => {
+            if let Some(ParsedValue::Text(last, ..)) = components.last_mut() {
+                last.str = SharedStr::from(format!("{} {}", last.str, text.str));
+            }
+        }
+        term => {
+            if !components.is_empty() {
+                whitespace.push(space);
+            }
+            components.push(term);
+        }
+    }
+}
+
+/// Read a parameter word such as `#1`.
+fn parameter(word: &str) -> Option<usize> {
+    let digits = word.strip_prefix('#')?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Check that the parameters of a body are within the arity, and count their
+/// uses. The arity is the length of uses.
+fn check_parameters(value: &ParsedValue, uses: &mut [usize], errors: &mut Vec<MacroError>) {
+    match value {
+        ParsedValue::Text(text, from, _) => {
+            for word in text.str.split(' ') {
+                if let Some(n) = parameter(word) {
+                    match n.checked_sub(1).and_then(|i| uses.get_mut(i)) {
+                        Some(uses) => *uses += 1,
+                        None => errors.push(MacroError::UnknownParameter(*from, word.to_string())),
+                    }
+                }
+            }
+        }
+        ParsedValue::Tagged(tag, ..) => check_parameters(tag.get(), uses, errors),
+        ParsedValue::Compound(compound, ..) => {
+            for component in &compound.components {
+                check_parameters(component, uses, errors);
+            }
+        }
+        ParsedValue::List(list, ..) => {
+            for element in &list.elements {
+                check_parameters(element, uses, errors);
+            }
+        }
+        ParsedValue::Dictionary(dictionary, ..) => {
+            for value in dictionary.entries.values() {
+                check_parameters(value, uses, errors);
+            }
+        }
+        ParsedValue::Tuple(tuple, ..) => {
+            for value in tuple.iter() {
+                check_parameters(value, uses, errors);
+            }
+        }
+        ParsedValue::Nil(..) => {}
+    }
+}
+
+/// Replace the parameters of a body by arguments.
+fn substitute(value: &ParsedValue, arguments: &[ParsedValue]) -> ParsedValue {
+    match value {
+        ParsedValue::Text(text, from, to) => substitute_text(&text.str, *from, *to, arguments),
+        ParsedValue::Tagged(tag, from, to) => {
+            let value = substitute(tag.get(), arguments);
+            ParsedValue::Tagged(ParsedTaggedValue { name: tag.name.clone(), attributes: tag.attributes.clone(), value: Box::new(value) }, *from, *to)
+        }
+        ParsedValue::Compound(compound, from, to) => {
+            let mut components = vec![];
+            let mut whitespace = vec![];
+            for (i, component) in compound.components.iter().enumerate() {
+                let space = i > 0 && compound.whitespace[i - 1];
+                push_term(&mut components, &mut whitespace, substitute(component, arguments), space);
+            }
+            ParsedValue::from_terms(*from, *to, components, whitespace)
+        }
+        ParsedValue::List(list, from, to) => {
+            let elements = list.elements.iter().map(|e| substitute(e, arguments)).collect();
+            ParsedValue::List(ParsedList { elements }, *from, *to)
+        }
+        ParsedValue::Dictionary(dictionary, from, to) => {
+            let entries = dictionary.entries.iter().map(|(k, v)| (k.clone(), substitute(v, arguments))).collect();
+            ParsedValue::Dictionary(ParsedDictionary { entries }, *from, *to)
+        }
+        ParsedValue::Tuple(ParsedTuple::Single(value), from, to) => {
+            ParsedValue::Tuple(ParsedTuple::Single(Box::new(substitute(value, arguments))), *from, *to)
+        }
+        ParsedValue::Tuple(ParsedTuple::Multiple(values), from, to) => {
+            let values = values.iter().map(|v| substitute(v, arguments)).collect();
+            ParsedValue::Tuple(ParsedTuple::Multiple(values), *from, *to)
+        }
+        value => value.clone(),
+    }
+}
+
+/// Replace parameter words of text by arguments.
+///
+/// Text arguments are joined with the surrounding words, other arguments split
+/// the text into a compound.
+fn substitute_text(str: &str, from: Position, to: Position, arguments: &[ParsedValue]) -> ParsedValue {
+    if !str.contains('#') {
+        return ParsedValue::Text(ParsedText { str: SharedStr::from(str) }, from, to);
+    }
+    let mut components = vec![];
+    let mut whitespace = vec![];
+    let mut text = String::new();
+    for word in str.split(' ') {
+        let argument = parameter(word).and_then(|n| arguments.get(n.wrapping_sub(1)));
+        match argument {
+            Some(ParsedValue::Text(argument, ..)) => {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(&argument.str);
+            }
+            Some(argument) => {
+                if !text.is_empty() {
+                    let str = std::mem::take(&mut text);
+                    push_term(&mut components, &mut whitespace, ParsedValue::Text(ParsedText { str: SharedStr::from(str) }, from, to), true);
+                }
+                push_term(&mut components, &mut whitespace, argument.clone(), true);
+            }
+            None => {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(word);
+            }
+        }
+    }
+    if !text.is_empty() {
+        push_term(&mut components, &mut whitespace, ParsedValue::Text(ParsedText { str: SharedStr::from(text) }, from, to), true);
+    }
+    ParsedValue::from_terms(from, to, components, whitespace)
+}
+
+//// Errors
+
+/// Error expanding macros.
+pub enum MacroError {
+    /// Macro definition at X is malformed.
+    IllegalDefinition(Position),
+    /// Parameter Y at X exceeds the arity of its macro.
+    UnknownParameter(Position, String),
+    /// Call at X of macro Y defined at W takes Z1 arguments, but Z2 were given.
+    ArityMismatch(Position, SharedStr, usize, usize, Position),
+    /// Macro call at X has attributes.
+    IllegalCall(Position),
+    /// Expansion of macro Y at X is nested too deeply.
+    TooDeep(Position, SharedStr),
+    /// Expansion of macro Y at X exceeds the total size of expansions.
+    TooLarge(Position, SharedStr),
+}
+
+impl Debug for MacroError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", macro_error_to_string(self))
+    }
+}
+
+pub fn macro_error_to_string(error: &MacroError) -> String {
+    match error {
+        MacroError::IllegalDefinition(at) => {
+            format!("Macro definition at {}:{} must take a name, an arity and a body.", at.line, at.column)
+        }
+        MacroError::UnknownParameter(at, parameter) => {
+            format!("Parameter {} at {}:{} exceeds the arity of the macro.", parameter, at.line, at.column)
+        }
+        MacroError::ArityMismatch(at, name, arity, given, definition) => {
+            format!("Macro {} at {}:{} takes {} arguments, but {} were given. Defined at {}:{}.", name, at.line, at.column, arity, given, definition.line, definition.column)
+        }
+        MacroError::IllegalCall(at) => {
+            format!("Macro call at {}:{} cannot have attributes.", at.line, at.column)
+        }
+        MacroError::TooDeep(at, name) => {
+            format!("Expansion of macro {} at {}:{} is nested too deeply.", name, at.line, at.column)
+        }
+        MacroError::TooLarge(at, name) => {
+            format!("Expansion of macro {} at {}:{} produces too large a document.", name, at.line, at.column)
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Layered merge of dictionaries.
+//!
+//! Documents are merged on top of each other, later layers overriding earlier
+//! ones. Every merged value remembers the document and position it came from.
+//!
+//! An entry whose value is the tag `<delete!>` removes the entry from the
+//! layers below.
+
+use std::collections::HashMap;
+use crate::Tagged;
+use crate::pdm::{ParsedDictionary, ParsedList, ParsedValue, Position, SharedStr};
+
+/// Name of the tag that deletes an entry.
+pub const DELETE_TAG: &str = "delete!";
+
+//// Strategy
+
+/// How dictionaries are merged.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MergeStrategy {
+    /// Merge nested dictionaries entry by entry instead of replacing them.
+    pub deep: bool,
+    /// How a list is merged with a list below it.
+    pub lists: ListStrategy,
+}
+
+impl Default for MergeStrategy {
+    fn default() -> Self {
+        MergeStrategy { deep: true, lists: ListStrategy::Replace }
+    }
+}
+
+/// How lists are merged.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ListStrategy {
+    /// The list of the upper layer replaces the list below.
+    Replace,
+    /// The elements of the upper layer are appended to the list below.
+    Append,
+}
+
+//// Origin
+
+/// Where a value came from.
+///
+/// The positions are relative to the source.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub source: Source,
+    pub from: Position,
+    pub to: Position,
+}
+
+/// Source of a layer.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A document, usually named by its path.
+    Document(SharedStr),
+    /// An environment variable.
+    Environment(SharedStr),
+    /// A command line argument.
+    Argument(SharedStr),
+}
+
+pub fn source_to_string(source: &Source) -> String {
+    match source {
+        Source::Document(name) => name.to_string(),
+        Source::Environment(name) => format!("environment variable {}", name),
+        Source::Argument(argument) => format!("command line argument {}", argument),
+    }
+}
+
+pub fn origin_to_string(origin: &Origin) -> String {
+    match &origin.source {
+        Source::Document(name) => format!("{}:{}:{}", name, origin.from.line, origin.from.column),
+        source => source_to_string(source),
+    }
+}
+
+//// Merged values
+
+/// A dictionary merged from one or more layers.
+#[derive(Clone)]
+pub struct MergedDictionary {
+    pub entries: HashMap<SharedStr, MergedValue>,
+}
+
+/// A value of a merged dictionary.
+#[derive(Clone)]
+pub enum MergedValue {
+    Dictionary(MergedDictionary, Origin),
+    List(Vec<MergedValue>, Origin),
+    /// Any other value, taken as a whole from a single layer.
+    Value(ParsedValue, Origin),
+    /// Deletion marker.
+    Delete(Origin),
+}
+
+impl MergedDictionary {
+
+    pub fn empty() -> Self {
+        MergedDictionary { entries: HashMap::new() }
+    }
+
+    /// Create a layer from a parsed dictionary.
+    pub fn new(dictionary: &ParsedDictionary, source: Source) -> Self {
+        Self::from_entries(dictionary, &source)
+    }
+
+    /// Create a layer from a parsed dictionary of the named document.
+    pub fn from_document(dictionary: &ParsedDictionary, name: &str) -> Self {
+        Self::new(dictionary, Source::Document(SharedStr::from(name)))
+    }
+
+    fn from_entries(dictionary: &ParsedDictionary, source: &Source) -> Self {
+        let mut entries = HashMap::new();
+        for (key, value) in &dictionary.entries {
+            entries.insert(key.clone(), MergedValue::from_value(value, source));
+        }
+        MergedDictionary { entries }
+    }
+
+    /// Get the value of an entry.
+    pub fn get(&self, key: &str) -> Option<&MergedValue> {
+        match self.entries.get(key) {
+            Some(MergedValue::Delete(..)) | None => None,
+            Some(value) => Some(value),
+        }
+    }
+
+    /// Get the value at a key path.
+    pub fn get_path(&self, path: &[&str]) -> Option<&MergedValue> {
+        let (last, parents) = path.split_last()?;
+        let mut dictionary = self;
+        for key in parents {
+            match dictionary.get(key)? {
+                MergedValue::Dictionary(d, _) => dictionary = d,
+                _ => return None,
+            }
+        }
+        dictionary.get(last)
+    }
+
+    /// Get the origin of the value at a key path.
+    pub fn origin(&self, path: &[&str]) -> Option<&Origin> {
+        self.get_path(path).map(|v| v.origin())
+    }
+
+    /// Convert to a parsed dictionary, dropping the origins.
+    pub fn to_dictionary(&self) -> ParsedDictionary {
+        let mut entries = HashMap::new();
+        for (key, value) in &self.entries {
+            if let Some(value) = value.to_value() {
+                entries.insert(key.clone(), value);
+            }
+        }
+        ParsedDictionary { entries }
+    }
+
+}
+
+impl MergedValue {
+
+    /// Create a value of a layer.
+    pub fn from_value(value: &ParsedValue, source: &Source) -> Self {
+        let origin = Origin { source: source.clone(), from: value.from(), to: value.to() };
+        match value {
+            ParsedValue::Dictionary(dictionary, ..) => {
+                MergedValue::Dictionary(MergedDictionary::from_entries(dictionary, source), origin)
+            }
+            ParsedValue::List(list, ..) => {
+                let elements = list.elements.iter().map(|e| MergedValue::from_value(e, source)).collect();
+                MergedValue::List(elements, origin)
+            }
+            ParsedValue::Tagged(tag, ..) if tag.name() == DELETE_TAG => MergedValue::Delete(origin),
+            value => MergedValue::Value(value.clone(), origin),
+        }
+    }
+
+    /// Get the origin of this value.
+    pub fn origin(&self) -> &Origin {
+        match self {
+            MergedValue::Dictionary(_, origin) => origin,
+            MergedValue::List(_, origin) => origin,
+            MergedValue::Value(_, origin) => origin,
+            MergedValue::Delete(origin) => origin,
+        }
+    }
+
+    /// Convert to a parsed value, dropping the origins.
+    ///
+    /// Returns `None` for a deletion marker.
+    pub fn to_value(&self) -> Option<ParsedValue> {
+        match self {
+            MergedValue::Dictionary(dictionary, origin) => {
+                Some(ParsedValue::Dictionary(dictionary.to_dictionary(), origin.from, origin.to))
+            }
+            MergedValue::List(elements, origin) => {
+                let elements = elements.iter().filter_map(|e| e.to_value()).collect();
+                Some(ParsedValue::List(ParsedList { elements }, origin.from, origin.to))
+            }
+            MergedValue::Value(value, ..) => Some(value.clone()),
+            MergedValue::Delete(..) => None,
+        }
+    }
+
+}
+
+//// Merge
+
+/// Merge an overlay on top of a base dictionary.
+///
+/// Deletion markers of the overlay remove entries from the base. No deletion
+/// markers remain in the result.
+pub fn merge(base: &MergedDictionary, overlay: &MergedDictionary, strategy: &MergeStrategy) -> MergedDictionary {
+    let mut merged = base.clone();
+    merge_into(&mut merged, overlay, strategy);
+    strip_deletions(&mut merged);
+    merged
+}
+
+/// Merge layers in order, the first being the lowest.
+pub fn merge_all(layers: &[MergedDictionary], strategy: &MergeStrategy) -> MergedDictionary {
+    let mut merged = MergedDictionary::empty();
+    for layer in layers {
+        merge_into(&mut merged, layer, strategy);
+    }
+    strip_deletions(&mut merged);
+    merged
+}
+
+fn merge_into(base: &mut MergedDictionary, overlay: &MergedDictionary, strategy: &MergeStrategy) {
+    for (key, value) in &overlay.entries {
+        match (base.entries.get_mut(key), value) {
+            (_, MergedValue::Delete(..)) => {
+                base.entries.remove(key);
+            }
+            (Some(MergedValue::Dictionary(below, origin)), MergedValue::Dictionary(above, above_origin)) if strategy.deep => {
+                merge_into(below, above, strategy);
+                *origin = above_origin.clone();
+            }
+            (Some(MergedValue::List(below, origin)), MergedValue::List(above, above_origin)) if strategy.lists == ListStrategy::Append => {
+                below.extend(above.iter().cloned());
+                *origin = above_origin.clone();
+            }
+            (_, value) => {
+                base.entries.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn strip_deletions(dictionary: &mut MergedDictionary) {
+    dictionary.entries.retain(|_, value| !matches!(value, MergedValue::Delete(..)));
+    for value in dictionary.entries.values_mut() {
+        if let MergedValue::Dictionary(dictionary, ..) = value {
+            strip_deletions(dictionary);
+        }
+    }
+}
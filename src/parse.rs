@@ -2,7 +2,8 @@
 //!
 //! A document conforms to a value, dictionary or list. Use the corresponding
 //! function to parse a document: [parse_value_str], [parse_dictionary_str] or
-//! [parse_list_str].
+//! [parse_list_str]. If the kind is not known in advance, use
+//! [parse_document_str], which infers it.
 
 // An O(n) predictive and recursive parser. Works in three stages: First, the
 // input string is lexed and tokenized. Second, some tokens are reduced. Bracket
@@ -12,19 +13,37 @@
 // create a parsed document model (AST).
 
 use std::collections::{HashSet};
-use crate::lex::{lex, LexError};
+use crate::lex::{LexError, Lexer, Token};
 use crate::parse::parser::{ParseError, Parser};
 use crate::parse::reducer::{Reduced, ReduceError, Reducer};
-use crate::pdm::{ParsedDictionary, ParsedList, ParsedValue, Position};
+use crate::pdm::{ParsedDictionary, ParsedList, ParsedValue, Position, SharedStr};
 
-const MAX_DEPTH: usize = 256; //TODO
+/// Default maximum nesting depth.
+///
+/// Deep enough for any hand written document, and shallow enough for the
+/// parser to fit in the 2 MiB stack of a spawned thread in an unoptimized build.
+pub const MAX_DEPTH: usize = 64;
 
 /// Parse a value document string.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected with [ParseError::TooDeep].
+/// Use [parse_value_str_with] for other limits.
 pub fn parse_value_str(document: &str) -> Result<ParsedValue, Vec<ParseError>> {
-    let tokens = unwrap_or_throw(tokenize(document))?;
-    let mut strings = HashSet::new();
+    parse_value_str_interned(document, &mut Interner::new())
+}
+
+/// Parse a value document string, storing its strings in an interner.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected, as in [parse_value_str].
+pub fn parse_value_str_interned(document: &str, interner: &mut Interner) -> Result<ParsedValue, Vec<ParseError>> {
+    parse_value_str_with(document, &ParseOptions::default(), interner)
+}
+
+/// Parse a value document string within the limits of the options.
+pub fn parse_value_str_with(document: &str, options: &ParseOptions, interner: &mut Interner) -> Result<ParsedValue, Vec<ParseError>> {
+    let tokens = unwrap_or_throw(tokenize_with(document, options))?;
     let mut errors = Vec::new();
-    let mut parser = Parser::new(&tokens, &mut strings, &mut errors, false, Position { index: 0, line: 0, column: 0 });
+    let mut parser = Parser::new(&tokens, interner, &mut errors, false, Position { index: 0, line: 0, column: 0 }).with_options(options);
     let parse = parser.parse_value_document();
     if parse.is_ok() && !parser.is_end() {
         let error = ParseError::ExpectedEnd(parser.t0.to_type(), parser.at());
@@ -34,11 +53,25 @@ pub fn parse_value_str(document: &str) -> Result<ParsedValue, Vec<ParseError>> {
 }
 
 /// Parse a dictionary document string.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected with [ParseError::TooDeep].
+/// Use [parse_dictionary_str_with] for other limits.
 pub fn parse_dictionary_str(document: &str) -> Result<ParsedDictionary, Vec<ParseError>> {
-    let tokens = unwrap_or_throw(tokenize(document))?;
-    let mut strings = HashSet::new();
+    parse_dictionary_str_interned(document, &mut Interner::new())
+}
+
+/// Parse a dictionary document string, storing its strings in an interner.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected, as in [parse_dictionary_str].
+pub fn parse_dictionary_str_interned(document: &str, interner: &mut Interner) -> Result<ParsedDictionary, Vec<ParseError>> {
+    parse_dictionary_str_with(document, &ParseOptions::default(), interner)
+}
+
+/// Parse a dictionary document string within the limits of the options.
+pub fn parse_dictionary_str_with(document: &str, options: &ParseOptions, interner: &mut Interner) -> Result<ParsedDictionary, Vec<ParseError>> {
+    let tokens = unwrap_or_throw(tokenize_with(document, options))?;
     let mut errors = Vec::new();
-    let mut parser = Parser::new(&tokens, &mut strings, &mut errors, false, Position { index: 0, line: 0, column: 0 });
+    let mut parser = Parser::new(&tokens, interner, &mut errors, false, Position { index: 0, line: 0, column: 0 }).with_options(options);
     let parse = parser.parse_dictionary_document();
     if parse.is_ok() && !parser.is_end() {
         let error = ParseError::ExpectedEnd(parser.t0.to_type(), parser.at());
@@ -48,11 +81,25 @@ pub fn parse_dictionary_str(document: &str) -> Result<ParsedDictionary, Vec<Pars
 }
 
 /// Parse a list document string.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected with [ParseError::TooDeep].
+/// Use [parse_list_str_with] for other limits.
 pub fn parse_list_str(document: &str) -> Result<ParsedList, Vec<ParseError>> {
-    let tokens = unwrap_or_throw(tokenize(document))?;
-    let mut strings = HashSet::new();
+    parse_list_str_interned(document, &mut Interner::new())
+}
+
+/// Parse a list document string, storing its strings in an interner.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected, as in [parse_list_str].
+pub fn parse_list_str_interned(document: &str, interner: &mut Interner) -> Result<ParsedList, Vec<ParseError>> {
+    parse_list_str_with(document, &ParseOptions::default(), interner)
+}
+
+/// Parse a list document string within the limits of the options.
+pub fn parse_list_str_with(document: &str, options: &ParseOptions, interner: &mut Interner) -> Result<ParsedList, Vec<ParseError>> {
+    let tokens = unwrap_or_throw(tokenize_with(document, options))?;
     let mut errors = Vec::new();
-    let mut parser = Parser::new(&tokens, &mut strings, &mut errors, false, Position { index: 0, line: 0, column: 0 });
+    let mut parser = Parser::new(&tokens, interner, &mut errors, false, Position { index: 0, line: 0, column: 0 }).with_options(options);
     let parse = parser.parse_list_document();
     if parse.is_ok() && !parser.is_end() {
         let error = ParseError::ExpectedEnd(parser.t0.to_type(), parser.at());
@@ -61,6 +108,216 @@ pub fn parse_list_str(document: &str) -> Result<ParsedList, Vec<ParseError>> {
     present_parse(parse, errors)
 }
 
+/// Limits on the documents accepted by the parser.
+///
+/// Exceeding a limit stops the parse with an error instead of exhausting the
+/// stack or memory on hostile input. The default limits the nesting depth to
+/// [MAX_DEPTH] and leaves the rest unlimited, and enforces the whitespace rules
+/// at [Strictness::Lenient].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Maximum depth of nested brackets and tags.
+    pub max_depth: usize,
+    /// Maximum length of the document in bytes.
+    pub max_document_size: usize,
+    /// Maximum number of tokens in the document, including whitespace.
+    pub max_tokens: usize,
+    /// Maximum length of a word, transcription or text block in bytes.
+    pub max_string_length: usize,
+    /// How strictly the whitespace rules are enforced.
+    pub strictness: Strictness,
+}
+
+impl ParseOptions {
+
+    /// Options without any limits.
+    ///
+    /// Only use on trusted documents: deep nesting overflows the stack.
+    pub fn unlimited() -> Self {
+        ParseOptions { max_depth: usize::MAX, max_document_size: usize::MAX, max_tokens: usize::MAX, max_string_length: usize::MAX, strictness: Strictness::Lenient }
+    }
+
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions { max_depth: MAX_DEPTH, ..ParseOptions::unlimited() }
+    }
+}
+
+/// How strictly the parser enforces the whitespace rules of the grammar.
+///
+/// Whitespace that decides how a document is read, such as the space after
+/// the colon of a tagged value header, is significant at every level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strictness {
+    /// Only enforce the rules around the colons of keys, headers, tagged values
+    /// and arguments, at the start of tags and headers, and around bullets.
+    #[default]
+    Lenient,
+    /// Also enforce the rules at the end of tags and headers, between the name
+    /// and the attributes of a tag, and between the entries of aligned
+    /// dictionaries.
+    Standard,
+    /// Also require whitespace between sections and between the values of
+    /// tagged lists.
+    Strict,
+}
+
+/// Stores each distinct string once.
+///
+/// Pass an interner to the `parse_*_str_interned` functions to share keys, tag
+/// names and text between the values parsed from many documents.
+#[derive(Default)]
+pub struct Interner {
+    strings: HashSet<SharedStr>,
+}
+
+impl Interner {
+
+    pub fn new() -> Self {
+        Interner { strings: HashSet::new() }
+    }
+
+    /// Get the stored string equal to a string, storing it if there is none.
+    pub fn intern(&mut self, string: &str) -> SharedStr {
+        if let Some(str) = self.strings.get(string) {
+            SharedStr::clone(str)
+        } else {
+            let str = SharedStr::from(string);
+            self.strings.insert(SharedStr::clone(&str));
+            str
+        }
+    }
+
+    /// Number of stored strings.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Remove the strings that are no longer used by any parsed value.
+    pub fn purge(&mut self) {
+        self.strings.retain(|str| SharedStr::strong_count(str) > 1);
+    }
+
+}
+
+/// A parsed document of any kind.
+pub enum ParsedDocument {
+    Value(ParsedValue),
+    Dictionary(ParsedDictionary),
+    List(ParsedList),
+}
+
+/// Kind of a document.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum DocumentKind {
+    Value,
+    Dictionary,
+    List,
+}
+
+/// Parse a document string of any kind.
+///
+/// The kind is taken from a pragma comment such as `# Document type: List`
+/// among the leading comments of the document. Without a pragma, the kind is
+/// inferred from the top-level structure: a leading key or header means a
+/// dictionary, a leading bullet or bar, semicolon separators or several tagged
+/// values mean a list, and anything else is a value.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected with [ParseError::TooDeep].
+/// Use [parse_document_str_with] for other limits.
+pub fn parse_document_str(document: &str) -> Result<ParsedDocument, Vec<ParseError>> {
+    parse_document_str_interned(document, &mut Interner::new())
+}
+
+/// Parse a document string of any kind, storing its strings in an interner.
+///
+/// Nesting deeper than [MAX_DEPTH] is rejected, as in [parse_document_str].
+pub fn parse_document_str_interned(document: &str, interner: &mut Interner) -> Result<ParsedDocument, Vec<ParseError>> {
+    parse_document_str_with(document, &ParseOptions::default(), interner)
+}
+
+/// Parse a document string of any kind within the limits of the options.
+pub fn parse_document_str_with(document: &str, options: &ParseOptions, interner: &mut Interner) -> Result<ParsedDocument, Vec<ParseError>> {
+    let tokens = unwrap_or_throw(tokenize_with(document, options))?;
+    let kind = match document_pragma(document) {
+        Some(kind) => kind,
+        None => infer_document_kind(&tokens),
+    };
+    let mut errors = Vec::new();
+    let mut parser = Parser::new(&tokens, interner, &mut errors, false, Position { index: 0, line: 0, column: 0 }).with_options(options);
+    let parse = match kind {
+        DocumentKind::Value => parser.parse_value_document().map(ParsedDocument::Value),
+        DocumentKind::Dictionary => parser.parse_dictionary_document().map(ParsedDocument::Dictionary),
+        DocumentKind::List => parser.parse_list_document().map(ParsedDocument::List),
+    };
+    if parse.is_ok() && !parser.is_end() {
+        let error = ParseError::ExpectedEnd(parser.t0.to_type(), parser.at());
+        errors.push(error);
+    };
+    present_parse(parse, errors)
+}
+
+/// Determine the kind of a document string without parsing it.
+pub fn detect_document_kind(document: &str) -> Result<DocumentKind, Vec<ParseError>> {
+    if let Some(kind) = document_pragma(document) {
+        return Ok(kind);
+    };
+    let tokens = unwrap_or_throw(tokenize(document))?;
+    Ok(infer_document_kind(&tokens))
+}
+
+/// Read the document type pragma from the leading comments of a document.
+fn document_pragma(document: &str) -> Option<DocumentKind> {
+    for line in document.lines() {
+        let line = line.trim();
+        if line.is_empty() || line == "#" {
+            continue;
+        }
+        let comment = line.strip_prefix("# ")?;
+        if let Some(kind) = comment.trim_start().strip_prefix("Document type:") {
+            let kind = kind.split_whitespace().next()?;
+            return if kind.eq_ignore_ascii_case("value") {
+                Some(DocumentKind::Value)
+            } else if kind.eq_ignore_ascii_case("dictionary") {
+                Some(DocumentKind::Dictionary)
+            } else if kind.eq_ignore_ascii_case("list") {
+                Some(DocumentKind::List)
+            } else {
+                None
+            };
+        };
+    }
+    None
+}
+
+/// Infer the kind of a document from its top-level tokens.
+fn infer_document_kind(tokens: &[Reduced]) -> DocumentKind {
+    match tokens.first() {
+        Some(Reduced::AssignmentHeader(..) | Reduced::CurlyHeader(..) | Reduced::SquareHeader(..)) => return DocumentKind::Dictionary,
+        Some(Reduced::Bullet(..) | Reduced::Bar(..)) => return DocumentKind::List,
+        _ => {}
+    };
+    let mut tagged = 0;
+    for token in tokens {
+        match token {
+            Reduced::Semicolon(..) => return DocumentKind::List,
+            Reduced::TaggedValueHeader(..) => tagged += 1,
+            _ => {}
+        };
+    }
+    if tagged > 1 {
+        DocumentKind::List
+    } else {
+        DocumentKind::Value
+    }
+}
+
 fn unwrap_or_throw<T>(t: Result<T, ParseError>) -> Result<T, Vec<ParseError>> {
     match t {
         Ok(o) => Ok(o),
@@ -85,59 +342,88 @@ fn present_parse<T>(parse: Result<T, ParseError>, mut errors: Vec<ParseError>) -
 }
 
 /// Convert a Khi document to tokens.
-fn tokenize(document: &str) -> Result<Vec<Reduced>, ParseError> {
-    let chars = document.chars();
-    let tokens = match lex(chars) {
-        Ok(tokens) => tokens,
-        Err(error) => {
-            return match error {
-                LexError::EscapeEos => Err(ParseError::EscapingEndOfStream),
-                LexError::InvalidEscapeSequence(at) => Err(ParseError::InvalidEscapeSequence(at)),
-                LexError::InvalidHashSequence(at) => Err(ParseError::IllegalHashSequence(at)),
-                LexError::UnclosedTextBlock(at) => Err(ParseError::UnclosedTextBlock(at)),
-                LexError::InvalidTextBlockConfiguration(at) => Err(ParseError::InvalidTextBlockConfiguration(at)),
-            };
-        }
-    };
-    let mut reducer = Reducer::new(&tokens);
-    let reduced = match reducer.reduce() {
-        Ok(tokens) => tokens,
-        Err(error) => {
-            return match error {
-                ReduceError::MismatchedClose(close_type, close_at, in_type, in_at) => Err(ParseError::MismatchedClose(close_at, close_type.to_rule(), in_at, in_type.to_rule())),
-            }
-        }
+///
+/// The text of the tokens is borrowed from the document where possible.
+pub(crate) fn tokenize(document: &str) -> Result<Vec<Reduced<'_>>, ParseError> {
+    tokenize_with(document, &ParseOptions::default())
+}
+
+/// Convert a Khi document to tokens within the limits of the options.
+pub(crate) fn tokenize_with<'a>(document: &'a str, options: &ParseOptions) -> Result<Vec<Reduced<'a>>, ParseError> {
+    if document.len() > options.max_document_size {
+        return Err(ParseError::DocumentTooLarge(document.len(), options.max_document_size));
     };
-    Ok(reduced)
+    reduce(Reducer::with_options(Lexer::new(document), *options))
+}
+
+/// Reduce lexed tokens within the limits of the options.
+pub(crate) fn reduce_tokens_with<'a>(tokens: Vec<Token<'a>>, options: &ParseOptions) -> Result<Vec<Reduced<'a>>, ParseError> {
+    reduce(Reducer::with_options(tokens.into_iter().map(Ok), *options))
+}
+
+fn reduce<'a, I: Iterator<Item = Result<Token<'a>, LexError>>>(mut reducer: Reducer<'a, I>) -> Result<Vec<Reduced<'a>>, ParseError> {
+    reducer.reduce().map_err(reduce_error_to_parse_error)
+}
+
+pub(crate) fn reduce_error_to_parse_error(error: ReduceError) -> ParseError {
+    match error {
+        ReduceError::MismatchedClose(close_type, close_at, in_type, in_at) => ParseError::MismatchedClose(close_at, close_type.to_rule(), in_at, in_type.to_rule()),
+        ReduceError::LexError(error) => lex_error_to_parse_error(error),
+        ReduceError::TooDeep(at, max) => ParseError::TooDeep(at, max),
+        ReduceError::TooManyTokens(at, max) => ParseError::TooManyTokens(at, max),
+        ReduceError::StringTooLong(at, max) => ParseError::StringTooLong(at, max),
+    }
+}
+
+pub(crate) fn lex_error_to_parse_error(error: LexError) -> ParseError {
+    match error {
+        LexError::EscapeEos => ParseError::EscapingEndOfStream,
+        LexError::InvalidEscapeSequence(at) => ParseError::InvalidEscapeSequence(at),
+        LexError::InvalidHashSequence(at) => ParseError::IllegalHashSequence(at),
+        LexError::UnclosedTextBlock(at) => ParseError::UnclosedTextBlock(at),
+        LexError::InvalidTextBlockConfiguration(at) => ParseError::InvalidTextBlockConfiguration(at),
+    }
 }
 
 /// Parser
 pub mod parser {
 
-    use std::collections::{HashMap, HashSet};
+    use std::collections::HashMap;
     use std::fmt::{Debug, Formatter};
     use std::ops::Deref;
-    use std::rc::Rc;
-    use std::slice::Iter;
+        use std::slice::Iter;
     use std::vec;
     use crate::{Dictionary, Value};
+    use crate::parse::{Interner, MAX_DEPTH, ParseOptions, Strictness};
     use crate::parse::reducer::{Reduced, ScopeType, StringType};
-    use crate::pdm::{ParsedAttribute, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedText, ParsedTuple, ParsedValue, Position};
+    use crate::pdm::{ParsedAttribute, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedText, ParsedTuple, ParsedValue, Position, SharedStr};
 
     pub struct Parser<'a> {
-        stream: Iter<'a, Reduced>,
-        pub t0: &'a Reduced,
-        t1: &'a Reduced,
-        strings: &'a mut HashSet<Rc<str>>,
+        stream: Iter<'a, Reduced<'a>>,
+        pub t0: &'a Reduced<'a>,
+        t1: &'a Reduced<'a>,
+        strings: &'a mut Interner,
         errors: &'a mut Vec<ParseError>,
         whitespace_before: bool,
+        /// Whether the previous token is a transcription ended by a line break.
+        line_break_before: bool,
         last_position: Position,
+        /// Position right after the previous token, where whitespace before
+        /// the current token starts.
+        last_end: Position,
+        /// Number of enclosing brackets and tags.
+        depth: usize,
+        max_depth: usize,
+        /// Depth of the content of the innermost tag, if no bracket is opened
+        /// in it yet. A tag and the bracket of its content count as one level.
+        free_bracket: Option<usize>,
+        strictness: Strictness,
     }
 
     impl<'a> Parser<'a> {
         pub fn new(
-            tokens: &'a Vec<Reduced>,
-            strings: &'a mut HashSet<Rc<str>>,
+            tokens: &'a Vec<Reduced<'a>>,
+            strings: &'a mut Interner,
             errors: &'a mut Vec<ParseError>,
             whitespace_before: bool,
             open_position: Position,
@@ -147,18 +433,61 @@ pub mod parser {
                 stream: tokens.iter(),
                 t0: &DEFAULT, t1: &DEFAULT,
                 strings, errors, whitespace_before,
+                line_break_before: false,
                 last_position: open_position,
+                last_end: open_position,
+                depth: 0,
+                max_depth: MAX_DEPTH,
+                free_bracket: None,
+                strictness: Strictness::Lenient,
             };
             iter.shift();
             iter.shift();
             iter.whitespace_before = whitespace_before;
+            iter.line_break_before = false;
             iter.last_position = open_position;
+            iter.last_end = open_position;
             iter
         }
 
+        /// Limit the depth of nested brackets and tags, and set the strictness.
+        pub fn with_options(mut self, options: &ParseOptions) -> Self {
+            self.max_depth = options.max_depth;
+            self.strictness = options.strictness;
+            self
+        }
+
+        /// Create a parser for the tokens within a bracket.
+        fn nested<'b>(&'b mut self, tokens: &'b Vec<Reduced<'b>>, whitespace_before: bool, open_position: Position) -> Result<Parser<'b>, ParseError> {
+            let free = self.free_bracket.take() == Some(self.depth);
+            if !free && self.depth >= self.max_depth {
+                return Err(ParseError::TooDeep(open_position, self.max_depth));
+            };
+            let mut parser = Parser::new(tokens, self.strings, self.errors, whitespace_before, open_position);
+            parser.depth = if free { self.depth } else { self.depth + 1 };
+            parser.max_depth = self.max_depth;
+            parser.strictness = self.strictness;
+            Ok(parser)
+        }
+
+        /// Parse the content of a tag.
+        fn nest<T>(&mut self, at: Position, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+            if self.depth >= self.max_depth {
+                return Err(ParseError::TooDeep(at, self.max_depth));
+            };
+            self.depth += 1;
+            self.free_bracket = Some(self.depth);
+            let result = parse(self);
+            self.free_bracket = None;
+            self.depth -= 1;
+            result
+        }
+
         fn shift(&mut self) {
             self.whitespace_before = self.t0.has_whitespace_after();
+            self.line_break_before = self.t0.is_line_transcription();
             self.last_position = self.t0.to();
+            self.last_end = self.t0.end();
             self.t0 = self.t1;
             self.t1 = self.stream.next().unwrap_or(self.t1);
         }
@@ -175,15 +504,8 @@ pub mod parser {
             matches!(self.t0, Reduced::End(..))
         }
 
-        fn store_str(&mut self, string: &str) -> Rc<str> {
-            if let Some(str) = self.strings.get(string) {
-                str.clone()
-            } else {
-                let count = Rc::from(string);
-                let str = Rc::clone(&count);
-                self.strings.insert(count);
-                str
-            }
+        fn store_str(&mut self, string: &str) -> SharedStr {
+            self.strings.intern(string)
         }
 
     }
@@ -427,9 +749,9 @@ pub mod parser {
             if !matches!(self.t0, Reduced::Colon(..)) {
                 return ParseError::token_expectation_error(&[Rule::Colon], self.t0, Rule::TaggedValue, from);
             };
-            self.require_whitespace_after();
+            self.require_whitespace_after(Strictness::Lenient, Rule::Colon, Rule::Value, Rule::TaggedValue, from);
             self.shift();
-            let value = self.parse_value()?;
+            let value = self.nest(from, |parser| parser.parse_value())?;
             let to = self.at();
             let tagged_value = match tag {
                 Some((name, attributes)) => {
@@ -470,31 +792,44 @@ pub mod parser {
             let mut dictionary_sections = vec![];
             let mut direct_entries = vec![];
             let from = self.at();
+            let mut previous = Rule::Dictionary;
             if matches!(self.t0, Reduced::AssignmentHeader(..)) {
                 let mut entries = self.parse_inner_dictionary()?;
                 direct_entries.append(&mut entries);
             }
             loop {
+                let section_from = self.at();
+                if !matches!(self.t0, Reduced::CurlyHeader(..) | Reduced::SquareHeader(..)) {
+                    break;
+                }
+                if section_from != from {
+                    self.require_whitespace_before(Strictness::Strict, previous, Rule::Section, Rule::AbsoluteDictionary, from);
+                }
+                previous = Rule::Section;
                 match self.t0 {
                     Reduced::CurlyHeader(..) => {
                         let header = self.parse_header()?;
                         if !matches!(self.t0, Reduced::Colon(..)) {
                             return ParseError::token_expectation_error(&[Rule::Colon], self.t0, Rule::AbsoluteDictionary, from);
                         }
-                        self.require_no_whitespace_before();
-                        self.require_whitespace_after(); // TODO
+                        self.require_no_whitespace_before(Strictness::Lenient, Rule::BracketHeader, Rule::Colon, Rule::Section, section_from);
                         self.shift();
                         let content_from = self.at();
                         match self.t0 {
                             Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..) | Reduced::Bar(..) | Reduced::TaggedValueHeader(..) => {
+                                self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Value, Rule::Section, section_from);
                                 let value = self.parse_value()?;
                                 direct_entries.push((header, value));
                             }
                             Reduced::AssignmentHeader(..) => {
+                                self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Dictionary, Rule::Section, section_from);
                                 let entries = self.parse_inner_dictionary()?;
                                 dictionary_sections.push((header, entries))
                             }
-                            _ => direct_entries.push((header, ParsedValue::Dictionary(ParsedDictionary::empty(), content_from, content_from))),
+                            _ => {
+                                self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Section, Rule::Section, section_from);
+                                direct_entries.push((header, ParsedValue::Dictionary(ParsedDictionary::empty(), content_from, content_from)))
+                            }
                         }
                     }
                     Reduced::SquareHeader(..) => {
@@ -502,15 +837,16 @@ pub mod parser {
                         if !matches!(self.t0, Reduced::Colon(..)) {
                             return ParseError::token_expectation_error(&[Rule::Colon], self.t0, Rule::AbsoluteDictionary, from);
                         }
-                        self.require_no_whitespace_before();
-                        self.require_whitespace_after();
+                        self.require_no_whitespace_before(Strictness::Lenient, Rule::SquareHeader, Rule::Colon, Rule::Section, section_from);
                         self.shift();
                         let table_from = self.at();
                         if matches!(self.t0, Reduced::String(..) | Reduced::CurlyBracket(..) | Reduced::SquareBracket(..) | Reduced::AngleBracket(..) | Reduced::Tilde(..) | Reduced::Bar(..) | Reduced::TaggedValueHeader(..)) {
+                            self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::List, Rule::Section, section_from);
                             let list = self.parse_list()?;
                             let table_to = self.at_last();
                             direct_entries.push((header, ParsedValue::List(list, table_from, table_to)))
                         } else {
+                            self.require_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::Section, Rule::Section, section_from);
                             direct_entries.push((header, ParsedValue::List(ParsedList::empty(), table_from, table_from)))
                         }
                     }
@@ -534,12 +870,14 @@ pub mod parser {
             let mut key = vec![];
             let at = self.at();
             match self.t0 {
-                Reduced::CurlyHeader(_, ht, fw, scope) | Reduced::SquareHeader(_, ht, fw, scope) => {
+                Reduced::CurlyHeader(_, _, fw, scope) | Reduced::SquareHeader(_, _, fw, scope) => {
+                    let open = if matches!(self.t0, Reduced::CurlyHeader(..)) { Rule::BracketOpen } else { Rule::SquareOpen };
                     self.shift();
-                    let mut parser = Parser::new(&scope, self.strings, self.errors, *fw, *ht);
-                    parser.require_no_whitespace_before();
+                    let mut parser = self.nested(scope, *fw, after_open(at))?;
+                    parser.require_no_whitespace_before(Strictness::Lenient, open, Rule::Key, Rule::Header, at);
                     key = parser.parse_key()?;
-                    parser.require_end();
+                    parser.require_no_whitespace_before(Strictness::Standard, Rule::Key, Rule::Close, Rule::Header, at);
+                    parser.require_no_whitespace_after(Strictness::Lenient, Rule::Key, Rule::Close, Rule::Header, at);
                 }
                 _ => return ParseError::token_expectation_error(&[Rule::Key], self.t0, Rule::Header, at),
             }
@@ -602,13 +940,14 @@ pub mod parser {
         /// ```
         fn parse_aligned_dictionary(&mut self, entries: Vec<ParsedEntry>) -> Result<Vec<ParsedEntry>, ParseError> {
             let mut entries = entries;
+            let from = self.at();
             loop {
+                self.require_whitespace_before(Strictness::Standard, Rule::Entry, Rule::Entry, Rule::AlignedDictionary, from);
                 let entry = self.parse_entry()?;
                 entries.push(entry);
                 if !matches!(self.t0, Reduced::AssignmentHeader(..)) {
                     break;
                 }
-                self.require_whitespace_before();
             }
             Ok(entries)
         }
@@ -637,8 +976,9 @@ pub mod parser {
         }
 
         /// Parse a key and a colon.
-        fn parse_entry_key(&mut self) -> Result<Vec<Rc<str>>, ParseError> {
+        fn parse_entry_key(&mut self) -> Result<Vec<SharedStr>, ParseError> {
             let mut key = vec![];
+            let from = self.at();
             loop {
                 let s = match self.t0 {
                     Reduced::String(_, _, _, _, s) => s,
@@ -651,12 +991,12 @@ pub mod parser {
                 if !matches!(self.t0, Reduced::Colon(..)) {
                     return ParseError::token_expectation_error(&[Rule::Colon], self.t0, Rule::Key, self.t0.at());
                 }
-                self.require_no_whitespace_before();
+                self.require_no_whitespace_before(Strictness::Lenient, Rule::String, Rule::Colon, Rule::Key, from);
                 self.shift();
                 if !matches!(self.t0, Reduced::String(..) | Reduced::AssignmentHeader(..)) || !matches!(self.t1, Reduced::Colon(..)) {
                     break;
                 }
-                self.require_no_whitespace_before();
+                self.require_no_whitespace_before(Strictness::Lenient, Rule::Colon, Rule::String, Rule::Key, from);
             }
             Ok(key)
         }
@@ -667,8 +1007,9 @@ pub mod parser {
         /// <key> → <string>
         ///       | <string>":"<key>
         /// ```
-        fn parse_key(&mut self) -> Result<Vec<Rc<str>>, ParseError> {
+        fn parse_key(&mut self) -> Result<Vec<SharedStr>, ParseError> {
             let mut key = vec![];
+            let from = self.at();
             loop {
                 match self.t0 {
                     Reduced::String(.., s) | Reduced::AssignmentHeader(.., s) => {
@@ -681,8 +1022,8 @@ pub mod parser {
                 if !matches!(self.t0, Reduced::Colon(..)) {
                     break;
                 }
-                self.require_no_whitespace_before();
-                self.require_no_whitespace_after();
+                self.require_no_whitespace_before(Strictness::Lenient, Rule::String, Rule::Colon, Rule::Key, from);
+                self.require_no_whitespace_after(Strictness::Lenient, Rule::Colon, Rule::String, Rule::Key, from);
                 self.shift();
             }
             Ok(key)
@@ -768,14 +1109,14 @@ pub mod parser {
                 return ParseError::token_expectation_error(&[Rule::RightAngle], self.t0, Rule::AlignedList, at);
             }
             loop {
-                self.require_whitespace_after();
+                self.require_whitespace_after(Strictness::Lenient, Rule::RightAngle, Rule::Value, Rule::AlignedList, at);
                 self.shift();
                 let value = self.parse_value()?;
                 elements.push(value);
                 if !matches!(self.t0, Reduced::Bullet(..)) {
                     break;
                 }
-                self.require_whitespace_before();
+                self.require_whitespace_before(Strictness::Lenient, Rule::Value, Rule::RightAngle, Rule::AlignedList, at);
             }
             Ok(ParsedList { elements })
         }
@@ -788,7 +1129,7 @@ pub mod parser {
         /// ```
         fn parse_tabular_list(&mut self, elements: Vec<ParsedValue>) -> Result<ParsedList, ParseError> {
             let mut elements = elements;
-            if !matches!(self.t0, Reduced::Bar(..)) {
+                if !matches!(self.t0, Reduced::Bar(..)) {
                 return ParseError::token_expectation_error(&[Rule::Bar], self.t0, Rule::TabularList, self.t0.at());
             }
             loop {
@@ -814,7 +1155,9 @@ pub mod parser {
         /// ```
         fn parse_tagged_list(&mut self, elements: Vec<ParsedValue>) -> Result<ParsedList, ParseError> {
             let mut elements = elements;
+            let from = self.at();
             loop {
+                self.require_whitespace_before(Strictness::Strict, Rule::TaggedValue, Rule::TaggedValue, Rule::TaggedList, from);
                 let value = self.parse_tagged_value()?;
                 elements.push(value);
                 if !matches!(self.t0, Reduced::TaggedValueHeader(..)) {
@@ -834,7 +1177,7 @@ pub mod parser {
             let from = self.at();
             let tag = self.parse_tag()?;
             let mut arguments = if matches!(self.t0, Reduced::Colon(..)) {
-                self.parse_arguments()?
+                self.nest(from, |parser| parser.parse_arguments())?
             } else {
                 vec![]
             };
@@ -880,8 +1223,9 @@ pub mod parser {
             if !matches!(self.t0, Reduced::Colon(..)) {
                 return ParseError::token_expectation_error(&[Rule::Colon], self.t0, Rule::Arguments, self.t0.at());
             }
+            let from = self.at();
             loop {
-                self.require_no_whitespace_after();
+                self.require_no_whitespace_after(Strictness::Lenient, Rule::Colon, Rule::Argument, Rule::Arguments, from);
                 self.shift();
                 match self.t0 {
                     Reduced::String(.., s) => {
@@ -921,11 +1265,11 @@ pub mod parser {
         ///       | "<"<word>_<attributes> ">"
         ///       | "<"">"
         /// ```
-        fn parse_tag(&mut self) -> Result<Option<(Rc<str>, Vec<ParsedAttribute>)>, ParseError> {
+        fn parse_tag(&mut self) -> Result<Option<(SharedStr, Vec<ParsedAttribute>)>, ParseError> {
             if let Reduced::AngleBracket(from, _, fw, _, scope) | Reduced::TaggedValueHeader(from, _, fw, scope) = self.t0 {
                 self.shift();
-                let mut parser = Parser::new(scope, self.strings, self.errors, *fw, *from);
-                parser.require_no_whitespace_before();
+                let mut parser = self.nested(scope, *fw, after_open(*from))?;
+                parser.require_no_whitespace_before(Strictness::Lenient, Rule::AngularBracket, Rule::Name, Rule::Tag, *from);
                 if parser.is_end() {
                     return Ok(None);
                 }
@@ -940,11 +1284,13 @@ pub mod parser {
                 };
                 parser.shift();
                 let attributes = if matches!(parser.t0, Reduced::String(..) | Reduced::AssignmentHeader(..)) {
+                    parser.require_whitespace_before(Strictness::Standard, Rule::Name, Rule::Attributes, Rule::Tag, *from);
                     parser.parse_attributes()?
                 } else {
+                    parser.require_no_whitespace_before(Strictness::Standard, Rule::Name, Rule::Close, Rule::Tag, *from);
                     vec![]
                 };
-                parser.require_end();
+                parser.require_no_whitespace_after(Strictness::Lenient, Rule::Attributes, Rule::Close, Rule::Tag, *from);
                 Ok(Some((name, attributes)))
             } else {
                 return ParseError::token_expectation_error(&[Rule::AngularBracket], self.t0, Rule::Tag, self.at());
@@ -962,10 +1308,14 @@ pub mod parser {
         /// ```
         fn parse_attributes(&mut self) -> Result<Vec<ParsedAttribute>, ParseError> {
             let mut attributes = vec![];
+            let from = self.at();
             if !matches!(self.t0, Reduced::AssignmentHeader(..) | Reduced::String(..)) {
-                return ParseError::token_expectation_error(&[Rule::Attribute], self.t0, Rule::Attributes, self.at());
+                return ParseError::token_expectation_error(&[Rule::Attribute], self.t0, Rule::Attributes, from);
             }
             loop {
+                if !attributes.is_empty() && matches!(self.t0, Reduced::AssignmentHeader(..) | Reduced::String(..)) {
+                    self.require_whitespace_before(Strictness::Standard, Rule::Attribute, Rule::Attribute, Rule::Attributes, from);
+                }
                 let key = match self.t0 {
                     Reduced::String(_, _, _, t, key) | Reduced::AssignmentHeader(_, _, t, key) => {
                         if *t != StringType::Word {
@@ -977,6 +1327,9 @@ pub mod parser {
                 };
                 self.shift();
                 if matches!(self.t0, Reduced::Colon(..)) {
+                    let at = self.at_last();
+                    self.require_no_whitespace_before(Strictness::Standard, Rule::Word, Rule::Colon, Rule::Attribute, at);
+                    self.require_no_whitespace_after(Strictness::Standard, Rule::Colon, Rule::String, Rule::Attribute, at);
                     self.shift();
                     let value = self.parse_string()?;
                     attributes.push(ParsedAttribute(key, Some(value)));
@@ -998,7 +1351,7 @@ pub mod parser {
         fn parse_bracketed_construct(&mut self) -> Result<ParsedValue, ParseError> {
             if let Reduced::CurlyBracket(from, to, wi, _, scope) = self.t0 {
                 self.shift();
-                let mut parser = Parser::new(scope, self.strings, self.errors, *wi, *to);
+                let mut parser = self.nested(scope, *wi, *to)?;
                 let value = match parser.t0 {
                     Reduced::AssignmentHeader(..) | Reduced::CurlyHeader(..) | Reduced::SquareHeader(..) => {
                         let dictionary = parser.parse_dictionary()?;
@@ -1013,7 +1366,7 @@ pub mod parser {
                     }
                     _ => return ParseError::token_expectation_error(&[Rule::Value, Rule::Dictionary], parser.t0, Rule::Bracket, *from),
                 };
-                parser.require_end();
+                parser.require_no_whitespace_after(Strictness::Lenient, Rule::Value, Rule::Close, Rule::Bracket, *from);
                 Ok(value)
             } else {
                 return ParseError::token_expectation_error(&[Rule::BracketOpen], self.t0, Rule::Bracket, self.at());
@@ -1029,13 +1382,13 @@ pub mod parser {
         fn parse_bracketed_list(&mut self) -> Result<ParsedValue, ParseError> {
             if let Reduced::SquareBracket(from, to, fw, _, scope) = &self.t0 {
                 self.shift();
-                let mut parser = Parser::new(scope, self.strings, self.errors, *fw, *to);
+                let mut parser = self.nested(scope, *fw, *to)?;
                 let list = if !parser.is_end() {
                     parser.parse_list()?
                 } else {
                     ParsedList::empty()
                 };
-                parser.require_end();
+                parser.require_no_whitespace_after(Strictness::Lenient, Rule::List, Rule::Close, Rule::Square, *from);
                 Ok(ParsedValue::List(list, *from, *to))
             } else {
                 return ParseError::token_expectation_error(&[Rule::SquareOpen], self.t0, Rule::Square, self.at());
@@ -1049,7 +1402,7 @@ pub mod parser {
         ///          | <transcription>
         ///          | <text-block>
         /// ```
-        fn parse_string(&mut self) -> Result<Rc<str>, ParseError> {
+        fn parse_string(&mut self) -> Result<SharedStr, ParseError> {
             match self.t0 {
                 Reduced::String(.., text) => {
                     self.shift();
@@ -1062,7 +1415,7 @@ pub mod parser {
     }
 
     /// Construct a dictionary from entries and sections.
-    fn create_dictionary(sections: Vec<(Vec<Rc<str>>, Vec<ParsedEntry>)>, errors: &mut Vec<ParseError>, from: Position, to: Position) -> ParsedDictionary {
+    fn create_dictionary(sections: Vec<(Vec<SharedStr>, Vec<ParsedEntry>)>, errors: &mut Vec<ParseError>, from: Position, to: Position) -> ParsedDictionary {
         let mut dictionary = ParsedDictionary { entries: HashMap::new() };
         for (section_key, entries) in sections {
             let dictionary_reference = match resolve_dictionary(&mut dictionary, &section_key, from) {
@@ -1092,7 +1445,7 @@ pub mod parser {
         dictionary
     }
 
-    fn resolve_dictionary<'a>(root: &'a mut ParsedDictionary, key: &[Rc<str>], at: Position) -> Result<&'a mut ParsedDictionary, ParseError> {
+    fn resolve_dictionary<'a>(root: &'a mut ParsedDictionary, key: &[SharedStr], at: Position) -> Result<&'a mut ParsedDictionary, ParseError> {
         let mut dictionary_reference = root;
         for k in key {
             let entries = &mut dictionary_reference.entries;
@@ -1112,37 +1465,41 @@ pub mod parser {
         Ok(dictionary_reference)
     }
 
+    /// The position right after the opening character of a bracket, tag or
+    /// header.
+    pub(crate) fn after_open(at: Position) -> Position {
+        Position { index: at.index + 1, line: at.line, column: at.column + 1 }
+    }
+
     //// Errors
 
     impl Parser<'_> {
 
-        fn require_whitespace_after(&mut self) {
-            if !self.t0.has_whitespace_after() {
-                self.errors.push(ParseError::ExpectedWhitespace(self.at()))
+        /// Require whitespace between the current token and the next.
+        fn require_whitespace_after(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+            if self.strictness >= strictness && !self.t0.has_whitespace_after() {
+                self.errors.push(ParseError::ExpectedWhitespace(self.t0.end(), before, after, within, within_at))
             }
         }
 
-        fn require_no_whitespace_after(&mut self) {
-            if self.t0.has_whitespace_after() {
-                self.errors.push(ParseError::UnexpectedWhitespace(self.at()))//TODO at
+        /// Forbid whitespace between the current token and the next.
+        fn require_no_whitespace_after(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+            if self.strictness >= strictness && self.t0.has_whitespace_after() {
+                self.errors.push(ParseError::UnexpectedWhitespace(self.t0.end(), before, after, within, within_at))
             }
         }
 
-        fn require_whitespace_before(&mut self) {
-            if !self.whitespace_before {
-                self.errors.push(ParseError::ExpectedWhitespace(self.at_last()))
+        /// Require whitespace between the previous token and the current.
+        fn require_whitespace_before(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+            if self.strictness >= strictness && !self.whitespace_before && !self.line_break_before {
+                self.errors.push(ParseError::ExpectedWhitespace(self.last_end, before, after, within, within_at))
             }
         }
 
-        fn require_no_whitespace_before(&mut self) {
-            if self.whitespace_before {
-                self.errors.push(ParseError::UnexpectedWhitespace(self.at_last()))
-            }
-        }
-
-        fn require_end(&mut self) {
-            if self.t0.has_whitespace_after() {
-                self.errors.push(ParseError::UnexpectedWhitespace(self.at()))//TODO at
+        /// Forbid whitespace between the previous token and the current.
+        fn require_no_whitespace_before(&mut self, strictness: Strictness, before: Rule, after: Rule, within: Rule, within_at: Position) {
+            if self.strictness >= strictness && self.whitespace_before {
+                self.errors.push(ParseError::UnexpectedWhitespace(self.last_end, before, after, within, within_at))
             }
         }
 
@@ -1156,7 +1513,7 @@ pub mod parser {
 
     }
 
-    type ParsedKey = Vec<Rc<str>>;
+    type ParsedKey = Vec<SharedStr>;
 
     type ParsedEntry = (ParsedKey, ParsedValue);
 
@@ -1172,7 +1529,7 @@ pub mod parser {
 
     }
 
-    fn token_to_rule(token: &Reduced) -> Rule {
+    pub(crate) fn token_to_rule(token: &Reduced) -> Rule {
         match token {
             Reduced::String(..) => Rule::String,
             Reduced::Colon(..) => Rule::Colon,
@@ -1194,7 +1551,7 @@ pub mod parser {
 
     //// Parsing errors
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq)]
     pub enum Rule {
         String,
         List,
@@ -1228,6 +1585,8 @@ pub mod parser {
         TaggedList,
         Header,
         AbsoluteDictionary,
+        AlignedDictionary,
+        Section,
         Entry,
         Text,
         Transcription,
@@ -1267,17 +1626,23 @@ pub mod parser {
         /// The key at X is already assigned a value.
         KeyAlreadyAssigned(Position),
         /// Expected whitespace at X between Y and Z in W at V.
-        // ExpectedWhitespace(Position, Rule, Rule, Rule, Position),
-        ExpectedWhitespace(Position),
+        ExpectedWhitespace(Position, Rule, Rule, Rule, Position),
         /// Unexpected whitespace at X between Y and Z in W at V.
-        //UnexpectedWhitespace(Position, Rule, Rule, Rule, Position),
-        UnexpectedWhitespace(Position),
+        UnexpectedWhitespace(Position, Rule, Rule, Rule, Position),
         /// Attribute name at X must be a word but found Y.
         AttributeMustBeWord(Position, Rule),
         /// Tag name at X must be a word but found Y.
         TagNameMustBeWord(Position, Rule),
         /// Expected end but found X at Y.
         ExpectedEnd(Rule, Position),
+        /// Document of X bytes is larger than Y bytes.
+        DocumentTooLarge(usize, usize),
+        /// Construct at X is nested deeper than Y.
+        TooDeep(Position, usize),
+        /// Token at X exceeds the maximum of Y tokens.
+        TooManyTokens(Position, usize),
+        /// String at X is longer than Y bytes.
+        StringTooLong(Position, usize),
     }
 
     impl Debug for ParseError {
@@ -1315,11 +1680,11 @@ pub mod parser {
             ParseError::KeyAlreadyAssigned(at) => {
                 format!("Key at {}:{} is already assigned a value.", at.line, at.column)
             }
-            ParseError::ExpectedWhitespace(at) => {
-                format!("Expected whitespace at {}:{} between {} and {} within {} at {}.", at.line, at.column, "-", "-", "-", "-") //TODO
+            ParseError::ExpectedWhitespace(at, before, after, within, within_at) => {
+                format!("Expected whitespace at {}:{} between ⟨{:?}⟩ and ⟨{:?}⟩ within ⟨{:?}⟩ at {}:{}.", at.line, at.column, before, after, within, within_at.line, within_at.column)
             }
-            ParseError::UnexpectedWhitespace(at) => {
-                format!("Unexpected whitespace at {}:{} between {} and {} within {} at {}.", at.line, at.column, "-", "-", "-", "-") //TODO
+            ParseError::UnexpectedWhitespace(at, before, after, within, within_at) => {
+                format!("Unexpected whitespace at {}:{} between ⟨{:?}⟩ and ⟨{:?}⟩ within ⟨{:?}⟩ at {}:{}.", at.line, at.column, before, after, within, within_at.line, within_at.column)
             }
             ParseError::AttributeMustBeWord(at, found) => {
                 format!("Attribute name at {}:{} must be a word but found {:?}.", at.line, at.column, found)
@@ -1333,6 +1698,18 @@ pub mod parser {
             ParseError::ExpectedEnd(found, at) => {
                 format!("Expected end but found {:?} at {}:{}.", found, at.line, at.column)
             }
+            ParseError::DocumentTooLarge(size, max) => {
+                format!("Document of {} bytes is larger than the maximum of {} bytes.", size, max)
+            }
+            ParseError::TooDeep(at, max) => {
+                format!("Nesting at {}:{} is deeper than the maximum depth of {}.", at.line, at.column, max)
+            }
+            ParseError::TooManyTokens(at, max) => {
+                format!("Token at {}:{} exceeds the maximum of {} tokens.", at.line, at.column, max)
+            }
+            ParseError::StringTooLong(at, max) => {
+                format!("String at {}:{} is longer than the maximum of {} bytes.", at.line, at.column, max)
+            }
         }
     }
 
@@ -1365,8 +1742,9 @@ pub mod parser {
 
 pub mod reducer {
 
-    use std::slice::Iter;
-    use crate::lex::Token;
+    use std::borrow::Cow;
+    use crate::lex::{LexError, Token};
+    use crate::parse::ParseOptions;
     use crate::parse::parser::Rule;
     use crate::pdm::Position;
 
@@ -1385,21 +1763,21 @@ pub mod reducer {
     /// - AngleBracket → TaggedValueHeader
     /// - String → AssignmentHeader
     #[derive(PartialEq, Eq, Clone)]
-    pub enum Reduced {
-        String(Position, Position, bool, StringType, String),
+    pub enum Reduced<'a> {
+        String(Position, Position, bool, StringType, Cow<'a, str>),
         Colon(Position, bool),
         Semicolon(Position, bool),
         Bar(Position, bool),
         Tilde(Position, bool),
         Bullet(Position, bool),
         MapArrow(Position, bool),
-        CurlyBracket(Position, Position, bool, bool, Vec<Reduced>),
-        SquareBracket(Position, Position, bool, bool, Vec<Reduced>),
-        AngleBracket(Position, Position, bool, bool, Vec<Reduced>),
-        CurlyHeader(Position, Position, bool, Vec<Reduced>),
-        SquareHeader(Position, Position, bool, Vec<Reduced>),
-        TaggedValueHeader(Position, Position, bool, Vec<Reduced>),
-        AssignmentHeader(Position, Position, StringType, String),
+        CurlyBracket(Position, Position, bool, bool, Vec<Reduced<'a>>),
+        SquareBracket(Position, Position, bool, bool, Vec<Reduced<'a>>),
+        AngleBracket(Position, Position, bool, bool, Vec<Reduced<'a>>),
+        CurlyHeader(Position, Position, bool, Vec<Reduced<'a>>),
+        SquareHeader(Position, Position, bool, Vec<Reduced<'a>>),
+        TaggedValueHeader(Position, Position, bool, Vec<Reduced<'a>>),
+        AssignmentHeader(Position, Position, StringType, Cow<'a, str>),
         End(Position),
     }
 
@@ -1413,7 +1791,7 @@ pub mod reducer {
         Word, Transcription, TextBlock
     }
 
-    impl Reduced {
+    impl Reduced<'_> {
         pub fn to_type(&self) -> Rule {
             match self {
                 Reduced::String(..) => Rule::String,
@@ -1434,6 +1812,14 @@ pub mod reducer {
             }
         }
 
+        /// Check if this is a transcription ended by a line break.
+        ///
+        /// The line break is not whitespace within text, but still separates
+        /// the transcription from the constructs after it.
+        pub(crate) fn is_line_transcription(&self) -> bool {
+            matches!(self, Reduced::String(at, to, _, StringType::Transcription, _) if to.line > at.line)
+        }
+
         pub(crate) fn has_whitespace_after(&self) -> bool {
             match self {
                 Reduced::String(_, _, wa, _, ..) => *wa,
@@ -1499,60 +1885,166 @@ pub mod reducer {
             }
         }
 
+        /// The position right after the token.
+        pub(crate) fn end(&self) -> Position {
+            match self {
+                Reduced::Colon(at, ..) | Reduced::Semicolon(at, ..) | Reduced::Bar(at, ..) | Reduced::Tilde(at, ..) | Reduced::Bullet(at, ..) => {
+                    Position { index: at.index + 1, line: at.line, column: at.column + 1 }
+                }
+                Reduced::MapArrow(at, ..) => Position { index: at.index + 2, line: at.line, column: at.column + 2 },
+                _ => self.to(),
+            }
+        }
+
     }
 
     pub enum ReduceError {
         /// Found unexpected closing X at Y in Z scope at W.
-        MismatchedClose(ScopeType, Position, ScopeType, Position)
+        MismatchedClose(ScopeType, Position, ScopeType, Position),
+        /// The tokens could not be lexed.
+        LexError(LexError),
+        /// Bracket at X is nested deeper than Y.
+        TooDeep(Position, usize),
+        /// Token at X exceeds the maximum of Y tokens.
+        TooManyTokens(Position, usize),
+        /// String at X is longer than Y bytes.
+        StringTooLong(Position, usize),
     }
 
-    pub struct Reducer<'a> {
-        stream: Iter<'a, Token>,
-        t: [&'a Token; 4],
+    /// Reduces tokens as they are lexed.
+    pub struct Reducer<'a, I: Iterator<Item = Result<Token<'a>, LexError>>> {
+        stream: I,
+        t: [Token<'a>; 4],
         /// Previous token.
-        previous: &'a Token,
+        previous: Token<'a>,
+        /// Error reading the tokens. The stream ends at the first error.
+        error: Option<ReduceError>,
+        options: ParseOptions,
+        /// Number of tokens read.
+        count: usize,
+        /// Number of open brackets.
+        depth: usize,
+        /// Position of the first token.
+        start: Position,
     }
 
-    impl<'a> Reducer<'a> {
-        pub fn new(tokens: &'a Vec<Token>) -> Self {
+    impl<'a, I: Iterator<Item = Result<Token<'a>, LexError>>> Reducer<'a, I> {
+        pub fn new(tokens: I) -> Self {
+            Self::with_options(tokens, ParseOptions::default())
+        }
+
+        pub fn with_options(tokens: I, options: ParseOptions) -> Self {
             const P: Token = Token::End(Position { index: 0, line: 0, column: 0 });
             let mut r = Self {
-                stream: tokens.iter(),
-                t: [&P, &P, &P, &P],
-                previous: &P,
+                stream: tokens,
+                t: [P, P, P, P],
+                previous: P,
+                error: None,
+                options,
+                count: 0,
+                depth: 0,
+                start: P.at(),
             };
             r.shift(); r.shift(); r.shift(); r.shift();
+            r.start = r.t[0].at();
             r
         }
 
         fn shift(&mut self) {
-            self.previous = self.t[0];
-            self.t[0] = self.t[1];
-            self.t[1] = self.t[2];
-            self.t[2] = self.t[3];
-            self.t[3] = self.stream.next().unwrap_or(self.t[3]);
+            let next = match self.stream.next() {
+                Some(Ok(token)) if self.error.is_none() => {
+                    self.count += 1;
+                    if let Some(error) = self.exceeded_limit(&token) {
+                        self.error = Some(error);
+                        Token::End(token.at())
+                    } else {
+                        token
+                    }
+                }
+                Some(Err(error)) if self.error.is_none() => {
+                    self.error = Some(ReduceError::LexError(error));
+                    Token::End(self.t[3].at())
+                }
+                _ => self.t[3].clone(),
+            };
+            self.t.rotate_left(1);
+            self.previous = std::mem::replace(&mut self.t[3], next);
+        }
+
+        fn exceeded_limit(&self, token: &Token) -> Option<ReduceError> {
+            if self.count > self.options.max_tokens {
+                return Some(ReduceError::TooManyTokens(token.at(), self.options.max_tokens));
+            };
+            match token {
+                Token::Word(at, string) | Token::Transcription(at, string) | Token::TextBlock(at, string) if string.len() > self.options.max_string_length => {
+                    Some(ReduceError::StringTooLong(*at, self.options.max_string_length))
+                }
+                _ => None,
+            }
         }
     }
 
-    impl Reducer<'_> {
-        pub fn reduce(&mut self) -> Result<Vec<Reduced>, ReduceError> {
-            self.reduce_scope(ScopeType::Open, self.t[0].at())
+    impl<'a, I: Iterator<Item = Result<Token<'a>, LexError>>> Reducer<'a, I> {
+        pub fn reduce(&mut self) -> Result<Vec<Reduced<'a>>, ReduceError> {
+            let reduced = self.reduce_scope(ScopeType::Open, self.start);
+            match self.error.take() {
+                Some(error) => Err(error),
+                None => reduced,
+            }
+        }
+
+        /// Reduce the next top-level token. Returns the end token at the end
+        /// of the document.
+        ///
+        /// Reduce no more tokens after an error.
+        pub fn reduce_next(&mut self) -> Result<Reduced<'a>, ReduceError> {
+            let reduced = self.reduce_token(&ScopeType::Open, self.start);
+            match self.error.take() {
+                Some(error) => Err(error),
+                None => reduced,
+            }
         }
 
-        fn reduce_bracket(&mut self, scope_at: Position) -> Result<Vec<Reduced>, ReduceError> {
+        fn reduce_bracket(&mut self, scope_at: Position) -> Result<Vec<Reduced<'a>>, ReduceError> {
             self.reduce_scope(ScopeType::Curly, scope_at)
         }
 
-        fn reduce_square(&mut self, scope_at: Position) -> Result<Vec<Reduced>, ReduceError> {
+        fn reduce_square(&mut self, scope_at: Position) -> Result<Vec<Reduced<'a>>, ReduceError> {
             self.reduce_scope(ScopeType::Square, scope_at)
         }
 
-        fn reduce_angle(&mut self, scope_at: Position) -> Result<Vec<Reduced>, ReduceError> {
+        fn reduce_angle(&mut self, scope_at: Position) -> Result<Vec<Reduced<'a>>, ReduceError> {
             self.reduce_scope(ScopeType::Angle, scope_at)
         }
 
-        fn reduce_scope(&mut self, scope_type: ScopeType, scope_at: Position) -> Result<Vec<Reduced>, ReduceError> {
+        fn reduce_scope(&mut self, scope_type: ScopeType, scope_at: Position) -> Result<Vec<Reduced<'a>>, ReduceError> {
+            if scope_type == ScopeType::Open {
+                return self.reduce_tokens(scope_type, scope_at);
+            };
+            if self.depth >= self.options.max_depth {
+                return Err(ReduceError::TooDeep(scope_at, self.options.max_depth));
+            };
+            self.depth += 1;
+            let tokens = self.reduce_tokens(scope_type, scope_at);
+            self.depth -= 1;
+            tokens
+        }
+
+        fn reduce_tokens(&mut self, scope_type: ScopeType, scope_at: Position) -> Result<Vec<Reduced<'a>>, ReduceError> {
             let mut tokens = vec![];
+            loop {
+                let token = self.reduce_token(&scope_type, scope_at)?;
+                let end = matches!(token, Reduced::End(..));
+                tokens.push(token);
+                if end {
+                    return Ok(tokens);
+                }
+            }
+        }
+
+        /// Reduce the next token of a scope. Returns the end token at the end
+        /// of the scope.
+        fn reduce_token(&mut self, scope_type: &ScopeType, scope_at: Position) -> Result<Reduced<'a>, ReduceError> {
             loop {
                 match self.t[0].clone() {
                     Token::Whitespace(_) => {
@@ -1571,79 +2063,75 @@ pub mod reducer {
                         let colon_after = matches!(self.t[0], Token::Colon(..));
                         let to = self.t[0].at();
                         if !colon_before && colon_after {
-                            let header = Reduced::AssignmentHeader(at, to, string_type, string.clone());
-                            tokens.push(header);
+                            let header = Reduced::AssignmentHeader(at, to, string_type, string);
+                            return Ok(header);
                         } else {
                             let whitespace_after = matches!(self.t[0], Token::Whitespace(..));
-                            let word = Reduced::String(at, to, whitespace_after, string_type, string.clone());
-                            tokens.push(word);
+                            let word = Reduced::String(at, to, whitespace_after, string_type, string);
+                            return Ok(word);
                         }
                     }
                     Token::Colon(at) => {
-                        let following = self.t[1];
+                        let following = &self.t[1];
                         let word = Reduced::Colon(at, Self::is_whitespace(following));
-                        tokens.push(word);
                         self.shift();
+                        return Ok(word);
                     }
                     Token::Semicolon(at) => {
-                        let following = self.t[1];
+                        let following = &self.t[1];
                         let word = Reduced::Semicolon(at, Self::is_whitespace(following));
-                        tokens.push(word);
                         self.shift();
+                        return Ok(word);
                     }
                     Token::Bar(at) => {
-                        let following = self.t[1];
+                        let following = &self.t[1];
                         let word = Reduced::Bar(at, Self::is_whitespace(following));
-                        tokens.push(word);
                         self.shift();
+                        return Ok(word);
                     }
                     Token::Tilde(at) => {
-                        let following = self.t[1];
+                        let following = &self.t[1];
                         let word = Reduced::Tilde(at, Self::is_whitespace(following));
-                        tokens.push(word);
                         self.shift();
+                        return Ok(word);
                     }
                     Token::DoubleArrow(at) => {
-                        let following = self.t[1];
+                        let following = &self.t[1];
                         let arrow = Reduced::MapArrow(at, Self::is_whitespace(following));
-                        tokens.push(arrow);
                         self.shift();
+                        return Ok(arrow);
                     }
                     Token::RightAngle(at) => {
-                        if scope_type == ScopeType::Angle {
-                            tokens.push(Reduced::End(at));
-                            break;
+                        if *scope_type == ScopeType::Angle {
+                            return Ok(Reduced::End(at));
                         } else {
                             // An angular scope can never contain a right angle.
                             // This lets us distinguish these cases.
-                            let following = self.t[1];
+                            let following = &self.t[1];
                             let bullet = Reduced::Bullet(at, Self::is_whitespace(following));
-                            tokens.push(bullet);
                             self.shift();
+                            return Ok(bullet);
                         }
                     }
                     Token::RightBracket(at) => {
-                        if scope_type == ScopeType::Curly {
-                            tokens.push(Reduced::End(at));
-                            break;
+                        if *scope_type == ScopeType::Curly {
+                            return Ok(Reduced::End(at));
                         } else {
-                            return Err(ReduceError::MismatchedClose(ScopeType::Curly, at, scope_type, scope_at));
+                            return Err(ReduceError::MismatchedClose(ScopeType::Curly, at, scope_type.clone(), scope_at));
                         }
                     }
                     Token::RightSquare(at) => {
-                        if scope_type == ScopeType::Square {
-                            tokens.push(Reduced::End(at));
-                            break;
+                        if *scope_type == ScopeType::Square {
+                            return Ok(Reduced::End(at));
                         } else {
-                            return Err(ReduceError::MismatchedClose(ScopeType::Square, at, scope_type, scope_at));
+                            return Err(ReduceError::MismatchedClose(ScopeType::Square, at, scope_type.clone(), scope_at));
                         }
                     }
                     Token::End(at) => {
-                        if scope_type == ScopeType::Open {
-                            tokens.push(Reduced::End(at));
-                            break;
+                        if *scope_type == ScopeType::Open {
+                            return Ok(Reduced::End(at));
                         } else {
-                            return Err(ReduceError::MismatchedClose(ScopeType::Open, at, scope_type, scope_at));
+                            return Err(ReduceError::MismatchedClose(ScopeType::Open, at, scope_type.clone(), scope_at));
                         }
                     }
                     Token::LeftBracket(at) => {
@@ -1656,11 +2144,11 @@ pub mod reducer {
                         let to = self.t[0].at();
                         if !colon_before && colon_after {
                             let header = Reduced::CurlyHeader(at, to, initial_whitespace, scope);
-                            tokens.push(header);
+                            return Ok(header);
                         } else {
                             let whitespace_after = matches!(self.t[0], Token::Whitespace(..));
                             let bracket = Reduced::CurlyBracket(at, to, initial_whitespace, whitespace_after, scope);
-                            tokens.push(bracket);
+                            return Ok(bracket);
                         }
                     }
                     Token::LeftSquare(at) => {
@@ -1673,11 +2161,11 @@ pub mod reducer {
                         let to = self.t[0].at();
                         if !colon_before && colon_after {
                             let header = Reduced::SquareHeader(at, to, initial_whitespace, scope);
-                            tokens.push(header);
+                            return Ok(header);
                         } else {
                             let whitespace_after = matches!(self.t[0], Token::Whitespace(..));
                             let square = Reduced::SquareBracket(at, to, initial_whitespace, whitespace_after, scope);
-                            tokens.push(square);
+                            return Ok(square);
                         }
                     }
                     Token::LeftAngle(at) => {
@@ -1691,16 +2179,15 @@ pub mod reducer {
                         let to = self.t[0].at();
                         if !colon_before && colon_after && whitespace_after_after {
                             let header = Reduced::TaggedValueHeader(at, to, initial_whitespace, scope);
-                            tokens.push(header);
+                            return Ok(header);
                         } else {
                             let whitespace_after = matches!(self.t[0], Token::Whitespace(..));
                             let square = Reduced::AngleBracket(at, to, initial_whitespace, whitespace_after, scope);
-                            tokens.push(square);
+                            return Ok(square);
                         }
                     }
                 }
             }
-            Ok(tokens)
         }
 
         fn is_whitespace(token: &Token) -> bool {
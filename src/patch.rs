@@ -0,0 +1,444 @@
+//! Patches for documents.
+//!
+//! A patch is a sequence of changes, as produced by [crate::diff], that can be
+//! applied to another document. Each change records the value it expects to
+//! find, so that a patch applied to a document that differs from its base
+//! reports conflicts instead of overwriting values.
+//!
+//! Patches apply to the parsed data model. Borrowed and arena values are
+//! converted with their `to_parsed` method first.
+//!
+//! A patch is written as a list document in tagged notation:
+//!
+//! ```text
+//! <replace>: {path: [article; title]; old: Aluminium; new: Aluminum}
+//! <insert>: {path: [article; tags; <index>:2]; value: light}
+//! ```
+
+use std::fmt::{Debug, Formatter};
+use crate::{Dictionary, Tagged, Value};
+use crate::diff::{Change, ChangeKind, equal, path_to_string, Step};
+use crate::format::{format_key, format_text, format_value};
+use crate::pdm::{ParsedAttribute, ParsedDictionary, ParsedList, ParsedTuple, ParsedValue, Position, SharedStr};
+
+//// Patch
+
+/// A patch.
+#[derive(Clone)]
+pub struct Patch {
+    pub changes: Vec<Change>,
+}
+
+impl Patch {
+    pub fn new(changes: Vec<Change>) -> Self {
+        Patch { changes }
+    }
+}
+
+impl From<Vec<Change>> for Patch {
+    fn from(changes: Vec<Change>) -> Self {
+        Patch { changes }
+    }
+}
+
+//// Apply
+
+/// Apply a patch to a value.
+///
+/// Either all changes are applied, or the value is left untouched and the
+/// conflicts are returned.
+pub fn apply_patch(value: &mut ParsedValue, patch: &Patch) -> Result<(), Vec<Conflict>> {
+    let mut patched = value.clone();
+    let mut conflicts = vec![];
+    for change in &patch.changes {
+        if let Err(conflict) = apply_change(&mut patched, change) {
+            conflicts.push(conflict);
+        }
+    }
+    if conflicts.is_empty() {
+        *value = patched;
+        Ok(())
+    } else {
+        Err(conflicts)
+    }
+}
+
+/// Apply a patch to a dictionary document.
+pub fn apply_patch_to_dictionary(dictionary: &mut ParsedDictionary, patch: &Patch) -> Result<(), Vec<Conflict>> {
+    const AT: Position = Position { index: 0, line: 0, column: 0 };
+    let mut value = ParsedValue::Dictionary(std::mem::replace(dictionary, ParsedDictionary::empty()), AT, AT);
+    let result = apply_patch(&mut value, patch);
+    match value {
+        ParsedValue::Dictionary(d, ..) => {
+            *dictionary = d;
+            result
+        }
+        _ => Err(vec![Conflict::ValueChanged(vec![])]),
+    }
+}
+
+/// Apply a patch to a list document.
+pub fn apply_patch_to_list(list: &mut ParsedList, patch: &Patch) -> Result<(), Vec<Conflict>> {
+    const AT: Position = Position { index: 0, line: 0, column: 0 };
+    let mut value = ParsedValue::List(std::mem::replace(list, ParsedList::empty()), AT, AT);
+    let result = apply_patch(&mut value, patch);
+    match value {
+        ParsedValue::List(l, ..) => {
+            *list = l;
+            result
+        }
+        _ => Err(vec![Conflict::ValueChanged(vec![])]),
+    }
+}
+
+fn apply_change(root: &mut ParsedValue, change: &Change) -> Result<(), Conflict> {
+    let path = &change.path;
+    match &change.kind {
+        ChangeKind::Added(value) => {
+            let (dictionary, key) = resolve_entry(root, path)?;
+            if dictionary.entries.contains_key(key) {
+                return Err(Conflict::KeyExists(path.clone()));
+            }
+            dictionary.entries.insert(key.clone(), value.clone());
+        }
+        ChangeKind::Removed(old) => {
+            let (dictionary, key) = resolve_entry(root, path)?;
+            match dictionary.get(key) {
+                None => return Err(Conflict::MissingPath(path.clone())),
+                Some(value) if !equal(value, old) => return Err(Conflict::ValueChanged(path.clone())),
+                Some(..) => {}
+            }
+            dictionary.entries.remove(key);
+        }
+        ChangeKind::Changed(old, new) => {
+            let value = resolve(root, path)?;
+            if !equal(value, old) {
+                return Err(Conflict::ValueChanged(path.clone()));
+            }
+            *value = new.clone();
+        }
+        ChangeKind::Inserted(value) => {
+            let (list, index) = resolve_element(root, path)?;
+            if index > list.elements.len() {
+                return Err(Conflict::MissingPath(path.clone()));
+            }
+            list.elements.insert(index, value.clone());
+        }
+        ChangeKind::Deleted(_, old) => {
+            let (list, index) = resolve_element(root, path)?;
+            match list.elements.get(index) {
+                None => return Err(Conflict::MissingPath(path.clone())),
+                Some(value) if !equal(value, old) => return Err(Conflict::ValueChanged(path.clone())),
+                Some(..) => {}
+            }
+            list.elements.remove(index);
+        }
+        ChangeKind::Renamed(old, new) => {
+            let tag = resolve_tagged(root, path)?;
+            if tag.name != *old {
+                return Err(Conflict::NameChanged(path.clone()));
+            }
+            tag.name = new.clone();
+        }
+        ChangeKind::AttributeAdded(key, value) => {
+            let tag = resolve_tagged(root, path)?;
+            if tag.attributes.iter().any(|ParsedAttribute(k, _)| k == key) {
+                return Err(Conflict::AttributeChanged(path.clone(), key.clone()));
+            }
+            tag.attributes.push(ParsedAttribute(key.clone(), value.clone()));
+        }
+        ChangeKind::AttributeRemoved(key, old) => {
+            let tag = resolve_tagged(root, path)?;
+            match tag.attributes.iter().position(|ParsedAttribute(k, _)| k == key) {
+                Some(i) if tag.attributes[i].1 == *old => {
+                    tag.attributes.remove(i);
+                }
+                _ => return Err(Conflict::AttributeChanged(path.clone(), key.clone())),
+            }
+        }
+        ChangeKind::AttributeChanged(key, old, new) => {
+            let tag = resolve_tagged(root, path)?;
+            match tag.attributes.iter_mut().find(|ParsedAttribute(k, _)| k == key) {
+                Some(attribute) if attribute.1 == *old => attribute.1 = new.clone(),
+                _ => return Err(Conflict::AttributeChanged(path.clone(), key.clone())),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find the value at a path.
+///
+/// A tag step only passes through a tagged value with the same tag name.
+fn resolve<'a>(root: &'a mut ParsedValue, path: &[Step]) -> Result<&'a mut ParsedValue, Conflict> {
+    let missing = || Conflict::MissingPath(path.to_vec());
+    let mut value = root;
+    for (i, step) in path.iter().enumerate() {
+        value = match (step, value) {
+            (Step::Key(key), ParsedValue::Dictionary(d, ..)) => d.entries.get_mut(key).ok_or_else(missing)?,
+            (Step::Index(index), ParsedValue::List(l, ..)) => l.elements.get_mut(*index).ok_or_else(missing)?,
+            (Step::Element(0), ParsedValue::Tuple(ParsedTuple::Single(v), ..)) => v,
+            (Step::Element(index), ParsedValue::Tuple(ParsedTuple::Multiple(vs), ..)) => vs.get_mut(*index).ok_or_else(missing)?,
+            (Step::Tag(name), ParsedValue::Tagged(t, ..)) => {
+                if t.name != *name {
+                    return Err(Conflict::NameChanged(path[..i].to_vec()));
+                }
+                &mut t.value
+            }
+            _ => return Err(missing()),
+        };
+    }
+    Ok(value)
+}
+
+/// Find the dictionary containing the entry at a path.
+fn resolve_entry<'a, 'b>(root: &'a mut ParsedValue, path: &'b [Step]) -> Result<(&'a mut ParsedDictionary, &'b SharedStr), Conflict> {
+    if let Some((Step::Key(key), parent)) = path.split_last() {
+        if let ParsedValue::Dictionary(dictionary, ..) = resolve(root, parent)? {
+            return Ok((dictionary, key));
+        }
+    }
+    Err(Conflict::MissingPath(path.to_vec()))
+}
+
+/// Find the list containing the element at a path.
+fn resolve_element<'a>(root: &'a mut ParsedValue, path: &[Step]) -> Result<(&'a mut ParsedList, usize), Conflict> {
+    if let Some((Step::Index(index), parent)) = path.split_last() {
+        if let ParsedValue::List(list, ..) = resolve(root, parent)? {
+            return Ok((list, *index));
+        }
+    }
+    Err(Conflict::MissingPath(path.to_vec()))
+}
+
+/// Find the tagged value at a path.
+fn resolve_tagged<'a>(root: &'a mut ParsedValue, path: &[Step]) -> Result<&'a mut crate::pdm::ParsedTaggedValue, Conflict> {
+    match resolve(root, path)? {
+        ParsedValue::Tagged(tag, ..) => Ok(tag),
+        _ => Err(Conflict::ValueChanged(path.to_vec())),
+    }
+}
+
+//// Conflicts
+
+/// A change that could not be applied.
+#[derive(Clone)]
+pub enum Conflict {
+    /// No value at path X.
+    MissingPath(Vec<Step>),
+    /// Key at X is already assigned a value.
+    KeyExists(Vec<Step>),
+    /// Value at X differs from the base value of the patch.
+    ValueChanged(Vec<Step>),
+    /// Tag at X has a different name than in the base of the patch.
+    NameChanged(Vec<Step>),
+    /// Attribute Y of the tag at X differs from the base of the patch.
+    AttributeChanged(Vec<Step>, SharedStr),
+}
+
+impl Debug for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", conflict_to_string(self))
+    }
+}
+
+pub fn conflict_to_string(conflict: &Conflict) -> String {
+    match conflict {
+        Conflict::MissingPath(path) => {
+            format!("No value at {}.", path_to_string(path))
+        }
+        Conflict::KeyExists(path) => {
+            format!("Key {} is already assigned a value.", path_to_string(path))
+        }
+        Conflict::ValueChanged(path) => {
+            format!("Value at {} differs from the base of the patch.", path_to_string(path))
+        }
+        Conflict::NameChanged(path) => {
+            format!("Tag at {} has a different name than in the base of the patch.", path_to_string(path))
+        }
+        Conflict::AttributeChanged(path, key) => {
+            format!("Attribute {} of the tag at {} differs from the base of the patch.", key, path_to_string(path))
+        }
+    }
+}
+
+//// Format
+
+/// Write a patch as a list document.
+pub fn format_patch(patch: &Patch) -> String {
+    let mut output = String::new();
+    for change in &patch.changes {
+        let path = format_path(&change.path);
+        let line = match &change.kind {
+            ChangeKind::Added(value) => {
+                format!("<add>: {{path: {}; value: {}}}", path, format_value(value))
+            }
+            ChangeKind::Removed(old) => {
+                format!("<remove>: {{path: {}; old: {}}}", path, format_value(old))
+            }
+            ChangeKind::Changed(old, new) => {
+                format!("<replace>: {{path: {}; old: {}; new: {}}}", path, format_value(old), format_value(new))
+            }
+            ChangeKind::Inserted(value) => {
+                format!("<insert>: {{path: {}; value: {}}}", path, format_value(value))
+            }
+            ChangeKind::Deleted(_, old) => {
+                format!("<delete>: {{path: {}; old: {}}}", path, format_value(old))
+            }
+            ChangeKind::Renamed(old, new) => {
+                format!("<rename>: {{path: {}; old: {}; new: {}}}", path, format_key(old), format_key(new))
+            }
+            ChangeKind::AttributeAdded(key, value) => {
+                format!("<add-attribute>: {{path: {}; key: {}{}}}", path, format_key(key), format_attribute("value", value))
+            }
+            ChangeKind::AttributeRemoved(key, old) => {
+                format!("<remove-attribute>: {{path: {}; key: {}{}}}", path, format_key(key), format_attribute("old", old))
+            }
+            ChangeKind::AttributeChanged(key, old, new) => {
+                format!("<change-attribute>: {{path: {}; key: {}{}{}}}", path, format_key(key), format_attribute("old", old), format_attribute("new", new))
+            }
+        };
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output
+}
+
+fn format_path(path: &[Step]) -> String {
+    let mut string = String::from("[");
+    for (i, step) in path.iter().enumerate() {
+        if i != 0 {
+            string.push_str("; ");
+        }
+        match step {
+            Step::Key(key) => string.push_str(&format_text(key)),
+            Step::Index(index) => string.push_str(&format!("<index>:{}", index)),
+            Step::Element(index) => string.push_str(&format!("<element>:{}", index)),
+            Step::Tag(name) => string.push_str(&format!("<tag>:{}", format_key(name))),
+        }
+    }
+    string.push(']');
+    string
+}
+
+fn format_attribute(field: &str, value: &Option<SharedStr>) -> String {
+    match value {
+        Some(value) => format!("; {}: {}", field, format_key(value)),
+        None => String::new(),
+    }
+}
+
+/// Read a patch from a list document.
+pub fn read_patch(list: &ParsedList) -> Result<Patch, PatchFormatError> {
+    let mut changes = vec![];
+    for element in &list.elements {
+        let at = element.from();
+        let tag = element.as_tagged().ok_or(PatchFormatError::InvalidOperation(at))?;
+        let fields = tag.get().as_dictionary().ok_or(PatchFormatError::InvalidOperation(at))?;
+        let path = read_path(field(fields, "path", at)?)?;
+        let kind = match tag.name() {
+            "add" => ChangeKind::Added(field(fields, "value", at)?.clone()),
+            "remove" => ChangeKind::Removed(field(fields, "old", at)?.clone()),
+            "replace" => ChangeKind::Changed(field(fields, "old", at)?.clone(), field(fields, "new", at)?.clone()),
+            "insert" => ChangeKind::Inserted(field(fields, "value", at)?.clone()),
+            "delete" => {
+                let index = match path.last() {
+                    Some(Step::Index(index)) => *index,
+                    _ => return Err(PatchFormatError::InvalidField(at, "path")),
+                };
+                ChangeKind::Deleted(index, field(fields, "old", at)?.clone())
+            }
+            "rename" => ChangeKind::Renamed(text_field(fields, "old", at)?, text_field(fields, "new", at)?),
+            "add-attribute" => ChangeKind::AttributeAdded(text_field(fields, "key", at)?, optional_text_field(fields, "value", at)?),
+            "remove-attribute" => ChangeKind::AttributeRemoved(text_field(fields, "key", at)?, optional_text_field(fields, "old", at)?),
+            "change-attribute" => ChangeKind::AttributeChanged(
+                text_field(fields, "key", at)?,
+                optional_text_field(fields, "old", at)?,
+                optional_text_field(fields, "new", at)?,
+            ),
+            _ => return Err(PatchFormatError::UnknownOperation(at)),
+        };
+        changes.push(Change { path, kind, before: None, after: None });
+    }
+    Ok(Patch { changes })
+}
+
+/// Parse a patch document string.
+#[cfg(feature = "parse")]
+pub fn parse_patch_str(document: &str) -> Result<Patch, Vec<PatchFormatError>> {
+    match crate::parse::parse_list_str(document) {
+        Ok(list) => read_patch(&list).map_err(|e| vec![e]),
+        Err(errors) => Err(errors.into_iter().map(PatchFormatError::ParseError).collect()),
+    }
+}
+
+fn field<'a>(fields: &'a ParsedDictionary, key: &'static str, at: Position) -> Result<&'a ParsedValue, PatchFormatError> {
+    fields.get(key).ok_or(PatchFormatError::MissingField(at, key))
+}
+
+fn text_field(fields: &ParsedDictionary, key: &'static str, at: Position) -> Result<SharedStr, PatchFormatError> {
+    match field(fields, key, at)? {
+        ParsedValue::Text(text, ..) => Ok(text.str.clone()),
+        value => Err(PatchFormatError::InvalidField(value.from(), key)),
+    }
+}
+
+fn optional_text_field(fields: &ParsedDictionary, key: &'static str, at: Position) -> Result<Option<SharedStr>, PatchFormatError> {
+    if fields.get(key).is_some() {
+        Ok(Some(text_field(fields, key, at)?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_path(value: &ParsedValue) -> Result<Vec<Step>, PatchFormatError> {
+    let list = value.as_list().ok_or(PatchFormatError::InvalidField(value.from(), "path"))?;
+    let mut path = vec![];
+    for step in &list.elements {
+        let at = step.from();
+        let step = match step {
+            ParsedValue::Text(text, ..) => Step::Key(text.str.clone()),
+            ParsedValue::Tagged(tag, ..) => {
+                let argument = tag.get().as_text().ok_or(PatchFormatError::InvalidField(at, "path"))?;
+                match tag.name() {
+                    "index" => Step::Index(argument.str.parse().or(Err(PatchFormatError::InvalidField(at, "path")))?),
+                    "element" => Step::Element(argument.str.parse().or(Err(PatchFormatError::InvalidField(at, "path")))?),
+                    "tag" => Step::Tag(argument.str.clone()),
+                    _ => return Err(PatchFormatError::InvalidField(at, "path")),
+                }
+            }
+            _ => return Err(PatchFormatError::InvalidField(at, "path")),
+        };
+        path.push(step);
+    }
+    Ok(path)
+}
+
+/// Error reading a patch document.
+#[derive(Clone)]
+pub enum PatchFormatError {
+    /// Patch document could not be parsed.
+    #[cfg(feature = "parse")]
+    ParseError(crate::parse::parser::ParseError),
+    /// Operation at X is unknown.
+    UnknownOperation(Position),
+    /// Operation at X is not a tagged dictionary.
+    InvalidOperation(Position),
+    /// Operation at X is missing field Y.
+    MissingField(Position, &'static str),
+    /// Field Y at X is invalid.
+    InvalidField(Position, &'static str),
+}
+
+impl Debug for PatchFormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "parse")]
+            PatchFormatError::ParseError(error) => write!(f, "{:?}", error),
+            PatchFormatError::UnknownOperation(at) => write!(f, "Unknown patch operation at {}:{}.", at.line, at.column),
+            PatchFormatError::InvalidOperation(at) => write!(f, "Patch operation at {}:{} must be a tagged dictionary.", at.line, at.column),
+            PatchFormatError::MissingField(at, field) => write!(f, "Patch operation at {}:{} is missing field {}.", at.line, at.column, field),
+            PatchFormatError::InvalidField(at, field) => write!(f, "Invalid field {} at {}:{}.", field, at.line, at.column),
+        }
+    }
+}
@@ -2,10 +2,29 @@
 
 use std::collections::HashMap;
 use std::ops::Deref;
+#[cfg(not(feature = "sync"))]
 use std::rc::Rc;
 use std::slice::Iter;
+#[cfg(feature = "sync")]
+use std::sync::Arc;
 use crate::{Attribute, AttributeValue, Compound, Dictionary, Element, List, Tagged, Text, Tuple, Value};
 
+//// Strings
+
+/// A shared string.
+///
+/// An [Rc] by default. With the `sync` feature it is an [Arc], so that parsed
+/// values are [Send] and [Sync] and can be shared between threads.
+#[cfg(not(feature = "sync"))]
+pub type SharedStr = Rc<str>;
+
+/// A shared string.
+///
+/// An [Rc](std::rc::Rc) by default. With the `sync` feature it is an [Arc], so
+/// that parsed values are [Send] and [Sync] and can be shared between threads.
+#[cfg(feature = "sync")]
+pub type SharedStr = Arc<str>;
+
 //// Position
 
 /// A char position.
@@ -89,6 +108,19 @@ impl ParsedValue {
         matches!(self, ParsedValue::Tuple(ParsedTuple::Unit, ..))
     }
 
+    /// Size of the value, counted as the number of values and bytes of text.
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            ParsedValue::Text(text, ..) => 1 + text.str.len(),
+            ParsedValue::Tagged(tag, ..) => 1 + tag.name.len() + tag.value.size(),
+            ParsedValue::Compound(compound, ..) => compound.components.iter().fold(1, |n, c| n.saturating_add(c.size())),
+            ParsedValue::List(list, ..) => list.elements.iter().fold(1, |n, e| n.saturating_add(e.size())),
+            ParsedValue::Dictionary(dictionary, ..) => dictionary.entries.iter().fold(1, |n, (k, v)| n.saturating_add(k.len()).saturating_add(v.size())),
+            ParsedValue::Tuple(tuple, ..) => tuple.iter().fold(1, |n, v| n.saturating_add(v.size())),
+            ParsedValue::Nil(..) => 1,
+        }
+    }
+
     fn elements_as_tuple(&self) -> Vec<&ParsedValue> {
         match self {
             ParsedValue::Tuple(t, _, _) => {
@@ -256,7 +288,7 @@ impl Value<ParsedValue, ParsedText, ParsedDictionary, ParsedList, ParsedCompound
 
 #[derive(PartialEq, Eq, Clone)]
 pub struct ParsedText {
-    pub str: Rc<str>
+    pub str: SharedStr
 }
 
 impl Text<ParsedValue, ParsedText, ParsedDictionary, ParsedList, ParsedCompound, ParsedTuple, ParsedTaggedValue> for ParsedText {
@@ -270,7 +302,7 @@ impl Text<ParsedValue, ParsedText, ParsedDictionary, ParsedList, ParsedCompound,
 /// A parsed tagged value.
 #[derive(Clone)]
 pub struct ParsedTaggedValue {
-    pub name: Rc<str>,
+    pub name: SharedStr,
     pub attributes: Vec<ParsedAttribute>,
     pub value: Box<ParsedValue>,
 }
@@ -319,10 +351,10 @@ impl Tagged<ParsedValue, ParsedText, ParsedDictionary, ParsedList, ParsedCompoun
 }
 
 #[derive(Clone)]
-pub struct ParsedAttribute(pub Rc<str>, pub Option<Rc<str>>);
+pub struct ParsedAttribute(pub SharedStr, pub Option<SharedStr>);
 
 impl ParsedAttribute {
-    fn key(&self) -> Rc<str> {
+    fn key(&self) -> SharedStr {
         self.0.clone()
     }
 }
@@ -427,7 +459,7 @@ impl<'a> Iterator for TupleIterator<'a> {
 /// A parsed dictionary.
 #[derive(Clone)]
 pub struct ParsedDictionary {
-    pub entries: HashMap<Rc<str>, ParsedValue>,
+    pub entries: HashMap<SharedStr, ParsedValue>,
 }
 
 impl ParsedDictionary {
@@ -468,7 +500,7 @@ impl Dictionary<ParsedValue, ParsedText, ParsedDictionary, ParsedList, ParsedCom
     }
 }
 
-pub struct EntryIterator<'a>(std::collections::hash_map::Iter<'a, Rc<str>, ParsedValue>);
+pub struct EntryIterator<'a>(std::collections::hash_map::Iter<'a, SharedStr, ParsedValue>);
 
 impl<'a> Iterator for EntryIterator<'a> {
     type Item = (&'a str, &'a ParsedValue);
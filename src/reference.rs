@@ -0,0 +1,265 @@
+//! References inside a document.
+//!
+//! A reference `<ref!>:a.b.c` is replaced by a copy of the value at the key
+//! path `a.b.c` from the root of the document. Elements of lists are selected
+//! by their index, as in `items.0`.
+//!
+//! A value can be anchored with `<anchor! name>:value`. The anchor tag is
+//! replaced by its value, and a reference path starting with the name of an
+//! anchor starts at the anchored value.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use crate::{Tagged, Value};
+use crate::diff::Step;
+use crate::pdm::{ParsedAttribute, ParsedDictionary, ParsedTuple, ParsedValue, Position, SharedStr};
+
+/// Name of the reference tag.
+pub const REFERENCE_TAG: &str = "ref!";
+
+/// Name of the anchor tag.
+pub const ANCHOR_TAG: &str = "anchor!";
+
+/// References copy at most this many values and bytes of text in total.
+const MAX_EXPANSION_SIZE: usize = 1 << 20;
+
+/// Resolve the references of a value document.
+pub fn resolve_references(value: &mut ParsedValue) -> Result<(), Vec<ReferenceError>> {
+    let mut resolver = ReferenceResolver { anchors: HashMap::new(), stack: vec![], size: 0, aborted: false, errors: vec![] };
+    let mut location = vec![];
+    resolver.strip_anchors(value, &mut location);
+    let source = value.clone();
+    resolver.expand(value, &source, &mut location);
+    if resolver.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+/// Resolve the references of a dictionary document.
+pub fn resolve_dictionary_references(dictionary: &mut ParsedDictionary) -> Result<(), Vec<ReferenceError>> {
+    const AT: Position = Position { index: 0, line: 0, column: 0 };
+    let mut value = ParsedValue::Dictionary(std::mem::replace(dictionary, ParsedDictionary::empty()), AT, AT);
+    let result = resolve_references(&mut value);
+    if let ParsedValue::Dictionary(d, ..) = value {
+        *dictionary = d;
+    }
+    result
+}
+
+struct ReferenceResolver {
+    anchors: HashMap<SharedStr, Vec<Step>>,
+    /// Targets of the references being expanded.
+    stack: Vec<Vec<Step>>,
+    /// Size of the copies made so far.
+    size: usize,
+    /// Whether expansion stopped because the copies grew too large.
+    aborted: bool,
+    errors: Vec<ReferenceError>,
+}
+
+impl ReferenceResolver {
+
+    /// Replace anchor tags by their values and record their paths.
+    fn strip_anchors(&mut self, value: &mut ParsedValue, location: &mut Vec<Step>) {
+        if let ParsedValue::Tagged(tag, from, _) = value {
+            if tag.name() == ANCHOR_TAG {
+                match &tag.attributes[..] {
+                    [ParsedAttribute(name, None)] => {
+                        if self.anchors.contains_key(name) {
+                            self.error(ReferenceError::DuplicateAnchor(*from, name.clone()));
+                        } else {
+                            self.anchors.insert(name.clone(), location.clone());
+                        }
+                    }
+                    _ => self.error(ReferenceError::IllegalReference(*from)),
+                }
+                let inner = std::mem::replace(&mut *tag.value, ParsedValue::nil(*from, *from));
+                *value = inner;
+                self.strip_anchors(value, location);
+                return;
+            }
+        }
+        for_each_child(value, location, &mut |child, location| self.strip_anchors(child, location));
+    }
+
+    /// Expand the references of a value at a location of the source.
+    fn expand(&mut self, value: &mut ParsedValue, source: &ParsedValue, location: &mut Vec<Step>) {
+        if self.aborted {
+            return;
+        }
+        if let ParsedValue::Tagged(tag, from, _) = value {
+            if tag.name() == REFERENCE_TAG {
+                let at = *from;
+                let path = match tag.get().as_text() {
+                    Some(text) if !tag.has_attributes() => text.str.clone(),
+                    _ => {
+                        self.error(ReferenceError::IllegalReference(at));
+                        return;
+                    }
+                };
+                let target = match self.target(source, &path) {
+                    Some(target) => target,
+                    None => {
+                        self.error(ReferenceError::Dangling(at, path.to_string()));
+                        return;
+                    }
+                };
+                if location.starts_with(&target) || self.stack.contains(&target) {
+                    self.error(ReferenceError::Cycle(at, path.to_string()));
+                    return;
+                }
+                let target_value = lookup(source, &target).unwrap();
+                self.size = self.size.saturating_add(target_value.size());
+                if self.size > MAX_EXPANSION_SIZE {
+                    self.error(ReferenceError::TooLarge(at, path.to_string()));
+                    self.aborted = true;
+                    return;
+                }
+                let mut copy = target_value.clone();
+                self.stack.push(target.clone());
+                let mut target_location = target;
+                self.expand(&mut copy, source, &mut target_location);
+                self.stack.pop();
+                *value = copy;
+                return;
+            }
+        }
+        for_each_child(value, location, &mut |child, location| self.expand(child, source, location));
+    }
+
+    /// Find the path of the value a reference points to.
+    fn target(&self, source: &ParsedValue, path: &str) -> Option<Vec<Step>> {
+        let mut segments = path.split('.');
+        let first = segments.next()?;
+        let mut target = match self.anchors.get(first) {
+            Some(anchor) => anchor.clone(),
+            None => {
+                segments = path.split('.');
+                vec![]
+            }
+        };
+        let mut value = lookup(source, &target)?;
+        for segment in segments {
+            let step = match value {
+                ParsedValue::Dictionary(..) => Step::Key(SharedStr::from(segment)),
+                ParsedValue::List(..) => Step::Index(segment.parse().ok()?),
+                _ => return None,
+            };
+            value = lookup(value, std::slice::from_ref(&step))?;
+            target.push(step);
+        }
+        Some(target)
+    }
+
+    fn error(&mut self, error: ReferenceError) {
+        // References inside referenced values are visited once per copy.
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+}
+
+/// Find the value at a path.
+fn lookup<'a>(value: &'a ParsedValue, path: &[Step]) -> Option<&'a ParsedValue> {
+    let mut value = value;
+    for step in path {
+        value = match (step, value) {
+            (Step::Key(key), ParsedValue::Dictionary(d, ..)) => d.entries.get(key)?,
+            (Step::Index(index), ParsedValue::List(l, ..)) => l.elements.get(*index)?,
+            (Step::Element(0), ParsedValue::Tuple(ParsedTuple::Single(v), ..)) => v,
+            (Step::Element(index), ParsedValue::Tuple(ParsedTuple::Multiple(vs), ..)) => vs.get(*index)?,
+            (Step::Tag(..), ParsedValue::Tagged(t, ..)) => &t.value,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Call a function on each child of a value with its location.
+fn for_each_child<F: FnMut(&mut ParsedValue, &mut Vec<Step>)>(value: &mut ParsedValue, location: &mut Vec<Step>, f: &mut F) {
+    match value {
+        ParsedValue::Dictionary(dictionary, ..) => {
+            for (key, value) in dictionary.entries.iter_mut() {
+                location.push(Step::Key(key.clone()));
+                f(value, location);
+                location.pop();
+            }
+        }
+        ParsedValue::List(list, ..) => {
+            for (i, value) in list.elements.iter_mut().enumerate() {
+                location.push(Step::Index(i));
+                f(value, location);
+                location.pop();
+            }
+        }
+        ParsedValue::Tuple(ParsedTuple::Single(value), ..) => {
+            location.push(Step::Element(0));
+            f(value, location);
+            location.pop();
+        }
+        ParsedValue::Tuple(ParsedTuple::Multiple(values), ..) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                location.push(Step::Element(i));
+                f(value, location);
+                location.pop();
+            }
+        }
+        ParsedValue::Tagged(tag, ..) => {
+            location.push(Step::Tag(tag.name.clone()));
+            f(&mut tag.value, location);
+            location.pop();
+        }
+        ParsedValue::Compound(compound, ..) => {
+            for value in compound.components.iter_mut() {
+                f(value, location);
+            }
+        }
+        _ => {}
+    }
+}
+
+//// Errors
+
+/// Error resolving references.
+#[derive(PartialEq, Eq)]
+pub enum ReferenceError {
+    /// Reference at X to Y does not resolve to a value.
+    Dangling(Position, String),
+    /// Reference at X to Y refers to itself.
+    Cycle(Position, String),
+    /// Reference at X to Y copies too large a document.
+    TooLarge(Position, String),
+    /// Anchor X at Y is already defined.
+    DuplicateAnchor(Position, SharedStr),
+    /// Reference or anchor at X is malformed.
+    IllegalReference(Position),
+}
+
+impl Debug for ReferenceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", reference_error_to_string(self))
+    }
+}
+
+pub fn reference_error_to_string(error: &ReferenceError) -> String {
+    match error {
+        ReferenceError::Dangling(at, path) => {
+            format!("Reference to {} at {}:{} does not resolve to a value.", path, at.line, at.column)
+        }
+        ReferenceError::Cycle(at, path) => {
+            format!("Reference to {} at {}:{} refers to itself.", path, at.line, at.column)
+        }
+        ReferenceError::TooLarge(at, path) => {
+            format!("Reference to {} at {}:{} copies too large a document.", path, at.line, at.column)
+        }
+        ReferenceError::DuplicateAnchor(at, name) => {
+            format!("Anchor {} at {}:{} is already defined.", name, at.line, at.column)
+        }
+        ReferenceError::IllegalReference(at) => {
+            format!("Illegal reference or anchor at {}:{}.", at.line, at.column)
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Hot reload of configuration files.
+//!
+//! A [Reloader] polls a set of dictionary documents and reparses those that
+//! have been modified. If a file fails to load, the last good version of it is
+//! kept and the error is reported. Subscribers receive the structural changes
+//! of the merged configuration.
+//!
+//! Files are read on every poll and compared by the hash of their contents,
+//! since modification times are too coarse to notice every edit.
+//!
+//! Subscribers need not be `Send`, so the reloader is polled from the thread
+//! that owns the configuration, for example from the main loop of a daemon.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::config::{Config, ConfigError};
+use crate::diff::{Change, diff_dictionaries};
+use crate::merge::{merge, MergedDictionary, MergeStrategy, Source};
+use crate::parse::parse_dictionary_str;
+use crate::pdm::{ParsedDictionary, SharedStr};
+
+/// An event emitted by a reloader.
+pub enum ReloadEvent {
+    /// The configuration changed.
+    Changed(Vec<Change>),
+    /// A file could not be reloaded. Its last good version is kept.
+    Failed(ConfigError),
+}
+
+/// A subscriber to reload events.
+pub type Subscriber = Box<dyn FnMut(&ReloadEvent)>;
+
+/// Watches a set of dictionary documents.
+pub struct Reloader {
+    files: Vec<WatchedFile>,
+    strategy: MergeStrategy,
+    config: Config,
+    subscribers: Vec<Subscriber>,
+}
+
+struct WatchedFile {
+    path: PathBuf,
+    /// Hash of the contents when last read.
+    hash: Option<u64>,
+    /// Last good version.
+    document: Option<ParsedDictionary>,
+}
+
+impl Reloader {
+
+    /// Create a reloader for files merged in order.
+    ///
+    /// Nothing is loaded until the first poll.
+    pub fn new<P: AsRef<Path>, I: IntoIterator<Item = P>>(paths: I, strategy: MergeStrategy) -> Self {
+        let files = paths.into_iter().map(|path| WatchedFile {
+            path: path.as_ref().to_path_buf(),
+            hash: None,
+            document: None,
+        }).collect();
+        Reloader { files, strategy, config: Config { dictionary: MergedDictionary::empty() }, subscribers: vec![] }
+    }
+
+    /// Get the current configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Subscribe to events.
+    pub fn subscribe<F: FnMut(&ReloadEvent) + 'static>(&mut self, subscriber: F) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// Reload modified files and notify subscribers.
+    pub fn poll(&mut self) -> Vec<ReloadEvent> {
+        let mut events = vec![];
+        let mut modified = false;
+        for file in &mut self.files {
+            match file.reload() {
+                Ok(changed) => modified |= changed,
+                Err(error) => events.push(ReloadEvent::Failed(error)),
+            }
+        }
+        if modified {
+            let mut merged = MergedDictionary::empty();
+            for file in &self.files {
+                if let Some(document) = &file.document {
+                    let layer = MergedDictionary::new(document, Source::Document(SharedStr::from(file.path.to_string_lossy().as_ref())));
+                    merged = merge(&merged, &layer, &self.strategy);
+                }
+            }
+            let changes = diff_dictionaries(&self.config.dictionary.to_dictionary(), &merged.to_dictionary());
+            self.config = Config { dictionary: merged };
+            if !changes.is_empty() {
+                events.push(ReloadEvent::Changed(changes));
+            }
+        }
+        for event in &events {
+            for subscriber in &mut self.subscribers {
+                subscriber(event);
+            }
+        }
+        events
+    }
+
+    /// Poll at an interval while the condition holds.
+    pub fn run<F: FnMut(&Config) -> bool>(&mut self, interval: Duration, mut condition: F) {
+        loop {
+            self.poll();
+            if !condition(&self.config) {
+                break;
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
+}
+
+impl WatchedFile {
+
+    /// Reload the file if it was modified. Returns whether the document
+    /// changed.
+    fn reload(&mut self) -> Result<bool, ConfigError> {
+        let source = match std::fs::read_to_string(&self.path) {
+            Ok(source) => source,
+            Err(error) => return self.fail(ConfigError::IoError(self.path.clone(), error)),
+        };
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.hash == Some(hash) {
+            return Ok(false);
+        }
+        self.hash = Some(hash);
+        let document = match parse_dictionary_str(&source) {
+            Ok(document) => document,
+            Err(errors) => {
+                let name = Source::Document(SharedStr::from(self.path.to_string_lossy().as_ref()));
+                return Err(ConfigError::ParseError(name, errors));
+            }
+        };
+        self.document = Some(document);
+        Ok(true)
+    }
+
+    /// Forget the hash so that the file is parsed again once it can be read.
+    fn fail(&mut self, error: ConfigError) -> Result<bool, ConfigError> {
+        self.hash = None;
+        Err(error)
+    }
+
+}
@@ -0,0 +1,416 @@
+//! Streaming parser.
+//!
+//! Reads a list or dictionary document from a [BufRead] and yields its top-level
+//! elements or entries one at a time, so that memory is bounded by the size of
+//! the largest element rather than the size of the document. This suits large
+//! append-only documents such as logs.
+//!
+//! The document is lexed incrementally and split into chunks at top-level
+//! boundaries. Each chunk is reduced and parsed on its own. Lists are split at
+//! bullets, at semicolons and at bars starting the rows of a table. Dictionaries
+//! are split at semicolons and at keys and headers starting a line. A section
+//! after a header is parsed as one chunk.
+//!
+//! Duplicate keys in different chunks are not detected. Positions are relative
+//! to the start of the stream.
+
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::BufRead;
+use crate::lex::{CharIter, lex_token, Token};
+use crate::parse::{Interner, lex_error_to_parse_error, ParseOptions, reduce_tokens_with};
+use crate::parse::parser::{error_to_string, ParseError, Parser};
+use crate::pdm::{ParsedList, ParsedValue, Position, SharedStr};
+
+/// Stream the elements of a list document.
+pub fn stream_list<R: BufRead>(reader: R) -> ListStream<R> {
+    stream_list_with(reader, &ParseOptions::default())
+}
+
+/// Stream the elements of a list document within the limits of the options.
+///
+/// The size limit applies to the whole stream and the other limits to each
+/// chunk.
+pub fn stream_list_with<R: BufRead>(reader: R, options: &ParseOptions) -> ListStream<R> {
+    ListStream { chunker: Chunker::new(reader, Mode::List, *options), ready: VecDeque::new() }
+}
+
+/// Stream the entries of a dictionary document.
+pub fn stream_dictionary<R: BufRead>(reader: R) -> DictionaryStream<R> {
+    stream_dictionary_with(reader, &ParseOptions::default())
+}
+
+/// Stream the entries of a dictionary document within the limits of the
+/// options.
+///
+/// The size limit applies to the whole stream and the other limits to each
+/// chunk.
+pub fn stream_dictionary_with<R: BufRead>(reader: R, options: &ParseOptions) -> DictionaryStream<R> {
+    DictionaryStream { chunker: Chunker::new(reader, Mode::Dictionary, *options), ready: VecDeque::new() }
+}
+
+/// Iterator over the elements of a list document.
+///
+/// Ends after the first error.
+pub struct ListStream<R: BufRead> {
+    chunker: Chunker<R>,
+    ready: VecDeque<ParsedValue>,
+}
+
+impl<R: BufRead> Iterator for ListStream<R> {
+
+    type Item = Result<ParsedValue, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(element) = self.ready.pop_front() {
+                return Some(Ok(element));
+            }
+            let chunk = match self.chunker.next_chunk() {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error)),
+            };
+            let list = match parse_list_chunk(chunk, &self.chunker.options) {
+                Ok(list) => list,
+                Err(error) => {
+                    self.chunker.done = true;
+                    return Some(Err(error));
+                }
+            };
+            self.ready.extend(list.elements);
+        }
+    }
+
+}
+
+/// Iterator over the entries of a dictionary document.
+///
+/// Ends after the first error.
+pub struct DictionaryStream<R: BufRead> {
+    chunker: Chunker<R>,
+    ready: VecDeque<(SharedStr, ParsedValue)>,
+}
+
+impl<R: BufRead> Iterator for DictionaryStream<R> {
+
+    type Item = Result<(SharedStr, ParsedValue), StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.ready.pop_front() {
+                return Some(Ok(entry));
+            }
+            let chunk = match self.chunker.next_chunk() {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error)),
+            };
+            let dictionary = match parse_chunk(chunk, &self.chunker.options, |parser| parser.parse_dictionary_document()) {
+                Ok(dictionary) => dictionary,
+                Err(error) => {
+                    self.chunker.done = true;
+                    return Some(Err(error));
+                }
+            };
+            self.ready.extend(dictionary.entries);
+        }
+    }
+
+}
+
+/// Reduce and parse a chunk of a list document.
+///
+/// A table is split into a chunk per row, but a tabular list has at least two
+/// rows, so a chunk of one row is parsed as the value between its bars.
+fn parse_list_chunk(chunk: Vec<Token>, options: &ParseOptions) -> Result<ParsedList, StreamError> {
+    if let Some(row) = row_content(&chunk) {
+        if let Ok(value) = parse_chunk(row, options, |parser| parser.parse_value_document()) {
+            return Ok(ParsedList { elements: vec![value] });
+        }
+    }
+    parse_chunk(chunk, options, |parser| parser.parse_list_document())
+}
+
+/// Get the tokens between the bars around a chunk, if it is enclosed in bars
+/// and not empty within them.
+fn row_content<'a>(chunk: &[Token<'a>]) -> Option<Vec<Token<'a>>> {
+    let solid = |token: &&Token| !matches!(token, Token::Whitespace(..) | Token::End(..));
+    let first = chunk.iter().position(|token| solid(&token))?;
+    let last = chunk.iter().rposition(|token| solid(&token))?;
+    if !matches!(chunk[first], Token::Bar(..)) || !matches!(chunk[last], Token::Bar(..)) {
+        return None;
+    }
+    if !chunk[first + 1..last].iter().any(|token| solid(&token)) {
+        return None;
+    }
+    let mut row = chunk[first + 1..last].to_vec();
+    row.push(Token::End(chunk[last].at()));
+    Some(row)
+}
+
+/// Reduce and parse a chunk of tokens.
+fn parse_chunk<T, F: FnOnce(&mut Parser) -> Result<T, ParseError>>(chunk: Vec<Token>, options: &ParseOptions, parse: F) -> Result<T, StreamError> {
+    let tokens = match reduce_tokens_with(chunk, options) {
+        Ok(tokens) => tokens,
+        Err(error) => return Err(StreamError::ParseError(vec![error])),
+    };
+    let mut interner = Interner::new();
+    let mut errors = Vec::new();
+    let mut parser = Parser::new(&tokens, &mut interner, &mut errors, false, Position { index: 0, line: 0, column: 0 }).with_options(options);
+    let parse = parse(&mut parser);
+    if parse.is_ok() && !parser.is_end() {
+        let error = ParseError::ExpectedEnd(parser.t0.to_type(), parser.at());
+        errors.push(error);
+    };
+    match parse {
+        Ok(parse) if errors.is_empty() => Ok(parse),
+        Ok(..) => Err(StreamError::ParseError(errors)),
+        Err(error) => {
+            errors.push(error);
+            Err(StreamError::ParseError(errors))
+        }
+    }
+}
+
+//// Chunker
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+enum Mode {
+    List,
+    Dictionary,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+enum Bracket {
+    Curly,
+    Square,
+    Angle,
+}
+
+/// Splits a token stream into chunks at top-level boundaries.
+struct Chunker<R: BufRead> {
+    iter: CharIter<'static, ReadChars<R>>,
+    mode: Mode,
+    options: ParseOptions,
+    /// Lexed tokens not yet added to a chunk.
+    pending: VecDeque<Token<'static>>,
+    /// Open brackets.
+    brackets: Vec<Bracket>,
+    done: bool,
+}
+
+impl<R: BufRead> Chunker<R> {
+
+    fn new(reader: R, mode: Mode, options: ParseOptions) -> Self {
+        let chars = ReadChars { reader, line: String::new(), index: 0, size: 0, error: None };
+        Chunker { iter: CharIter::new(chars), mode, options, pending: VecDeque::new(), brackets: vec![], done: false }
+    }
+
+    /// Get the next chunk, terminated by an end token, or `None` at the end of
+    /// the stream.
+    fn next_chunk(&mut self) -> Result<Option<Vec<Token<'static>>>, StreamError> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.read_chunk() {
+            Ok(chunk) => {
+                if chunk.is_none() {
+                    self.done = true;
+                }
+                Ok(chunk)
+            }
+            Err(error) => {
+                self.done = true;
+                Err(error)
+            }
+        }
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Vec<Token<'static>>>, StreamError> {
+        let mut chunk = vec![];
+        // The first non-whitespace token of the chunk.
+        let mut first: Option<Token<'static>> = None;
+        loop {
+            let token = self.take()?;
+            if let Token::End(at) = token {
+                return if first.is_some() {
+                    chunk.push(Token::End(at));
+                    Ok(Some(chunk))
+                } else {
+                    Ok(None)
+                };
+            }
+            if let Some(first) = &first {
+                if self.brackets.is_empty() && self.starts_chunk(first, chunk.last(), &token)? {
+                    let at = token.at();
+                    self.pending.push_front(token);
+                    chunk.push(Token::End(at));
+                    return Ok(Some(chunk));
+                }
+            }
+            self.enter(&token);
+            if first.is_none() && !matches!(token, Token::Whitespace(..)) {
+                first = Some(token.clone());
+            }
+            let ends = self.brackets.is_empty() && matches!(token, Token::Semicolon(..));
+            let at = token.at();
+            chunk.push(token);
+            if ends {
+                chunk.push(Token::End(at));
+                return Ok(Some(chunk));
+            }
+        }
+    }
+
+    /// Whether a top-level token starts a new chunk.
+    fn starts_chunk(&mut self, first: &Token, last: Option<&Token>, token: &Token) -> Result<bool, StreamError> {
+        let starts_line = match last {
+            Some(Token::Whitespace(at)) => at.line < token.at().line,
+            _ => false,
+        };
+        match self.mode {
+            Mode::List => {
+                Ok(match token {
+                    Token::RightAngle(..) => true,
+                    Token::Bar(..) => starts_line && matches!(first, Token::Bar(..)),
+                    _ => false,
+                })
+            }
+            Mode::Dictionary => {
+                if !starts_line {
+                    return Ok(false);
+                }
+                Ok(match token {
+                    Token::LeftBracket(..) | Token::LeftSquare(..) => true,
+                    Token::Word(..) | Token::Transcription(..) => {
+                        let header = matches!(first, Token::LeftBracket(..) | Token::LeftSquare(..));
+                        !header && matches!(self.peek()?, Token::Colon(..))
+                    }
+                    _ => false,
+                })
+            }
+        }
+    }
+
+    /// Track the brackets opened and closed by a token.
+    fn enter(&mut self, token: &Token) {
+        match token {
+            Token::LeftBracket(..) => self.brackets.push(Bracket::Curly),
+            Token::LeftSquare(..) => self.brackets.push(Bracket::Square),
+            Token::LeftAngle(..) => self.brackets.push(Bracket::Angle),
+            Token::RightBracket(..) | Token::RightSquare(..) => {
+                self.brackets.pop();
+            }
+            Token::RightAngle(..) => {
+                // A right angle outside of angle brackets is a bullet.
+                if let Some(Bracket::Angle) = self.brackets.last() {
+                    self.brackets.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Take the next token.
+    fn take(&mut self) -> Result<Token<'static>, StreamError> {
+        match self.pending.pop_front() {
+            Some(token) => Ok(token),
+            None => self.lex(),
+        }
+    }
+
+    /// Look at the token after the last taken token.
+    fn peek(&mut self) -> Result<&Token<'static>, StreamError> {
+        if self.pending.is_empty() {
+            let token = self.lex()?;
+            self.pending.push_back(token);
+        }
+        Ok(&self.pending[0])
+    }
+
+    fn lex(&mut self) -> Result<Token<'static>, StreamError> {
+        let token = lex_token(&mut self.iter);
+        let chars = self.iter.source_mut();
+        if let Some(error) = chars.error.take() {
+            return Err(StreamError::IoError(error));
+        }
+        if chars.size > self.options.max_document_size {
+            return Err(StreamError::ParseError(vec![ParseError::DocumentTooLarge(chars.size, self.options.max_document_size)]));
+        }
+        match token {
+            Ok(token) => Ok(token),
+            Err(error) => Err(StreamError::ParseError(vec![lex_error_to_parse_error(error)])),
+        }
+    }
+
+}
+
+/// Characters read line by line from a reader.
+///
+/// Stops at the first read error, which is kept.
+struct ReadChars<R: BufRead> {
+    reader: R,
+    line: String,
+    index: usize,
+    /// Number of bytes read.
+    size: usize,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> Iterator for ReadChars<R> {
+
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(c) = self.line[self.index..].chars().next() {
+                self.index += c.len_utf8();
+                return Some(c);
+            }
+            if self.error.is_some() {
+                return None;
+            }
+            self.line.clear();
+            self.index = 0;
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(size) => self.size += size,
+                Err(error) => {
+                    self.error = Some(error);
+                    return None;
+                }
+            }
+        }
+    }
+
+}
+
+//// Errors
+
+/// Error streaming a document.
+pub enum StreamError {
+    /// Error reading the stream.
+    IoError(io::Error),
+    /// Error parsing an element or entry.
+    ParseError(Vec<ParseError>),
+}
+
+impl Debug for StreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", stream_error_to_string(self))
+    }
+}
+
+pub fn stream_error_to_string(error: &StreamError) -> String {
+    match error {
+        StreamError::IoError(error) => {
+            format!("Could not read stream: {}", error)
+        }
+        StreamError::ParseError(errors) => {
+            let errors: Vec<String> = errors.iter().map(error_to_string).collect();
+            errors.join("\n")
+        }
+    }
+}
@@ -1,30 +1,52 @@
 //! Command binary for the TeX-preprocessor.
 //!
 //! Test: cargo run --bin khi-tex-cmd --features="tex" -- examples/equations.tex.khi
+//!
+//! Use -D name to define a name for conditionals.
 
-use std::env;
+use std::collections::HashSet;
+use std::{env, process};
 use std::fs::File;
 use std::io::{Read, Write};
+use khi::condition::{condition_error_to_string, evaluate_conditionals};
 use khi::parse::{parse_value_str};
 use khi::parse::parser::error_to_string;
-use khi::tex::{PreprocessorError, write_tex};
+use khi::tex::{preprocessor_error_to_string, write_tex};
 
 fn main() {
     match preprocess() {
         Ok(output) => print!("{}\n\n", output),
-        Err(error) => eprint!("{}\n\n", error),
+        Err(error) => {
+            eprint!("{}\n\n", error);
+            process::exit(1);
+        }
     };
 }
 
 fn preprocess() -> Result<String, String> {
     let mut args = env::args();
     args.next(); // The first arg is the binary. Skip.
+    let mut defines = HashSet::new();
+    let mut paths = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "-D" {
+            match args.next() {
+                Some(name) => defines.insert(name),
+                None => return Err("Specify name to define after -D.".to_string()),
+            };
+        } else if let Some(name) = arg.strip_prefix("-D") {
+            defines.insert(name.to_string());
+        } else {
+            paths.push(arg);
+        }
+    }
+    let mut args = paths.into_iter();
     if let Some(first) = args.next() {
-        let mut file = File::open(&first).unwrap();
+        let mut file = File::open(&first).map_err(|e| format!("Could not open {}: {}.", first, e))?;
         let mut source = String::new();
-        file.read_to_string(&mut source).unwrap();
+        file.read_to_string(&mut source).map_err(|e| format!("Could not read {}: {}.", first, e))?;
         eprint!("Preprocessing document of size: {}\n\n", source.len());
-        let document = match parse_value_str(&source) {
+        let mut document = match parse_value_str(&source) {
             Ok(document) => document,
             Err(errors) => {
                 let mut errs = String::new();
@@ -35,39 +57,28 @@ fn preprocess() -> Result<String, String> {
                 return Err(errs);
             },
         };
+        if let Err(errors) = evaluate_conditionals(&mut document, &defines) {
+            let mut errs = String::new();
+            for e in errors {
+                errs.push_str(&condition_error_to_string(&e));
+                errs.push('\n');
+            }
+            return Err(errs);
+        }
         match write_tex(&document) {
             Ok(output) => {
                 if let Some(second) = args.next() {
                     if first.eq(&second) {
                         return Err(format!("Trying to overwrite source!"));
                     }
-                    let mut out = File::create(&second).unwrap();
-                    out.write_all(output.as_bytes()).unwrap();
+                    let mut out = File::create(&second).map_err(|e| format!("Could not create {}: {}.", second, e))?;
+                    out.write_all(output.as_bytes()).map_err(|e| format!("Could not write {}: {}.", second, e))?;
                     Ok(format!("Successfully generated document."))
                 } else {
                     Ok(output)
                 }
             },
-            Err(error) => match error {
-                PreprocessorError::IllegalTable(at) => {
-                    Err(format!("Illegal table at {}:{}.", at.line, at.column))
-                }
-                PreprocessorError::IllegalDictionary(at) => {
-                    Err(format!("Illegal dictionary at {}:{}.", at.line, at.column))
-                }
-                PreprocessorError::ZeroTable(at) => {
-                    Err(format!("Table cannot be empty at {}:{}.", at.line, at.column))
-                }
-                PreprocessorError::MacroError(at, directive) => {
-                    Err(format!("Unknown macro {} at {}:{}.", &directive, at.line, at.column))
-                }
-                PreprocessorError::MissingOptionalArgument(at) => {
-                    Err(format!("Missing optional argument at {}:{}.", at.line, at.column))
-                }
-                PreprocessorError::IllegalTuple(at) => {
-                    Err(format!("Illegal tuple at {}:{}.", at.line, at.column))
-                }
-            },
+            Err(error) => Err(preprocessor_error_to_string(&error)),
         }
     } else {
         Err(format!("Specify source file as first argument."))
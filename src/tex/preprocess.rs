@@ -6,18 +6,30 @@
 // '#' must be inserted as "\#". # is the argument substitution operator.
 // '\' must be inserted as "\textbackslash" in text and "\backslash" or "\setminus" in math. "\\" indicates a line break.
 
-use std::fmt::Write;
+use std::borrow::Cow;
+use std::fmt::{Debug, Formatter, Write};
+use crate::macros::{expand_macros, has_macros, macro_error_to_string, MacroError};
 use crate::pdm::{ParsedList, ParsedTaggedValue, ParsedValue, Position};
 use crate::{Compound, Element, List, Tagged, Text, Tuple, Value};
 
+/// Write a document as TeX. Document macros are expanded first.
 pub fn write_tex(structure: &ParsedValue) -> Result<String, PreprocessorError> {
     write_tex_with(structure, BreakMode::Mirror)
 }
 
+/// Write a document as TeX with a break mode. Document macros are expanded
+/// first.
 pub fn write_tex_with(structure: &ParsedValue, mode: BreakMode) -> Result<String, PreprocessorError> {
+    let structure = if has_macros(structure) {
+        let mut structure = structure.clone();
+        expand_macros(&mut structure).map_err(PreprocessorError::MacroExpansion)?;
+        Cow::Owned(structure)
+    } else {
+        Cow::Borrowed(structure)
+    };
     let mut output = String::new();
     let mut writer = Writer { output: &mut output, column: 1, break_mode: mode, last_type: LastType::Whitespace, line: 1 };
-    writer.write_inner(structure)?;
+    writer.write_inner(&structure)?;
     Ok(output)
 }
 
@@ -277,16 +289,15 @@ impl Writer<'_> {
         let inner_value = tag.get();
         if name.ends_with("!") {
             if name.eq("def!") {
-                if !inner_value.is_tuple() {
+                let Some(arguments) = inner_value.as_tuple().filter(|t| t.len() == 3) else {
                     return Err(PreprocessorError::MacroError(at, format!("def! must take 3 arguments.")));
-                }
-                let inner_value = inner_value.as_tuple().unwrap();
-                if inner_value.len() != 3 {
+                };
+                let Some(tag) = arguments.get(0).and_then(|t| t.as_text()) else {
+                    return Err(PreprocessorError::MacroError(at, "def! must take a text name as first argument.".to_string()));
+                };
+                let (Some(arity), Some(substitute)) = (arguments.get(1), arguments.get(2)) else {
                     return Err(PreprocessorError::MacroError(at, format!("def! must take 3 arguments.")));
-                }
-                let tag = inner_value.get(0).unwrap().as_text().unwrap();
-                let arity = inner_value.get(1).unwrap();
-                let substitute = inner_value.get(2).unwrap();
+                };
                 self.output.push_str("\\newcommand");
                 self.output.push('\\');
                 self.output.push_str(tag.as_str());
@@ -298,18 +309,16 @@ impl Writer<'_> {
                 self.output.push('}');
                 self.last_type = LastType::Glyph;
             } else if name.eq("lines!") {
-                if inner_value.is_tuple() {
+                let Some(text) = inner_value.as_text() else {
                     return Err(PreprocessorError::MacroError(at, format!("lines! takes 1 text argument.")));
-                }
-                let text = inner_value.as_text().unwrap();
+                };
                 self.output.write_char('\n').or(Err(PreprocessorError::MacroError(at, format!("Error on writing to output in macro at {}:{}.", at.line, at.column))))?;
                 self.line += 1;
                 self.write_raw(text.as_str());
             } else if name.eq("raw!") {
-                if !inner_value.is_text() {
+                let Some(text) = inner_value.as_text() else {
                     return Err(PreprocessorError::MacroError(at, format!("raw! must take 1 text argument.")));
-                }
-                let text = inner_value.as_text().unwrap();
+                };
                 self.write_raw(text.as_str());
             } else {
                 return Err(PreprocessorError::MacroError(at, format!("Unknown macro {}.", name)));
@@ -420,11 +429,52 @@ impl Writer<'_> {
 
 }
 
+/// Error writing TeX.
 pub enum PreprocessorError {
+    /// Table at X cannot be an argument.
     IllegalTable(Position),
+    /// Dictionary at X cannot be written.
     IllegalDictionary(Position),
+    /// Tuple at X cannot be written.
     IllegalTuple(Position),
+    /// Table at X is empty.
     ZeroTable(Position),
+    /// Macro at X is malformed, as described by Y.
     MacroError(Position, String),
+    /// Command at X is missing its optional argument.
     MissingOptionalArgument(Position),
+    /// Document macros could not be expanded.
+    MacroExpansion(Vec<MacroError>),
+}
+
+impl Debug for PreprocessorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", preprocessor_error_to_string(self))
+    }
+}
+
+pub fn preprocessor_error_to_string(error: &PreprocessorError) -> String {
+    match error {
+        PreprocessorError::IllegalTable(at) => {
+            format!("Illegal table at {}:{}.", at.line, at.column)
+        }
+        PreprocessorError::IllegalDictionary(at) => {
+            format!("Illegal dictionary at {}:{}.", at.line, at.column)
+        }
+        PreprocessorError::IllegalTuple(at) => {
+            format!("Illegal tuple at {}:{}.", at.line, at.column)
+        }
+        PreprocessorError::ZeroTable(at) => {
+            format!("Table cannot be empty at {}:{}.", at.line, at.column)
+        }
+        PreprocessorError::MacroError(at, message) => {
+            format!("Macro error at {}:{}: {}", at.line, at.column, message)
+        }
+        PreprocessorError::MissingOptionalArgument(at) => {
+            format!("Missing optional argument at {}:{}.", at.line, at.column)
+        }
+        PreprocessorError::MacroExpansion(errors) => {
+            errors.iter().map(macro_error_to_string).collect::<Vec<String>>().join("\n")
+        }
+    }
 }
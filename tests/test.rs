@@ -1,7 +1,10 @@
 use std::ops::Deref;
 use khi::{Compound, Dictionary, Tagged, Value, List, Element, Tuple};
-use khi::parse::{parse_dictionary_str, parse_list_str, parse_value_str};
+use khi::parse::{Interner, ParseOptions, Strictness, parse_dictionary_str, parse_document_str_with, parse_list_str, parse_value_str};
+use khi::parse::parser::{ParseError, Rule};
 use khi::pdm::ParsedValue;
+use khi::diff::equal;
+use khi::format::format_value;
 
 #[test]
 fn test_lexer() { // TODO
@@ -167,9 +170,24 @@ fn test_escape_sequences() {
     assert_text("`>", ">");
     assert_text("`#", "#");
     assert_text("`n", "\n");
+    assert_text("`t", "\t");
+    assert_text("`r", "\r");
+    assert_text("`0", "\0");
+    assert_text("`u{1F600}", "\u{1F600}");
+    assert_text("a`u{200D}b`u{a0}c", "a\u{200D}b\u{A0}c");
+    assert_text("\\`u{0041}`u{10FFFF}\\", "A\u{10FFFF}");
     // Invalid escapes
     assert_invalid_expression("`a");
     assert_invalid_expression("`1");
+    assert_invalid_expression("`u");
+    assert_invalid_expression("`u1F600");
+    assert_invalid_expression("`u{}");
+    assert_invalid_expression("`u{x}");
+    assert_invalid_expression("`u{+1}");
+    assert_invalid_expression("`u{1F600");
+    assert_invalid_expression("`u{0001F600}");
+    assert_invalid_expression("`u{110000}");
+    assert_invalid_expression("`u{D800}");
     assert_invalid_expression("[`");
     assert_invalid_expression("n`");
 }
@@ -418,28 +436,95 @@ fn assert_dictionary(source: &str, size: usize) {
 /// <tag> → "<"<word>_<attributes> ">"
 /// <attributes> → <attribute>_<attributes>
 /// ```
+#[test]
 fn test_required_whitespace() {
-    let source = "key: {value}key: value";
+    assert_whitespace_error("key: {value}other: value", Strictness::Standard, Rule::Entry, Rule::Entry);
+    assert_whitespace_error("a: 1\nb: {2}c: 3", Strictness::Standard, Rule::Entry, Rule::Entry);
+    assert_whitespace_error("{a}:b", Strictness::Lenient, Rule::Colon, Rule::Value);
+    assert_whitespace_error("[a]:b; c", Strictness::Lenient, Rule::Colon, Rule::List);
+    assert_whitespace_error(">a", Strictness::Lenient, Rule::RightAngle, Rule::Value);
+    assert_whitespace_error("> a\n>b", Strictness::Lenient, Rule::RightAngle, Rule::Value);
+    assert_whitespace_error("a: 1{b}: 2", Strictness::Strict, Rule::Dictionary, Rule::Section);
+    assert_whitespace_error("{a}: 1\n{b}: 2[c]: 3", Strictness::Strict, Rule::Section, Rule::Section);
+    assert_whitespace_error("<a>: x<b>: y", Strictness::Strict, Rule::TaggedValue, Rule::TaggedValue);
 }
 
-fn assert_err() {
+/// Assert that a document has a single whitespace error at a strictness, and
+/// none at the strictness below.
+fn assert_whitespace_error(source: &str, strictness: Strictness, before: Rule, after: Rule) {
+    let errors = whitespace_errors(source, strictness);
+    assert!(matches!(&errors[..], [ParseError::ExpectedWhitespace(_, b, a, ..) | ParseError::UnexpectedWhitespace(_, b, a, ..)] if *b == before && *a == after), "{:?} {:?}", source, errors);
+    let lower = match strictness {
+        Strictness::Lenient => return,
+        Strictness::Standard => Strictness::Lenient,
+        Strictness::Strict => Strictness::Standard,
+    };
+    assert!(whitespace_errors(source, lower).is_empty(), "{:?}", source);
+}
 
+/// Parse a document and get its whitespace errors.
+fn whitespace_errors(source: &str, strictness: Strictness) -> Vec<ParseError> {
+    let options = ParseOptions { strictness, ..ParseOptions::default() };
+    match parse_document_str_with(source, &options, &mut Interner::new()) {
+        Ok(..) => vec![],
+        Err(errors) => {
+            assert!(errors.iter().all(|e| matches!(e, ParseError::ExpectedWhitespace(..) | ParseError::UnexpectedWhitespace(..))), "{:?} {:?}", source, errors);
+            errors
+        }
+    }
 }
 
 /// Test grammar rules where whitespace is disallowed.
 ///
 /// ```text
-/// <tagged-value> → <tag>":"_<value>
-///
-///
+/// <key> → <string>":"<key>
+/// <curly-header> → "{"<key>"}"
+/// <square-header> → "["<key>"]"
+/// <tag> → "<"<word>">"
+/// <attribute> → <word>":"<string>
+/// <arguments> → ":"<argument><arguments>
 /// ```
+#[test]
 fn test_disallowed_whitespace() {
+    assert_whitespace_error("a:b : x", Strictness::Lenient, Rule::String, Rule::Colon);
+    assert_whitespace_error("{ a}: x", Strictness::Lenient, Rule::BracketOpen, Rule::Key);
+    assert_whitespace_error("[a ]: x", Strictness::Standard, Rule::Key, Rule::Close);
+    assert_whitespace_error("{a: b}: x", Strictness::Lenient, Rule::Colon, Rule::String);
+    assert_whitespace_error("< a>:x", Strictness::Lenient, Rule::AngularBracket, Rule::Name);
+    assert_whitespace_error("<a >:x", Strictness::Standard, Rule::Name, Rule::Close);
+    assert_whitespace_error("<a b: c>:x", Strictness::Standard, Rule::Colon, Rule::String);
+    assert_whitespace_error("<a>:x: y", Strictness::Lenient, Rule::Colon, Rule::Argument);
+}
 
+/// Test that whitespace errors point at where the whitespace is or should be.
+#[test]
+fn test_whitespace_error_position() {
+    for (source, line, column) in [("{ a}: x", 1, 2), ("[a ]: x", 1, 3), ("<a >:x", 1, 3), ("a:b : x", 1, 4), ("{a: b}: x", 1, 4), ("{a}:b", 1, 5), ("> a\n>b", 2, 2), ("key: {value}other: value", 1, 13)] {
+        let errors = whitespace_errors(source, Strictness::Standard);
+        match &errors[..] {
+            [ParseError::ExpectedWhitespace(at, ..) | ParseError::UnexpectedWhitespace(at, ..)] => assert_eq!((at.line, at.column), (line, column), "{:?}", source),
+            _ => panic!("{:?} {:?}", source, errors),
+        }
+    }
+}
+
+/// Test that documents parse with the default options whatever their
+/// whitespace around the ends of tags and headers and between entries.
+#[test]
+fn test_default_strictness() {
+    assert_eq!(ParseOptions::default().strictness, Strictness::Lenient);
+    assert!(parse_dictionary_str("key: {value}other: value").is_ok());
+    assert!(parse_value_str("<a >:x").is_ok());
+    assert!(parse_dictionary_str("[a ]: x").is_ok());
+    assert!(parse_dictionary_str("{ a}: x").is_err());
 }
 
 /// Test grammar rules where whitespace is optional.
+#[test]
 fn test_optional_whitespace() {
-
+    for source in ["{ a: 1 }", "{a:1}", "[ a; b ]", "a ; b", "<a b >:x", "<a b:c d>: x", "a:b: x", "k1:v1;k2:v2", "{a => b}", "{a=>b}", "a | b", "a|b", "|a| |b|\n|c|", "a: \\x\nb: y"] {
+        assert!(whitespace_errors(source, Strictness::Strict).is_empty(), "{:?}", source);
+    }
 }
 
 #[test]
@@ -466,3 +551,28 @@ fn test_compound() {
     let compound = parse.as_compound().unwrap();
     assert!(compound.whitespace.get(0).unwrap());
 }
+
+#[test]
+fn test_format_round_trip() {
+    assert_round_trip("Hello world!");
+    assert_round_trip("{k1: v1; k2: a | b; k3: [1; 2; 3]}");
+    assert_round_trip("<a x y:z>:b:{c d}:[e] {f} <g>~h");
+    assert_round_trip("\\ leading and  double  spaces\\");
+    assert_round_trip("`:`;`|`~```\\`{`}`[`]`<`>`#");
+    assert_round_trip("<>:{a | b}");
+    assert_round_trip("<>");
+    assert_round_trip("{~} {Text [Table]}");
+    assert_round_trip("<#>\n  line 1\n    line 2\n<#>");
+    assert_round_trip("{\\key with spaces\\: x}");
+    assert_round_trip("a`u{200D}b c`0d `u{1F600}");
+    assert_round_trip("\\a`u{A0}b`rc`0\\");
+    assert_eq!(format_value(&parse_value_str("a`u{200d}b c`u{a0}d").unwrap()), "\\a`u{200D}b c`u{A0}d\\");
+    assert_eq!(format_value(&parse_value_str("`u{1F600}`r").unwrap()), "\\\u{1F600}`r\\");
+}
+
+fn assert_round_trip(source: &str) {
+    let document = parse_value_str(source).unwrap();
+    let formatted = format_value(&document);
+    let reparsed = parse_value_str(&formatted).unwrap();
+    assert!(equal(&document, &reparsed), "{} formatted as {}", source, formatted);
+}
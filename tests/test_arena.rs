@@ -0,0 +1,96 @@
+#![cfg(feature = "arena")]
+
+use std::fs;
+use khi::{Compound, Dictionary, Element, List, Tagged, Text, Tuple, Value};
+use khi::arena::{Arena, ArenaValue, parse_arena_dictionary, parse_arena_dictionary_with, parse_arena_list, parse_arena_value, parse_arena_value_with};
+use khi::diff::equal;
+use khi::parse::{ParseOptions, parse_dictionary_str, parse_list_str, parse_value_str};
+use khi::parse::parser::ParseError;
+use khi::pdm::{ParsedValue, Position};
+
+const P: Position = Position { index: 0, line: 0, column: 0 };
+
+#[test]
+fn test_arena_matches_examples() {
+    let mut arena = Arena::new();
+    for path in ["examples/equations.tex.khi", "examples/frontpage.html.khi", "examples/fruits.xml.khi", "examples/style.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_value_str(&document).unwrap();
+        let value = parse_arena_value(&arena, &document).unwrap();
+        assert!(equal(&value.to_parsed(), &tree), "{}", path);
+        arena.reset();
+    }
+    for path in ["examples/aluminium.a", "examples/materials.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_dictionary_str(&document).unwrap();
+        let dictionary = parse_arena_dictionary(&arena, &document).unwrap();
+        assert!(equal(&ParsedValue::Dictionary(dictionary.to_parsed(), P, P), &ParsedValue::Dictionary(tree, P, P)), "{}", path);
+        arena.reset();
+    }
+    for path in ["examples/elements.khi", "examples/inventory-log.khi", "examples/primes.khi", "examples/server-log.khi", "examples/text-blocks.khi", "examples/words.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_list_str(&document).unwrap();
+        let list = parse_arena_list(&arena, &document).unwrap();
+        assert!(equal(&ParsedValue::List(list.to_parsed(), P, P), &ParsedValue::List(tree, P, P)), "{}", path);
+        arena.reset();
+    }
+}
+
+#[test]
+fn test_arena_value_traits() {
+    let arena = Arena::new();
+    let mut dictionary = parse_arena_dictionary(&arena, "b: 2\na:x: 1\nt: <p id:main>:{Some <b>:bold text}\nl: [1; 2; 3]\nu: 1|2\na:y: 3").unwrap();
+    assert_eq!(dictionary.len(), 5);
+    let keys: Vec<&str> = dictionary.iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, vec!["a", "b", "l", "t", "u"]);
+    let a = dictionary.get("a").unwrap().as_dictionary().unwrap();
+    assert_eq!(a.get("y").unwrap().as_text().unwrap().as_str(), "3");
+    let tag = dictionary.get("t").unwrap().as_tagged().unwrap();
+    assert_eq!(tag.name(), "p");
+    assert!(tag.get_attribute_by("id").is_some());
+    let compound = tag.get().as_compound().unwrap();
+    let whitespace = compound.iter().filter(|e| matches!(e, Element::Whitespace)).count();
+    assert_eq!(compound.len(), compound.iter().count());
+    assert_eq!(whitespace, 2);
+    let list = dictionary.get("l").unwrap().as_list().unwrap();
+    let elements: Vec<&str> = list.iter().map(|v| v.as_text().unwrap().as_str()).collect();
+    assert_eq!(elements, vec!["1", "2", "3"]);
+    let tuple = dictionary.get("u").unwrap();
+    assert_eq!(tuple.len_as_tuple(), 2);
+    assert_eq!(tuple.as_tuple().unwrap().get(1).unwrap().as_text().unwrap().as_str(), "2");
+    assert_eq!(dictionary.get("b").unwrap().iter_as_tuple().count(), 1);
+    let b = dictionary.get_mut("b").unwrap();
+    b.as_mut_text().unwrap().str = "changed";
+    assert_eq!(dictionary.get("b").unwrap().as_text().unwrap().as_str(), "changed");
+}
+
+#[test]
+fn test_arena_errors() {
+    let arena = Arena::new();
+    assert!(parse_arena_dictionary(&arena, "a: 1\na: 2").is_err());
+    assert!(parse_arena_dictionary(&arena, "a:b: 1\na:b: 2").is_err());
+    assert!(parse_arena_value(&arena, "{a").is_err());
+}
+
+#[test]
+fn test_arena_with_options() {
+    let arena = Arena::new();
+    let options = ParseOptions { max_depth: 2, ..ParseOptions::default() };
+    assert!(parse_arena_value_with(&arena, "<a>:{<b>:{x}}", &options).is_ok());
+    let errors = parse_arena_value_with(&arena, "{{{x}}}", &options).err().unwrap();
+    assert!(matches!(errors[..], [ParseError::TooDeep(_, 2)]));
+    let errors = parse_arena_dictionary_with(&arena, "a:b:c:d: x", &options).err().unwrap();
+    assert!(matches!(errors[..], [ParseError::TooDeep(_, 2)]));
+}
+
+#[test]
+fn test_arena_frees_without_destructors() {
+    assert!(!std::mem::needs_drop::<ArenaValue>());
+    let mut arena = Arena::with_capacity(1 << 16);
+    for _ in 0..1000 {
+        let list = parse_arena_list(&arena, "{a: 1; b: x y z}; <t>:{c}").unwrap();
+        assert_eq!(list.len(), 2);
+        arena.reset();
+    }
+    assert!(arena.allocated_bytes() < 1 << 20);
+}
@@ -0,0 +1,68 @@
+use std::borrow::Cow;
+use std::fs;
+use khi::borrowed::{BorrowedValue, parse_borrowed_dictionary, parse_borrowed_list, parse_borrowed_value};
+use khi::diff::equal;
+use khi::parse::{parse_dictionary_str, parse_list_str, parse_value_str};
+use khi::pdm::{ParsedValue, Position};
+
+const P: Position = Position { index: 0, line: 0, column: 0 };
+
+#[test]
+fn test_borrowed_matches_examples() {
+    for path in ["examples/equations.tex.khi", "examples/frontpage.html.khi", "examples/fruits.xml.khi", "examples/style.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_value_str(&document).unwrap();
+        let borrowed = parse_borrowed_value(&document).unwrap();
+        assert!(equal(&borrowed.to_parsed(), &tree), "{}", path);
+    }
+    for path in ["examples/aluminium.a", "examples/materials.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_dictionary_str(&document).unwrap();
+        let borrowed = parse_borrowed_dictionary(&document).unwrap();
+        assert!(equal(&ParsedValue::Dictionary(borrowed.to_parsed(), P, P), &ParsedValue::Dictionary(tree, P, P)), "{}", path);
+    }
+    for path in ["examples/elements.khi", "examples/inventory-log.khi", "examples/primes.khi", "examples/server-log.khi", "examples/text-blocks.khi", "examples/words.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_list_str(&document).unwrap();
+        let borrowed = parse_borrowed_list(&document).unwrap();
+        assert!(equal(&ParsedValue::List(borrowed.to_parsed(), P, P), &ParsedValue::List(tree, P, P)), "{}", path);
+    }
+}
+
+#[test]
+fn test_borrowed_text() {
+    let document = "name: Oak planks\nnote: a`:b\nkey`;: x\nwrapped: first\n  second\nraw: <#x r>text<#x>\ntrimmed: <#x>\n  text\n<#x>\ns::s: \\tr\\";
+    let dictionary = parse_borrowed_dictionary(document).unwrap();
+    let text = |key: &str| dictionary.get(key).unwrap().as_text().unwrap().clone();
+    assert!(matches!(text("name"), Cow::Borrowed("Oak planks")));
+    assert!(matches!(text("note"), Cow::Owned(ref s) if s == "a:b"));
+    assert!(matches!(text("wrapped"), Cow::Owned(ref s) if s == "first second"));
+    assert!(matches!(text("raw"), Cow::Borrowed("text")));
+    assert!(matches!(text("trimmed"), Cow::Owned(ref s) if s == "text\n"));
+    assert!(matches!(text("s:s"), Cow::Borrowed("tr")));
+    let keys: Vec<bool> = ["name", "key;", "s:s"].iter().map(|k| {
+        matches!(dictionary.entries.get_key_value(*k).unwrap().0, Cow::Borrowed(..))
+    }).collect();
+    assert_eq!(keys, vec![true, false, false]);
+}
+
+#[test]
+fn test_borrowed_carriage_returns() {
+    let list = parse_borrowed_list("> a b\r\n> c`;\r\n").unwrap();
+    let texts: Vec<&str> = list.iter().map(|v| v.as_text().unwrap().as_ref()).collect();
+    assert_eq!(texts, vec!["a b", "c;"]);
+    assert!(matches!(list.get(0), Some(BorrowedValue::Text(Cow::Borrowed(..), ..))));
+}
+
+#[test]
+fn test_borrowed_structure() {
+    let value = parse_borrowed_value("<p id:main>:{Some <b>:bold text}").unwrap();
+    let tag = value.as_tagged().unwrap();
+    assert!(matches!(tag.name, Cow::Borrowed("p")));
+    assert!(matches!(tag.get_attribute("id"), Some(Some(Cow::Borrowed("main")))));
+    assert!(value.to_parsed().to().index > 0);
+    let dictionary = parse_borrowed_dictionary("a:b: 1\na:c: 2").unwrap();
+    assert_eq!(dictionary.get("a").unwrap().as_dictionary().unwrap().len(), 2);
+    assert!(parse_borrowed_dictionary("a: 1\na: 2").is_err());
+    assert!(parse_borrowed_value("{a").is_err());
+}
@@ -0,0 +1,29 @@
+use std::collections::HashSet;
+use khi::condition::{ConditionError, evaluate_conditionals};
+use khi::diff::equal;
+use khi::parse::parse_value_str;
+
+fn evaluate(source: &str, defines: &[&str]) -> khi::pdm::ParsedValue {
+    let defines: HashSet<String> = defines.iter().map(|d| d.to_string()).collect();
+    let mut document = parse_value_str(source).unwrap();
+    evaluate_conditionals(&mut document, &defines).unwrap();
+    document
+}
+
+#[test]
+fn test_conditionals() {
+    let source = "<p>:Title <if!>:draft:{<p>:Draft} <else!>:{<p>:Final} <if!>:!nb:{<p>:English}";
+    let draft = evaluate(source, &["draft"]);
+    assert!(equal(&draft, &parse_value_str("<p>:Title <p>:Draft <p>:English").unwrap()));
+    let final_nb = evaluate(source, &["nb"]);
+    assert!(equal(&final_nb, &parse_value_str("<p>:Title <p>:Final").unwrap()));
+    let list = evaluate("[a; <if!>:x:b; <else!>:c; d]", &[]);
+    assert!(equal(&list, &parse_value_str("[a; c; d]").unwrap()));
+}
+
+#[test]
+fn test_conditional_errors() {
+    let mut document = parse_value_str("a <else!>:b <if!>:c").unwrap();
+    let errors = evaluate_conditionals(&mut document, &HashSet::new()).unwrap_err();
+    assert!(matches!(errors[..], [ConditionError::UnmatchedElse(..), ConditionError::IllegalConditional(..)]));
+}
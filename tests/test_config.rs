@@ -0,0 +1,29 @@
+use khi::config::{ConfigError, ConfigLoader};
+use khi::merge::Source;
+
+#[test]
+fn test_config_layers() {
+    let mut loader = ConfigLoader::new();
+    loader.document("defaults.khi", "db: {host: localhost; port: 5432}\nname: service").unwrap();
+    loader.document("production.khi", "db: {host: db.internal}").unwrap();
+    loader.variables("APP", vec![
+        ("APP__DB__PORT".to_string(), "6543".to_string()),
+        ("OTHER__NAME".to_string(), "x".to_string()),
+    ]);
+    let remaining = loader.args(vec!["--set".to_string(), "db.pool={size: 4}".to_string(), "-v".to_string()]).unwrap();
+    assert_eq!(remaining, vec!["-v".to_string()]);
+    let config = loader.load();
+    assert_eq!(config.explain("db.host").unwrap(), "db.host = db.internal (from production.khi:1:12)");
+    assert_eq!(config.explain("db.port").unwrap(), "db.port = 6543 (from environment variable APP__DB__PORT)");
+    assert_eq!(config.explain("db.pool.size").unwrap(), "db.pool.size = 4 (from command line argument db.pool={size: 4})");
+    assert_eq!(config.explain("name").unwrap(), "name = service (from defaults.khi:2:7)");
+    assert!(matches!(config.origin("db.port").unwrap().source, Source::Environment(..)));
+}
+
+#[test]
+fn test_config_errors() {
+    let mut loader = ConfigLoader::new();
+    assert!(matches!(loader.document("broken.khi", "a: {"), Err(ConfigError::ParseError(..))));
+    assert!(matches!(loader.set("db.port"), Err(ConfigError::InvalidOverride(..))));
+    assert!(matches!(loader.file("missing.khi"), Err(ConfigError::IoError(..))));
+}
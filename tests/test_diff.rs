@@ -0,0 +1,43 @@
+use khi::diff::{ChangeKind, diff_dictionaries, diff_lists, render_diff, Step};
+use khi::parse::{parse_dictionary_str, parse_list_str};
+
+#[test]
+fn test_diff_dictionary() {
+    let a = parse_dictionary_str("name: Oak planks\nprice: 200\ntags: [wood]").unwrap();
+    let b = parse_dictionary_str("name: Oak planks\nprice: 250\nbeauty: 1\ntags: [wood]").unwrap();
+    let changes = diff_dictionaries(&a, &b);
+    assert_eq!(changes.len(), 2);
+    assert!(matches!(changes[0].kind, ChangeKind::Added(..)));
+    assert!(changes[0].path == vec![Step::Key("beauty".into())]);
+    assert!(matches!(changes[1].kind, ChangeKind::Changed(..)));
+    assert_eq!(changes[1].before.unwrap().0.line, 2);
+    assert_eq!(changes[1].after.unwrap().0.line, 2);
+    assert_eq!(render_diff(&changes), "+ beauty: 1 (3:9)\n~ price: 200 -> 250 (2:8 -> 2:8)\n");
+}
+
+#[test]
+fn test_diff_realigned_table() {
+    let a = parse_list_str("| 1 | H | Hydrogen |\n| 2 | He | Helium |\n| 3 | Li | Lithium |").unwrap();
+    let b = parse_list_str("|  1 |  H | Hydrogen |\n|  3 | Li |  Lithium |").unwrap();
+    let changes = diff_lists(&a, &b);
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(changes[0].kind, ChangeKind::Deleted(1, ..)));
+    assert_eq!(render_diff(&changes), "- [1]: 2 | He | Helium (2:3)\n");
+}
+
+#[test]
+fn test_diff_tags() {
+    let a = parse_list_str("<Gas x:1 y>:a; b").unwrap();
+    let b = parse_list_str("<Solid x:2 z>:c; b; d").unwrap();
+    let changes = diff_lists(&a, &b);
+    let kinds: Vec<&str> = changes.iter().map(|c| match c.kind {
+        ChangeKind::Renamed(..) => "renamed",
+        ChangeKind::AttributeChanged(..) => "attribute changed",
+        ChangeKind::AttributeRemoved(..) => "attribute removed",
+        ChangeKind::AttributeAdded(..) => "attribute added",
+        ChangeKind::Changed(..) => "changed",
+        ChangeKind::Inserted(..) => "inserted",
+        _ => "other",
+    }).collect();
+    assert_eq!(kinds, vec!["renamed", "attribute changed", "attribute removed", "attribute added", "changed", "inserted"]);
+}
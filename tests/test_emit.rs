@@ -0,0 +1,129 @@
+use khi::{Dictionary, Tagged, Value};
+use khi::diff::equal;
+use khi::emit::{EmitError, Emitter, Notation};
+use khi::parse::{parse_dictionary_str, parse_list_str, parse_value_str};
+use khi::pdm::{ParsedValue, Position};
+
+const P: Position = Position { index: 0, line: 0, column: 0 };
+
+#[test]
+fn test_emit_dictionary() {
+    for notation in [Notation::Aligned, Notation::Delimited] {
+        let mut emitter = Emitter::dictionary_document(vec![], notation);
+        emitter.key("name").unwrap();
+        emitter.text("Oak planks").unwrap();
+        emitter.key("size").unwrap();
+        emitter.begin_dictionary().unwrap();
+        emitter.key("width").unwrap();
+        emitter.text("2").unwrap();
+        emitter.key("tags").unwrap();
+        emitter.begin_list().unwrap();
+        emitter.text("wood").unwrap();
+        emitter.text("a; b").unwrap();
+        emitter.begin_list().unwrap();
+        emitter.end().unwrap();
+        emitter.end().unwrap();
+        emitter.end().unwrap();
+        emitter.key("key with spaces").unwrap();
+        emitter.begin_dictionary().unwrap();
+        emitter.end().unwrap();
+        let output = String::from_utf8(emitter.finish().unwrap()).unwrap();
+        let expected = parse_dictionary_str("name: Oak planks; size: {width: 2; tags: [wood; \\a; b\\; []]}; \\key with spaces\\: {}").unwrap();
+        let dictionary = parse_dictionary_str(&output).unwrap();
+        assert!(equal(&ParsedValue::Dictionary(dictionary, P, P), &ParsedValue::Dictionary(expected, P, P)), "{}", output);
+    }
+    let mut emitter = Emitter::dictionary_document(vec![], Notation::Aligned);
+    emitter.key("a").unwrap();
+    emitter.begin_dictionary().unwrap();
+    emitter.key("b").unwrap();
+    emitter.begin_list().unwrap();
+    emitter.text("1").unwrap();
+    emitter.text("2").unwrap();
+    emitter.end().unwrap();
+    emitter.end().unwrap();
+    emitter.key("c").unwrap();
+    emitter.text("3").unwrap();
+    assert_eq!(String::from_utf8(emitter.finish().unwrap()).unwrap(), "a: {\n  b: [\n    > 1\n    > 2\n  ]\n}\nc: 3\n");
+}
+
+#[test]
+fn test_emit_list() {
+    let mut emitter = Emitter::list_document(vec![], Notation::Delimited);
+    emitter.text("2").unwrap();
+    emitter.text("3").unwrap();
+    emitter.text("5").unwrap();
+    assert_eq!(String::from_utf8(emitter.finish().unwrap()).unwrap(), "2;\n3;\n5\n");
+    let mut emitter = Emitter::list_document(vec![], Notation::Aligned);
+    for line in ["2023-Nov-11", "2023-Nov-13"] {
+        emitter.tag("Start", &[("at", Some(line))]).unwrap();
+        emitter.text("server one").unwrap();
+        emitter.end().unwrap();
+    }
+    let output = String::from_utf8(emitter.finish().unwrap()).unwrap();
+    assert_eq!(output, "> <Start at:2023-Nov-11>:{server one}\n> <Start at:2023-Nov-13>:{server one}\n");
+    assert_eq!(parse_list_str(&output).unwrap().elements.len(), 2);
+}
+
+#[test]
+fn test_emit_tags() {
+    let mut emitter = Emitter::value_document(vec![], Notation::Delimited);
+    emitter.tag("p", &[]).unwrap();
+    emitter.tag("b", &[("class", Some("x y"))]).unwrap();
+    emitter.begin_dictionary().unwrap();
+    emitter.key("k").unwrap();
+    emitter.tag("br", &[]).unwrap();
+    emitter.end().unwrap();
+    emitter.end().unwrap();
+    emitter.end().unwrap();
+    emitter.end().unwrap();
+    let output = String::from_utf8(emitter.finish().unwrap()).unwrap();
+    assert_eq!(output, "<p>:<b class:\\x y\\>:{k: <br>}");
+    parse_value_str(&output).unwrap();
+}
+
+#[test]
+fn test_emit_text_blocks() {
+    let texts = ["line 1\nline 2\n", "\n  indented\n\ttab  \n", "contains <#> and <#1>\n"];
+    for text in texts {
+        let mut emitter = Emitter::dictionary_document(vec![], Notation::Aligned);
+        emitter.key("text").unwrap();
+        emitter.text(text).unwrap();
+        emitter.key("tagged").unwrap();
+        emitter.tag("code", &[]).unwrap();
+        emitter.text(text).unwrap();
+        emitter.end().unwrap();
+        let output = String::from_utf8(emitter.finish().unwrap()).unwrap();
+        let dictionary = parse_dictionary_str(&output).unwrap();
+        assert_eq!(dictionary.get("text").unwrap().as_text().unwrap().str.as_ref(), text);
+        let tagged = dictionary.get("tagged").unwrap().as_tagged().unwrap();
+        assert_eq!(tagged.get().as_text().unwrap().str.as_ref(), text);
+    }
+}
+
+#[test]
+fn test_emit_value() {
+    let value = parse_value_str("<p>:{Some <b>:bold text} | x").unwrap();
+    let mut emitter = Emitter::list_document(vec![], Notation::Aligned);
+    emitter.value(&value).unwrap();
+    emitter.tag("q", &[]).unwrap();
+    emitter.value(&value).unwrap();
+    emitter.end().unwrap();
+    let output = String::from_utf8(emitter.finish().unwrap()).unwrap();
+    let list = parse_list_str(&output).unwrap();
+    assert!(equal(&list.elements[0], &value));
+}
+
+#[test]
+fn test_emit_errors() {
+    let mut emitter = Emitter::dictionary_document(vec![], Notation::Aligned);
+    assert!(matches!(emitter.text("a"), Err(EmitError::ExpectedKey)));
+    emitter.key("a").unwrap();
+    assert!(matches!(emitter.key("b"), Err(EmitError::UnexpectedKey)));
+    assert!(matches!(emitter.end(), Err(EmitError::UnexpectedEnd)));
+    emitter.begin_list().unwrap();
+    assert!(matches!(emitter.key("b"), Err(EmitError::UnexpectedKey)));
+    assert!(matches!(emitter.finish(), Err(EmitError::Unfinished)));
+    let mut emitter = Emitter::value_document(vec![], Notation::Aligned);
+    emitter.text("a").unwrap();
+    assert!(matches!(emitter.text("b"), Err(EmitError::UnexpectedValue)));
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use khi::diff::equal;
+use khi::event::{Event, parse_dictionary_events, parse_list_events, parse_value_events};
+use khi::parse::{parse_dictionary_str, parse_list_str, parse_value_str};
+use khi::parse::parser::ParseError;
+use khi::pdm::{ParsedAttribute, ParsedCompound, ParsedDictionary, ParsedList, ParsedTaggedValue, ParsedText, ParsedTuple, ParsedValue, Position, SharedStr};
+
+const P: Position = Position { index: 0, line: 0, column: 0 };
+
+#[test]
+fn test_events_match_tree() {
+    let values = [
+        "Text with words",
+        "<p>:{Some <b>:bold text}",
+        "a|b|c",
+        "<>:a:b",
+        "<tag x y:z>:[1; 2; 3]",
+        "{a: 1; b:c: 2; b:d: 3}",
+        "x => 1 | y | z => 2",
+        "<f>:a:<g>:b",
+        "{}",
+        "[]",
+        "| a",
+        "a~b ~ c",
+    ];
+    for document in values {
+        let tree = parse_value_str(document).unwrap();
+        let mut events = parse_value_events(document).map(Result::unwrap).peekable();
+        assert!(equal(&build(&mut events), &tree), "{}", document);
+        assert!(events.next().is_none());
+    }
+    let dictionaries = ["a: 1\nb: {c: 2}", "a:b: 1\na:c: 2", "x: 0\n{a}:\nb: 1\nc: 2\n[d]: 1; 2"];
+    for document in dictionaries {
+        let tree = parse_dictionary_str(document).unwrap();
+        let mut events = parse_dictionary_events(document).map(Result::unwrap).peekable();
+        assert!(equal(&build(&mut events), &ParsedValue::Dictionary(tree, P, P)), "{}", document);
+    }
+}
+
+#[test]
+fn test_events_match_examples() {
+    for path in ["examples/equations.tex.khi", "examples/frontpage.html.khi", "examples/fruits.xml.khi", "examples/style.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_value_str(&document).unwrap();
+        let mut events = parse_value_events(&document).map(Result::unwrap).peekable();
+        assert!(equal(&build(&mut events), &tree), "{}", path);
+    }
+    for path in ["examples/aluminium.a", "examples/materials.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_dictionary_str(&document).unwrap();
+        let mut events = parse_dictionary_events(&document).map(Result::unwrap).peekable();
+        assert!(equal(&build(&mut events), &ParsedValue::Dictionary(tree, P, P)), "{}", path);
+    }
+    for path in ["examples/elements.khi", "examples/inventory-log.khi", "examples/primes.khi", "examples/server-log.khi", "examples/text-blocks.khi", "examples/words.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let tree = parse_list_str(&document).unwrap();
+        let mut events = parse_list_events(&document).map(Result::unwrap).peekable();
+        assert!(equal(&build(&mut events), &ParsedValue::List(tree, P, P)), "{}", path);
+    }
+}
+
+#[test]
+fn test_events() {
+    let events: Vec<Event> = parse_dictionary_events("name: Oak planks\nsize:width: 2").map(Result::unwrap).collect();
+    let keys: Vec<&str> = events.iter().filter_map(|e| match e {
+        Event::Key(.., key) => Some(key.as_ref()),
+        _ => None,
+    }).collect();
+    assert_eq!(keys, vec!["name", "size", "width"]);
+    assert!(matches!(&events[2], Event::Text(.., text) if text == "Oak planks"));
+    assert!(matches!(events.last(), Some(Event::End(..))));
+    assert_eq!(events.len(), 9);
+}
+
+#[test]
+fn test_event_errors() {
+    assert!(parse_value_events("{a").any(|event| event.is_err()));
+    assert!(parse_value_events("a: b").any(|event| event.is_err()));
+    assert!(parse_list_events("> a\n>b").any(|event| event.is_err()));
+    assert!(parse_dictionary_events("a: 1\n{b}: {c").any(|event| event.is_err()));
+}
+
+#[test]
+fn test_events_are_incremental() {
+    // The first elements are yielded before the rest of the document is read.
+    let mut events = parse_list_events("> a\n> b\n> c\n> {d");
+    assert!(matches!(events.next(), Some(Ok(Event::StartList(..)))));
+    assert!(matches!(events.next(), Some(Ok(Event::Text(.., text))) if text == "a"));
+    assert!(matches!(events.next(), Some(Ok(Event::Text(.., text))) if text == "b"));
+    assert!(matches!(events.next(), Some(Err(..))));
+    assert!(events.next().is_none());
+}
+
+#[test]
+fn test_events_depth() {
+    let document = "<a>:".repeat(100_000) + "x";
+    let errors: Vec<ParseError> = parse_value_events(&document).filter_map(Result::err).collect();
+    assert!(matches!(errors.as_slice(), [ParseError::TooDeep(..)]));
+    let document = "a:".repeat(100_000) + " x";
+    let errors: Vec<ParseError> = parse_dictionary_events(&document).filter_map(Result::err).collect();
+    assert!(matches!(errors.as_slice(), [ParseError::TooDeep(..)]));
+}
+
+/// Build a value from events, merging dictionaries assigned to the same key.
+fn build<'a, I: Iterator<Item = Event<'a>>>(events: &mut Peekable<I>) -> ParsedValue {
+    match events.next().unwrap() {
+        Event::Text(from, to, str) => ParsedValue::Text(ParsedText { str: str.into() }, from, to),
+        Event::Nil(from, to) => ParsedValue::Nil(from, to),
+        Event::StartTuple(from) => {
+            let mut values = vec![];
+            while !matches!(events.peek(), Some(Event::End(..))) {
+                values.push(build(events));
+            }
+            events.next();
+            let tuple = match values.len() {
+                0 => ParsedTuple::Unit,
+                1 => ParsedTuple::Single(Box::new(values.pop().unwrap())),
+                _ => ParsedTuple::Multiple(values.into_boxed_slice()),
+            };
+            ParsedValue::Tuple(tuple, from, P)
+        }
+        Event::StartCompound(from) => {
+            let mut components = vec![];
+            let mut whitespace = vec![];
+            let mut space = false;
+            loop {
+                match events.peek() {
+                    Some(Event::End(..)) => break,
+                    Some(Event::Whitespace) => {
+                        events.next();
+                        space = true;
+                    }
+                    _ => {
+                        if !components.is_empty() {
+                            whitespace.push(space);
+                        }
+                        space = false;
+                        components.push(build(events));
+                    }
+                }
+            }
+            events.next();
+            ParsedValue::Compound(ParsedCompound { components, whitespace }, from, P)
+        }
+        Event::StartList(from) => {
+            let mut elements = vec![];
+            while !matches!(events.peek(), Some(Event::End(..))) {
+                elements.push(build(events));
+            }
+            events.next();
+            ParsedValue::List(ParsedList { elements }, from, P)
+        }
+        Event::StartDictionary(from) => {
+            let mut entries = HashMap::new();
+            while let Some(Event::Key(.., key)) = events.next() {
+                let value = build(events);
+                insert(&mut entries, key.into(), value);
+            }
+            ParsedValue::Dictionary(ParsedDictionary { entries }, from, P)
+        }
+        Event::StartTag(from, name, attributes) => {
+            let attributes = attributes.into_iter().map(|(k, v)| ParsedAttribute(k.into(), v.map(|v| v.into()))).collect();
+            let value = Box::new(build(events));
+            assert!(matches!(events.next(), Some(Event::End(..))));
+            ParsedValue::Tagged(ParsedTaggedValue { name: name.into(), attributes, value }, from, P)
+        }
+        Event::Key(..) | Event::Whitespace | Event::End(..) => panic!("Unexpected event."),
+    }
+}
+
+fn insert(entries: &mut HashMap<SharedStr, ParsedValue>, key: SharedStr, value: ParsedValue) {
+    match (entries.get_mut(&key), value) {
+        (Some(ParsedValue::Dictionary(existing, ..)), ParsedValue::Dictionary(dictionary, ..)) => {
+            for (key, value) in dictionary.entries {
+                insert(&mut existing.entries, key, value);
+            }
+        }
+        (_, value) => {
+            entries.insert(key, value);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::fs::File;
 use std::io::Read;
-use khi::parse::{parse_dictionary_str, parse_value_str, parse_list_str};
+use khi::parse::{detect_document_kind, parse_dictionary_str, parse_document_str, parse_value_str, parse_list_str, DocumentKind, ParsedDocument};
 
 #[test]
 fn test_example_article_aluminium() {
@@ -62,6 +62,62 @@ fn test_example_words() {
     parse_list_str(&read_document_file("examples/words.khi")).unwrap();
 }
 
+#[test]
+fn test_detect_example_kinds() {
+    let examples = [
+        ("examples/aluminium.a", DocumentKind::Dictionary),
+        ("examples/elements.khi", DocumentKind::List),
+        ("examples/equations.tex.khi", DocumentKind::Value),
+        ("examples/frontpage.html.khi", DocumentKind::Value),
+        ("examples/fruits.xml.khi", DocumentKind::Value),
+        ("examples/inventory-log.khi", DocumentKind::List),
+        ("examples/materials.khi", DocumentKind::Dictionary),
+        ("examples/primes.khi", DocumentKind::List),
+        ("examples/server-log.khi", DocumentKind::List),
+        ("examples/style.khi", DocumentKind::Value),
+        ("examples/text-blocks.khi", DocumentKind::Value),
+        ("examples/words.khi", DocumentKind::List),
+    ];
+    for (path, kind) in examples {
+        let document = read_document_file(path);
+        assert_eq!(detect_document_kind(&document).unwrap(), kind, "{}", path);
+        let stripped: String = document.lines().filter(|l| !l.contains("Document type:")).map(|l| format!("{}\n", l)).collect();
+        assert_eq!(detect_document_kind(&stripped).unwrap(), kind, "{} without pragma", path);
+        let parsed = parse_document_str(&stripped).unwrap();
+        let parsed_kind = match parsed {
+            ParsedDocument::Value(..) => DocumentKind::Value,
+            ParsedDocument::Dictionary(..) => DocumentKind::Dictionary,
+            ParsedDocument::List(..) => DocumentKind::List,
+        };
+        assert_eq!(parsed_kind, kind, "{}", path);
+    }
+}
+
+#[test]
+fn test_detect_document_kind() {
+    assert_eq!(detect_document_kind("a: 1\nb: 2").unwrap(), DocumentKind::Dictionary);
+    assert_eq!(detect_document_kind("{a}: 1").unwrap(), DocumentKind::Dictionary);
+    assert_eq!(detect_document_kind("> 1\n> 2").unwrap(), DocumentKind::List);
+    assert_eq!(detect_document_kind("1; 2; 3").unwrap(), DocumentKind::List);
+    assert_eq!(detect_document_kind("| 1 | 2 |").unwrap(), DocumentKind::List);
+    assert_eq!(detect_document_kind("<p>: a\n<p>: b").unwrap(), DocumentKind::List);
+    assert_eq!(detect_document_kind("<p>:{Text}").unwrap(), DocumentKind::Value);
+    assert_eq!(detect_document_kind("Text").unwrap(), DocumentKind::Value);
+    assert_eq!(detect_document_kind("").unwrap(), DocumentKind::Value);
+}
+
+#[test]
+fn test_document_pragma_overrides_structure() {
+    let document = "# Document type: List\n\nText";
+    assert_eq!(detect_document_kind(document).unwrap(), DocumentKind::List);
+    match parse_document_str(document).unwrap() {
+        ParsedDocument::List(list) => assert_eq!(list.elements.len(), 1),
+        _ => panic!("Expected list document."),
+    }
+    let document = "Text\n# Document type: List";
+    assert_eq!(detect_document_kind(document).unwrap(), DocumentKind::Value);
+}
+
 fn read_document_file(path: &str) -> String {
     let mut file = File::open(path).unwrap();
     let mut document = String::new();
@@ -0,0 +1,143 @@
+use std::fs;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use khi::parse::{parse_dictionary_str, parse_list_str, parse_value_str};
+
+/// Fragments spliced into documents by the mutator.
+const FRAGMENTS: &[&str] = &[
+    "{", "}", "[", "]", "<", ">", ":", ";", "|", "||", "~", "`", "\\", "#", "<#>", "<#:a>", "'", "!", "$",
+    " ", "\n", "\t", "> ", ": ", "a", "x y", "<a>", "<a>:", "<a b:c>:", "<>", "<def!>:", "<lines!>:", "<raw!>:",
+    "<doctype!>:", "<$>:", "<p>", "<n>", "<b'>:", "<@>:", "<@x>:", "<@def>:", "<@if>:", "`n", "`u", "`",
+];
+
+/// Malformed documents, mostly targeting preprocessor macros.
+const CORPUS: &[&str] = &[
+    "", " ", "\n", "{", "}", "[", "]", "<", ">", ":", ";", "|", "~", "`", "\\", "<#>", "<>", "<a", "<a>:", "<a>:{",
+    "{a: }", "{:}", "[;]", "[|]", "a|", "|a", "a:b:", "<a>:<b>:", "<#>a", "\\a", "> ", ">", "\n>\n> a",
+    "<def!>", "<def!>:a", "<def!>:a|b", "<def!>:{a: b}:1:x", "<def!>:[a]:1:x", "<def!>:a:1:{a: b}", "<def!>:<x>:1:y", "<def!>:a:[1]:x", "<def!>:a:b:c:d",
+    "<lines!>", "<lines!>:{a: b}", "<lines!>:[a; b]", "<lines!>:<a>", "<lines!>:a:b", "<lines!>:{a b}",
+    "<raw!>", "<raw!>:a:b", "<raw!>:{a: b}", "<raw!>:<a>",
+    "<doctype!>", "<doctype!>:a:b", "<doctype!>:{a: b}", "<doctype! a>:html", "<unknown!>:x",
+    "<$>:{a: b}", "<$>:[a]", "<$>:a|b", "<b'>", "<b'>:{a: b}", "<b'>:[a]:c", "<b'>:a:b:c", "<b>:{a: b}", "<b>:[a]|[b]",
+    "<p>:a|b", "<p>:{}", "<p>:{~}", "<p>:[a|b; c]", "<p a b:c>:a|b", "[a|b; {c: d}]", "[{a: b}|c]", "{a|b}", "{a [b]}",
+];
+
+fn mutate(document: &str, seed: &mut u64, mutations: usize) -> String {
+    let mut chars: Vec<char> = document.chars().collect();
+    for _ in 0..mutations {
+        let at = if chars.is_empty() { 0 } else { next(seed) % (chars.len() + 1) };
+        match next(seed) % 4 {
+            0 => {
+                let end = (at + next(seed) % 8).min(chars.len());
+                chars.drain(at..end);
+            }
+            1 => {
+                let end = (at + next(seed) % 16).min(chars.len());
+                let slice: Vec<char> = chars[at..end].to_vec();
+                let to = next(seed) % (chars.len() + 1);
+                chars.splice(to..to, slice);
+            }
+            2 => {
+                chars.truncate(at);
+            }
+            _ => {
+                let fragment = FRAGMENTS[next(seed) % FRAGMENTS.len()];
+                chars.splice(at..at, fragment.chars());
+            }
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// Xorshift, so that failures are reproducible.
+fn next(seed: &mut u64) -> usize {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed as usize
+}
+
+/// Documents derived from the examples and the corpus.
+fn documents() -> Vec<String> {
+    let mut documents: Vec<String> = CORPUS.iter().map(|d| d.to_string()).collect();
+    let mut seed = 0x2545F4914F6CDD1D;
+    for a in CORPUS {
+        for b in CORPUS {
+            documents.push(format!("{}{}", a, b));
+        }
+    }
+    for entry in fs::read_dir("examples").unwrap() {
+        let document = fs::read_to_string(entry.unwrap().path()).unwrap();
+        for i in 0..200 {
+            documents.push(mutate(&document, &mut seed, 1 + i % 8));
+        }
+        documents.push(document);
+    }
+    for _ in 0..2000 {
+        let document = CORPUS[next(&mut seed) % CORPUS.len()];
+        let mutations = 1 + next(&mut seed) % 6;
+        documents.push(mutate(document, &mut seed, mutations));
+    }
+    documents
+}
+
+fn assert_no_panic<T>(document: &str, function: impl FnOnce() -> T) -> T {
+    match catch_unwind(AssertUnwindSafe(function)) {
+        Ok(result) => result,
+        Err(_) => panic!("Panicked on document: {:?}", document),
+    }
+}
+
+#[test]
+fn test_fuzz_parser() {
+    for document in documents() {
+        assert_no_panic(&document, || parse_value_str(&document).is_ok());
+        assert_no_panic(&document, || parse_dictionary_str(&document).is_ok());
+        assert_no_panic(&document, || parse_list_str(&document).is_ok());
+    }
+}
+
+#[cfg(feature = "tex")]
+#[test]
+fn test_fuzz_tex_writer() {
+    use khi::tex::{BreakMode, write_tex, write_tex_with};
+    for document in documents() {
+        if let Ok(value) = parse_value_str(&document) {
+            assert_no_panic(&document, || write_tex(&value).is_ok());
+            assert_no_panic(&document, || write_tex_with(&value, BreakMode::Never).is_ok());
+            assert_no_panic(&document, || write_tex_with(&value, BreakMode::Margin(20)).is_ok());
+        }
+    }
+}
+
+#[cfg(feature = "html")]
+#[test]
+fn test_fuzz_html_writer() {
+    use khi::html::write_html;
+    for document in documents() {
+        if let Ok(value) = parse_value_str(&document) {
+            assert_no_panic(&document, || write_html(&value).is_ok());
+        }
+    }
+}
+
+#[cfg(feature = "tex")]
+#[test]
+fn test_tex_macro_errors() {
+    use khi::tex::{PreprocessorError, write_tex};
+    for document in ["<def!>:{a: b}:1:x", "<def!>:[a]:1:x", "<def!>:a:b", "<lines!>:{a: b}", "<lines!>:[a; b]", "<raw!>:a:b"] {
+        let value = parse_value_str(&format!("\n  {}", document)).unwrap();
+        let error = write_tex(&value).err().unwrap();
+        assert!(matches!(error, PreprocessorError::MacroError(at, _) if at.line == 2 && at.column == 3), "{}", document);
+    }
+}
+
+#[cfg(feature = "html")]
+#[test]
+fn test_html_macro_errors() {
+    use khi::html::{PreprocessorError, write_html};
+    for document in ["<doctype!>:a:b", "<doctype!>:{a: b}", "<doctype! a>:html", "<raw!>:<a>", "<unknown!>:x"] {
+        let value = parse_value_str(&format!("\n  {}", document)).unwrap();
+        let error = write_html(&value).err().unwrap();
+        assert!(matches!(error, PreprocessorError::MacroError(at, _) if at.line == 2 && at.column == 3), "{}", document);
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use khi::{Dictionary, List, Value};
+use khi::diff::Step;
+use khi::include::{FileResolver, IncludeError, load_dictionary, load_dictionary_with};
+use khi::parse::ParseOptions;
+use khi::parse::parser::ParseError;
+
+#[test]
+fn test_include() {
+    let directory = std::env::temp_dir().join(format!("khi-test-include-{}", std::process::id()));
+    fs::create_dir_all(directory.join("regions")).unwrap();
+    fs::write(directory.join("main.khi"), "name: service\ninclude!: common.khi\nregions: [eu; <include! list splice>:regions/all.khi; local]").unwrap();
+    fs::write(directory.join("common.khi"), "timeout: 30\ndb: <include! dictionary>:db.khi").unwrap();
+    fs::write(directory.join("db.khi"), "host: localhost").unwrap();
+    fs::write(directory.join("regions/all.khi"), "us; ap").unwrap();
+    let mut resolver = FileResolver::with_root(&directory);
+    let included = load_dictionary(&mut resolver, "main.khi").unwrap();
+    let document = included.document;
+    assert_eq!(document.get("timeout").unwrap().as_text().unwrap().str.as_ref(), "30");
+    assert_eq!(document.get("db").unwrap().as_dictionary().unwrap().get("host").unwrap().as_text().unwrap().str.as_ref(), "localhost");
+    let regions = document.get("regions").unwrap().as_list().unwrap();
+    let regions: Vec<&str> = regions.iter().map(|r| r.as_text().unwrap().str.as_ref()).collect();
+    assert_eq!(regions, vec!["eu", "us", "ap", "local"]);
+    let sources = included.sources;
+    assert!(sources.document(&[Step::Key("name".into())]).ends_with("main.khi"));
+    assert!(sources.document(&[Step::Key("timeout".into())]).ends_with("common.khi"));
+    assert!(sources.document(&[Step::Key("db".into()), Step::Key("host".into())]).ends_with("db.khi"));
+    assert!(sources.document(&[Step::Key("regions".into()), Step::Index(2)]).ends_with("all.khi"));
+    assert!(sources.document(&[Step::Key("regions".into()), Step::Index(3)]).ends_with("main.khi"));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_include_cycle() {
+    let directory = std::env::temp_dir().join(format!("khi-test-include-cycle-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("a.khi"), "b: <include! dictionary>:b.khi").unwrap();
+    fs::write(directory.join("b.khi"), "a: <include! dictionary>:a.khi\nc: <include!>:missing.khi").unwrap();
+    let mut resolver = FileResolver::with_root(&directory);
+    let errors = match load_dictionary(&mut resolver, "a.khi") {
+        Ok(..) => panic!(),
+        Err(errors) => errors,
+    };
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().any(|e| matches!(e, IncludeError::Cycle(_, at, cycle) if at.line == 1 && cycle.len() == 3)));
+    assert!(errors.iter().any(|e| matches!(e, IncludeError::Unresolved(_, Some(at), _) if at.line == 2)));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_include_with() {
+    let directory = std::env::temp_dir().join(format!("khi-test-include-with-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("main.khi"), "include!: large.khi").unwrap();
+    fs::write(directory.join("large.khi"), format!("a: {}", "x".repeat(100))).unwrap();
+    let options = ParseOptions { max_document_size: 64, ..ParseOptions::default() };
+    let mut resolver = FileResolver::with_root(&directory);
+    assert!(load_dictionary(&mut resolver, "main.khi").is_ok());
+    let errors = match load_dictionary_with(&mut resolver, "main.khi", &options) {
+        Ok(..) => panic!(),
+        Err(errors) => errors,
+    };
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], IncludeError::ParseError(document, errors) if document.ends_with("large.khi") && matches!(errors[..], [ParseError::DocumentTooLarge(103, 64)])));
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use khi::Value;
+use khi::diff::equal;
+use khi::parse::{Interner, parse_dictionary_str, parse_dictionary_str_interned, parse_list_str_interned, parse_value_str_interned};
+use khi::pdm::{ParsedValue, Position, SharedStr};
+
+const P: Position = Position { index: 0, line: 0, column: 0 };
+
+fn key<'a>(dictionary: &'a khi::pdm::ParsedDictionary, key: &str) -> &'a SharedStr {
+    dictionary.entries.get_key_value(key).unwrap().0
+}
+
+#[test]
+fn test_interner_shares_strings_between_documents() {
+    let document = fs::read_to_string("examples/aluminium.a").unwrap();
+    let similar = document.replace("title: Aluminium", "title: Gallium").replace("number: 13", "number: 31");
+    let mut interner = Interner::new();
+    let a = parse_dictionary_str_interned(&document, &mut interner).unwrap();
+    let count = interner.len();
+    let b = parse_dictionary_str_interned(&similar, &mut interner).unwrap();
+    assert_eq!(interner.len(), count + 2);
+    assert!(SharedStr::ptr_eq(key(&a, "article"), key(&b, "article")));
+    let (a, b) = (a.entries.get("chemical-element").unwrap(), b.entries.get("chemical-element").unwrap());
+    let (ParsedValue::Dictionary(a, ..), ParsedValue::Dictionary(b, ..)) = (a, b) else { panic!() };
+    assert!(SharedStr::ptr_eq(key(a, "symbol"), key(b, "symbol")));
+    let (ParsedValue::Tagged(a, ..), ParsedValue::Tagged(b, ..)) = (a.entries.get("stp-phase").unwrap(), b.entries.get("stp-phase").unwrap()) else { panic!() };
+    assert!(SharedStr::ptr_eq(&a.name, &b.name));
+}
+
+#[test]
+fn test_interned_parse_matches_parse() {
+    let document = fs::read_to_string("examples/materials.khi").unwrap();
+    let mut interner = Interner::new();
+    let interned = parse_dictionary_str_interned(&document, &mut interner).unwrap();
+    let parsed = parse_dictionary_str(&document).unwrap();
+    assert!(equal(&ParsedValue::Dictionary(interned, P, P), &ParsedValue::Dictionary(parsed, P, P)));
+    assert!(!interner.is_empty());
+}
+
+#[test]
+fn test_interner_purge() {
+    let mut interner = Interner::new();
+    let kept = parse_value_str_interned("<kept>:{a b}", &mut interner).unwrap();
+    let dropped = parse_list_str_interned("x; y; z", &mut interner).unwrap();
+    let all = interner.len();
+    drop(dropped);
+    interner.purge();
+    assert_eq!(interner.len(), all - 3);
+    assert!(SharedStr::ptr_eq(&interner.intern("kept"), &kept.as_tagged().unwrap().name));
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use khi::diff::equal_dictionaries;
+use khi::interpolate::{interpolate_dictionary, InterpolationError};
+use khi::parse::parse_dictionary_str;
+
+#[test]
+fn test_interpolate() {
+    let variables: HashMap<&str, &str> = [("HOME", "/home/khi"), ("HOST", "alpha")].into_iter().collect();
+    let source = "\
+data: \\${HOME}/data\\
+cache: \\${CACHE:-/tmp}/khi\\
+price: $$5 and $x
+host: <env!>:HOST
+region: <env!>:REGION:eu
+path: <env!>:HOME~/bin
+block: <#>
+  Host ${HOST}
+<#>
+";
+    let mut document = parse_dictionary_str(source).unwrap();
+    interpolate_dictionary(&mut document, source, |name| variables.get(name).map(|v| v.to_string())).unwrap();
+    let expected = parse_dictionary_str("\
+data: /home/khi/data
+cache: /tmp/khi
+price: $5 and $x
+host: alpha
+region: eu
+path: /home/khi/bin
+block: \\Host alpha`n\\
+").unwrap();
+    assert!(equal_dictionaries(&document, &expected));
+}
+
+#[test]
+fn test_interpolate_errors() {
+    let source = "a: x\nb: \\${MISSING}\\\nc: \\${OPEN\\";
+    let mut document = parse_dictionary_str(source).unwrap();
+    let errors = interpolate_dictionary(&mut document, source, |_| None).unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().any(|e| matches!(e, InterpolationError::MissingVariable(at, name) if at.line == 2 && name == "MISSING")));
+    assert!(errors.iter().any(|e| matches!(e, InterpolationError::UnclosedPlaceholder(at) if at.line == 3)));
+}
+
+#[test]
+fn test_interpolate_error_positions() {
+    let source = "a: \\x ${ONE}\\\nb: <#>\n  x\n  y ${TWO}\n<#>\nc: \\``n ${THREE}\\\nd: x $`{FOUR`}\ne: <#>\n\t\tz\n\t\t${FIVE}\n\t<#>\n";
+    let mut document = parse_dictionary_str(source).unwrap();
+    let errors = interpolate_dictionary(&mut document, source, |_| None).unwrap_err();
+    assert_eq!(errors.len(), 5);
+    for (variable, line, column) in [("ONE", 1, 7), ("TWO", 4, 5), ("THREE", 6, 9), ("FOUR", 7, 6), ("FIVE", 10, 3)] {
+        assert!(errors.iter().any(|e| matches!(e, InterpolationError::MissingVariable(at, name) if at.line == line && at.column == column && name == variable)), "{} {:?}", variable, errors);
+    }
+}
@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+use std::fs;
+use khi::lex::{lex, lex_str, LexError, Token, Lexer};
+
+/// Lex with both lexers and check that they agree.
+fn check(document: &str) {
+    let chars = lex(document.chars());
+    let bytes = lex_str(document);
+    match (chars, bytes) {
+        (Ok(a), Ok(b)) => assert!(a == b, "{:?}", document),
+        (Err(a), Err(b)) => assert!(same_error(&a, &b), "{:?}", document),
+        _ => panic!("{:?}", document),
+    }
+}
+
+fn same_error(a: &LexError, b: &LexError) -> bool {
+    match (a, b) {
+        (LexError::EscapeEos, LexError::EscapeEos) => true,
+        (LexError::InvalidEscapeSequence(a), LexError::InvalidEscapeSequence(b)) => a == b,
+        (LexError::InvalidHashSequence(a), LexError::InvalidHashSequence(b)) => a == b,
+        (LexError::UnclosedTextBlock(a), LexError::UnclosedTextBlock(b)) => a == b,
+        (LexError::InvalidTextBlockConfiguration(a), LexError::InvalidTextBlockConfiguration(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[test]
+fn test_lexers_agree_on_examples() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let document = fs::read_to_string(entry.unwrap().path()).unwrap();
+        check(&document);
+    }
+}
+
+#[test]
+fn test_lexers_agree_on_snippets() {
+    let snippets = [
+        "", " ", "a", "a b", "a:b;c|d~e", "a::b;;c||d~~e<<f>>g", "{a}[b]<c>", "<<a", ">>", "<a", "a<", "a=>b", "a=>>b", "=>", "=",
+        "a # comment\nb", "a #", "#", "# comment", "## comment\n", "a#b", "a#::b", "a#:b", "a#{", "a##b", "a`:b", "a`", "a`q",
+        "\\transcription\\", "\\line\nnext", "\\open", "\\a`nb\\", "\\a`", "\\a`q\\",
+        "<#>text<#>", "<#x>\n  a\n  b\n<#x>", "<#x r>raw<#x>", "<#x tl>  a  \n  b  <#x>", "<#x n>\na\nb\n<#x>", "<#x q>a<#x>",
+        "<#x", "<#x>a", "<#x  r  >a<#x>", "<#x r ", "<#>a<#><#>b<#>",
+        "ä:ö ü€\n𝄞 # ß\n\\ñ`:\\", "é`:x", "<#é r>ü<#é>", "a\tb\n\n\tc",
+        "a\r\nb", "<#x r>\r\n<#x>", "\\a\r\n", "a\rb", "a \r b", "\r", "\r\r\n", "a:\r\nb", "\\a\rb\\", "\\a\r\r\nb", "# c\r\nx", "a #\r\n", "a # c\rd\r\nb",
+        "<#x>\r\n  a\r\n  b\r\n<#x>", "<#x\r\nr>a<#x>", "`\r\n",
+        "a`r`0b", "`u{1F600}", "a`u{200d}b", "\\`u{A0}\\", "`u", "`u{", "`u{}", "`u{12", "`u{1234567}", "`u{110000}", "`u{D800}", "`u{é}", "\\`u{x}\\",
+    ];
+    for snippet in snippets {
+        check(snippet);
+    }
+}
+
+#[test]
+fn test_lexer_borrows() {
+    let tokens = lex_str("word: \\tr`:\\ <#x r>raw<#x>").ok().unwrap();
+    assert!(matches!(tokens[0], Token::Word(_, Cow::Borrowed("word"))));
+    assert!(matches!(tokens[3], Token::Transcription(_, Cow::Owned(ref s)) if s == "tr:"));
+    assert!(matches!(tokens[5], Token::TextBlock(_, Cow::Borrowed("raw"))));
+    assert!(matches!(tokens[6], Token::End(..)));
+}
+
+#[test]
+fn test_lexer_borrows_across_line_breaks() {
+    let tokens = lex_str("word\r\n\\tr\r\n").ok().unwrap();
+    assert!(matches!(tokens[0], Token::Word(_, Cow::Borrowed("word"))));
+    assert!(matches!(tokens[2], Token::Transcription(_, Cow::Borrowed("tr"))));
+}
+
+#[test]
+fn test_lexer_stops_after_error() {
+    let mut lexer = Lexer::new("a `q b");
+    assert!(matches!(lexer.next(), Some(Ok(Token::Word(..)))));
+    assert!(matches!(lexer.next(), Some(Ok(Token::Whitespace(..)))));
+    assert!(matches!(lexer.next(), Some(Err(LexError::InvalidEscapeSequence(..)))));
+    assert!(lexer.next().is_none());
+}
+
+fn text_block(document: &str) -> String {
+    let chars = lex(document.chars()).ok().unwrap();
+    let bytes = lex_str(document).ok().unwrap();
+    assert!(chars == bytes, "{:?}", document);
+    match &bytes[0] {
+        Token::TextBlock(_, text) => text.to_string(),
+        _ => panic!("{:?}", document),
+    }
+}
+
+#[test]
+fn test_text_block_stops_at_first_closing_tag() {
+    assert_eq!(text_block("<#end r>a <#en <#end b<#end> c"), "a <#en <#end b");
+    assert_eq!(text_block("<#end r><#end> x"), "");
+    let mut lexed = lex("<#x r>a<#x> b <#x r>c<#x>".chars()).ok().unwrap().into_iter();
+    assert!(matches!(lexed.next(), Some(Token::TextBlock(_, ref s)) if s == "a"));
+    assert!(matches!(lexed.nth(3), Some(Token::TextBlock(_, ref s)) if s == "c"));
+}
+
+#[test]
+fn test_text_block_flags() {
+    assert_eq!(text_block("<#x>\n    a\n      b\n  <#x>"), "a\n  b\n");
+    assert_eq!(text_block("<#x tln>  a  \n  b  <#x>"), "ab");
+    assert_eq!(text_block("<#x rt>a  \n b \t<#x>"), "a\n b");
+    assert_eq!(text_block("<#x rn>\nä\nö\n<#x>"), "äö");
+}
+
+#[test]
+fn test_large_text_block() {
+    let line = "    <div class:main>`` <#html <#htm> & ;; </div>\n";
+    let body = line.repeat(20_000);
+    let document = format!("<#html>\n{}<#html>", body);
+    let expected = body.replace("\n    ", "\n");
+    assert_eq!(text_block(&document), expected[4..]);
+}
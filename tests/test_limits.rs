@@ -0,0 +1,108 @@
+use std::io::Cursor;
+use khi::borrowed::{parse_borrowed_list_with, parse_borrowed_value_with};
+use khi::event::{parse_dictionary_events_with, parse_value_events_with};
+use khi::parse::{Interner, MAX_DEPTH, ParseOptions, parse_dictionary_str, parse_list_str_with, parse_value_str, parse_value_str_with};
+use khi::parse::parser::ParseError;
+use khi::stream::{stream_list_with, StreamError};
+
+fn nested(open: &str, close: &str, depth: usize) -> String {
+    format!("{}x{}", open.repeat(depth), close.repeat(depth))
+}
+
+fn too_deep(errors: Vec<ParseError>) -> bool {
+    errors.iter().any(|e| matches!(e, ParseError::TooDeep(..)))
+}
+
+#[test]
+fn test_default_depth() {
+    for (open, close) in [("{", "}"), ("[", "]"), ("{a: ", "}")] {
+        assert!(parse_value_str(&nested(open, close, MAX_DEPTH)).is_ok(), "{}", open);
+        assert!(too_deep(parse_value_str(&nested(open, close, MAX_DEPTH + 1)).err().unwrap()), "{}", open);
+    }
+    // A tag and the bracket of its content are one level.
+    for (open, close) in [("<a>:{", "}"), ("<a>:[", "]"), ("<a>: {", "}")] {
+        assert!(parse_value_str(&nested(open, close, MAX_DEPTH)).is_ok(), "{}", open);
+        assert!(too_deep(parse_value_str(&nested(open, close, MAX_DEPTH + 1)).err().unwrap()), "{}", open);
+    }
+}
+
+#[test]
+fn test_deep_nesting_does_not_overflow() {
+    for (open, close) in [("{", "}"), ("[", "]"), ("{a: ", "}"), ("<a>:{", "}")] {
+        assert!(too_deep(parse_value_str(&nested(open, close, 100_000)).err().unwrap()), "{}", open);
+    }
+    assert!(too_deep(parse_dictionary_str(&format!("a: {}x", "<a>: ".repeat(100_000))).err().unwrap()));
+    assert!(too_deep(parse_value_str(&format!("{}x", "<a>:".repeat(100_000))).err().unwrap()));
+}
+
+#[test]
+fn test_max_depth() {
+    let options = ParseOptions { max_depth: 3, ..ParseOptions::default() };
+    let mut interner = Interner::new();
+    assert!(parse_value_str_with("{[{x}]}", &options, &mut interner).is_ok());
+    assert!(too_deep(parse_value_str_with("{[{{x}}]}", &options, &mut interner).err().unwrap()));
+    assert!(parse_value_str_with("<a>: <b>: <c>: x", &options, &mut interner).is_ok());
+    assert!(too_deep(parse_value_str_with("<a>: <b>: <c>: <d>: x", &options, &mut interner).err().unwrap()));
+    assert!(too_deep(parse_value_str_with("<a>:<b>:<c>:<d>:x", &options, &mut interner).err().unwrap()));
+}
+
+#[test]
+fn test_max_document_size() {
+    let options = ParseOptions { max_document_size: 8, ..ParseOptions::default() };
+    let mut interner = Interner::new();
+    assert!(parse_list_str_with("a; b; c", &options, &mut interner).is_ok());
+    let errors = parse_list_str_with("a; b; c; d", &options, &mut interner).err().unwrap();
+    assert!(matches!(errors[..], [ParseError::DocumentTooLarge(10, 8)]));
+}
+
+#[test]
+fn test_max_tokens() {
+    let options = ParseOptions { max_tokens: 5, ..ParseOptions::default() };
+    let mut interner = Interner::new();
+    assert!(parse_list_str_with("a; b", &options, &mut interner).is_ok());
+    let errors = parse_list_str_with("a; b; c", &options, &mut interner).err().unwrap();
+    assert!(matches!(errors[..], [ParseError::TooManyTokens(at, 5)] if at.index == 5));
+}
+
+#[test]
+fn test_max_string_length() {
+    let options = ParseOptions { max_string_length: 4, ..ParseOptions::default() };
+    let mut interner = Interner::new();
+    assert!(parse_value_str_with("word \\text\\ <#>text<#>", &options, &mut interner).is_ok());
+    for document in ["words", "{a \\texts\\}", "<#>texts<#>"] {
+        let errors = parse_value_str_with(document, &options, &mut interner).err().unwrap();
+        assert!(matches!(errors[..], [ParseError::StringTooLong(_, 4)]), "{}", document);
+    }
+}
+
+#[test]
+fn test_events_with() {
+    let options = ParseOptions { max_depth: 2, ..ParseOptions::default() };
+    assert!(parse_value_events_with("{[x]}", &options).all(|event| event.is_ok()));
+    assert!(too_deep(parse_value_events_with("{[{x}]}", &options).filter_map(Result::err).collect()));
+    assert!(parse_dictionary_events_with("a:b:c: x", &options).all(|event| event.is_ok()));
+    assert!(too_deep(parse_dictionary_events_with("a:b:c:d: x", &options).filter_map(Result::err).collect()));
+}
+
+#[test]
+fn test_borrowed_with() {
+    let options = ParseOptions { max_depth: 2, max_string_length: 4, ..ParseOptions::default() };
+    assert!(parse_borrowed_value_with("<a>:{<b>:{word}}", &options).is_ok());
+    assert!(too_deep(parse_borrowed_value_with("<a>:{<b>:{<c>:{word}}}", &options).err().unwrap()));
+    let errors = parse_borrowed_list_with("a; words", &options).err().unwrap();
+    assert!(matches!(errors[..], [ParseError::StringTooLong(_, 4)]));
+}
+
+#[test]
+fn test_stream_with() {
+    // The size limit applies to the stream and the other limits to each element.
+    let options = ParseOptions { max_document_size: 24, max_tokens: 8, ..ParseOptions::default() };
+    let elements: Vec<_> = stream_list_with(Cursor::new("> a b\n> c d\n"), &options).collect();
+    assert!(elements.iter().all(|element| element.is_ok()));
+    let mut elements = stream_list_with(Cursor::new("> a\n> b c d e f g\n"), &options);
+    assert!(elements.next().unwrap().is_ok());
+    assert!(matches!(elements.next().unwrap(), Err(StreamError::ParseError(errors)) if matches!(errors[..], [ParseError::TooManyTokens(..)])));
+    let mut elements = stream_list_with(Cursor::new("> a\n> b\n> c\n> d\n> e\n> f\n> g\n"), &options);
+    assert!(elements.next().unwrap().is_ok());
+    assert!(elements.any(|element| matches!(element, Err(StreamError::ParseError(errors)) if matches!(errors[..], [ParseError::DocumentTooLarge(_, 24)]))));
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::io::Write;
+use khi::Value;
+use khi::diff::equal;
+use khi::log::{LogError, LogOptions, LogReader, LogWriter, SyncMode};
+use khi::parse::{parse_list_str, parse_value_str};
+
+#[test]
+fn test_log() {
+    let directory = std::env::temp_dir().join(format!("khi-test-log-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("server-log.khi");
+    fs::copy("examples/server-log.khi", &path).unwrap();
+    let mut reader = LogReader::open(&path);
+    let existing = reader.poll().unwrap();
+    assert_eq!(existing.len(), parse_list_str(&fs::read_to_string(&path).unwrap()).unwrap().elements.len());
+    assert!(reader.poll().unwrap().is_empty());
+    let options = LogOptions { sync: SyncMode::Data, verify_tail: true };
+    let mut writer = LogWriter::open(&path, options).unwrap();
+    let entry = [
+        parse_value_str("2023-Nov-14").unwrap(),
+        parse_value_str("12.00.00").unwrap(),
+        parse_value_str("<Stop reason:\\disk full\\>").unwrap(),
+    ];
+    writer.append(&entry).unwrap();
+    writer.append(&[parse_value_str("\\multi`nline\\").unwrap()]).unwrap();
+    let appended = reader.poll().unwrap();
+    assert_eq!(appended.len(), 2);
+    assert!(equal(&appended[0], &parse_value_str("2023-Nov-14 | 12.00.00 | <Stop reason:\\disk full\\>").unwrap()));
+    assert_eq!(appended[1].as_text().unwrap().str.as_ref(), "multi\nline");
+    parse_list_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_log_partial_entries() {
+    let directory = std::env::temp_dir().join(format!("khi-test-log-partial-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("log.khi");
+    let mut file = fs::File::create(&path).unwrap();
+    let mut reader = LogReader::from_end(&path).unwrap();
+    file.write_all(b"> 1\n> {a: 2").unwrap();
+    assert_eq!(reader.poll().unwrap().len(), 1);
+    file.write_all(b";\n").unwrap();
+    assert!(reader.poll().unwrap().is_empty());
+    file.write_all(b"b: 3}\n> 4\n").unwrap();
+    assert_eq!(reader.poll().unwrap().len(), 2);
+    // An entry that does not parse is reported once.
+    file.write_all(b"> {5\n> 6\n").unwrap();
+    assert!(matches!(reader.poll(), Err(LogError::ParseError(..))));
+    assert_eq!(reader.poll().unwrap().len(), 1);
+    // The entries around a bad entry are kept.
+    file.write_all(b"> 1\n> {5\n> 6\n> 7\n").unwrap();
+    let before = reader.poll().unwrap();
+    assert_eq!(before.len(), 1);
+    assert_eq!(before[0].as_text().unwrap().str.as_ref(), "1");
+    assert!(matches!(reader.poll(), Err(LogError::ParseError(..))));
+    let after = reader.poll().unwrap();
+    assert_eq!(after.len(), 2);
+    assert_eq!(after[0].as_text().unwrap().str.as_ref(), "6");
+    // A writer completes a partial last line before appending.
+    file.write_all(b"> 7").unwrap();
+    let mut writer = LogWriter::open(&path, LogOptions::default()).unwrap();
+    writer.append(&[parse_value_str("8").unwrap()]).unwrap();
+    assert_eq!(reader.poll().unwrap().len(), 2);
+    // A corrupt tail is detected when verifying.
+    file.write_all(b"> [9\n").unwrap();
+    let options = LogOptions { sync: SyncMode::Never, verify_tail: true };
+    assert!(matches!(LogWriter::open(&path, options), Err(LogError::CorruptTail(..))));
+    // The log is read again after truncation.
+    fs::write(&path, "> 10\n").unwrap();
+    assert_eq!(reader.poll().unwrap().len(), 1);
+    fs::remove_dir_all(&directory).unwrap();
+}
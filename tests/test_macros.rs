@@ -0,0 +1,96 @@
+use khi::diff::equal;
+use khi::macros::{expand_macros, has_macros, MacroError};
+use khi::parse::parse_value_str;
+
+fn expand(source: &str) -> khi::pdm::ParsedValue {
+    let mut document = parse_value_str(source).unwrap();
+    expand_macros(&mut document).unwrap();
+    document
+}
+
+#[test]
+fn test_macros() {
+    let document = expand("<macro!>:card:2:{<div class:card>:{<h2>:#1 #2}}\n<card>:Title:{Some text} <card>:Other:<b>:bold");
+    let expected = parse_value_str("<div class:card>:{<h2>:Title Some text} <div class:card>:{<h2>:Other <b>:bold}").unwrap();
+    assert!(equal(&document, &expected));
+}
+
+#[test]
+fn test_macro_spans() {
+    let mut document = parse_value_str("<macro!>:greet:1:{Hello #1}\n<macro!>:twice:1:{<greet>:#1 <greet>:#1}\n<twice>:you").unwrap();
+    let expansions = expand_macros(&mut document).unwrap();
+    assert_eq!(expansions.len(), 3);
+    assert_eq!(&*expansions[0].name, "twice");
+    assert_eq!(expansions[0].call.0.line, 3);
+    assert_eq!(expansions[0].definition.0.line, 2);
+    assert!(equal(&document, &parse_value_str("Hello you Hello you").unwrap()));
+}
+
+#[test]
+fn test_macro_errors() {
+    let mut document = parse_value_str("<macro!>:card:2:{#1 #3}\n<card>:A").unwrap();
+    let errors = match expand_macros(&mut document) {
+        Ok(..) => panic!(),
+        Err(errors) => errors,
+    };
+    assert!(matches!(errors[0], MacroError::UnknownParameter(..)));
+    assert!(matches!(errors[1], MacroError::ArityMismatch(at, _, 2, 1, definition) if at.line == 2 && definition.line == 1));
+}
+
+fn expansion_errors(source: &str) -> Vec<MacroError> {
+    let mut document = parse_value_str(source).unwrap();
+    match expand_macros(&mut document) {
+        Ok(..) => panic!(),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn test_macro_limits() {
+    // Each macro calls the previous one twice.
+    let mut source = String::from("<macro!>:m0:0:x\n");
+    for i in 1..60 {
+        source.push_str(&format!("<macro!>:m{}:0:{{<m{}> <m{}>}}\n", i, i - 1, i - 1));
+    }
+    source.push_str("<m59>");
+    assert!(matches!(expansion_errors(&source)[..], [MacroError::TooLarge(..)]));
+    let errors = expansion_errors(&format!("<macro!>:d:1:{{#1 #1}}\n{}x", "<d>:".repeat(60)));
+    assert!(matches!(errors[..], [MacroError::TooLarge(..)]));
+    // Expansion is aborted at the first error.
+    assert!(matches!(expansion_errors("<macro!>:a:0:{<a> <a>}\n<a>")[..], [MacroError::TooDeep(..)]));
+    assert!(matches!(expansion_errors("<macro!>:a:0:<a>\n<a> <a>")[..], [MacroError::TooDeep(..)]));
+}
+
+#[test]
+fn test_def_is_not_a_macro_definition() {
+    let mut document = parse_value_str("<def!>:Log:0:{ <operatorname>:Log }\n<Log>:1").unwrap();
+    let expected = document.clone();
+    assert!(expand_macros(&mut document).unwrap().is_empty());
+    assert!(equal(&document, &expected));
+}
+
+#[cfg(feature = "tex")]
+#[test]
+fn test_tex_writer_expands_macros() {
+    use khi::tex::{BreakMode, write_tex, write_tex_with};
+    let document = parse_value_str("<macro!>:pair:2:{<frac>:#1:#2}\n<pair>:a:b").unwrap();
+    assert_eq!(write_tex_with(&document, BreakMode::Never).unwrap(), "\\frac{a}{b}");
+    let document = parse_value_str("<def!>:Log:0:{ <operatorname>:Log }\n<Log>:1").unwrap();
+    assert!(write_tex(&document).unwrap().starts_with("\\newcommand\\Log[0]"));
+}
+
+#[cfg(feature = "html")]
+#[test]
+fn test_html_writer_expands_macros() {
+    use khi::html::{PreprocessorError, write_html};
+    let document = parse_value_str("<macro!>:card:2:{<div class:card>:{<h2>:#1 #2}}\n<card>:Title:{Some text}").unwrap();
+    assert_eq!(write_html(&document).unwrap(), "<div class=\"card\"><h2>Title</h2> Some text</div>");
+    let document = parse_value_str("<macro!>:a:0:{<a> <a>}\n<a>").unwrap();
+    assert!(matches!(write_html(&document), Err(PreprocessorError::MacroExpansion(..))));
+}
+
+#[test]
+fn test_has_macros() {
+    assert!(has_macros(&parse_value_str("a [b; {c: <macro!>:d:0:e}]").unwrap()));
+    assert!(!has_macros(&parse_value_str("<card>:a [b; {c: <def!>:d:0:e}]").unwrap()));
+}
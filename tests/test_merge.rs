@@ -0,0 +1,35 @@
+use khi::Dictionary;
+use khi::diff::equal_dictionaries;
+use khi::merge::{ListStrategy, merge, merge_all, MergedDictionary, MergeStrategy, origin_to_string, Source};
+use khi::parse::parse_dictionary_str;
+
+fn layer(source: &str, name: &str) -> MergedDictionary {
+    MergedDictionary::from_document(&parse_dictionary_str(source).unwrap(), name)
+}
+
+#[test]
+fn test_merge_deep() {
+    let defaults = layer("db: {host: localhost; port: 5432}\nhosts: [a; b]\nlog: info", "defaults.khi");
+    let environment = layer("db: {port: 6543}\nhosts: [c]\nlog: <delete!>", "production.khi");
+    let merged = merge(&defaults, &environment, &MergeStrategy::default());
+    let expected = parse_dictionary_str("db: {host: localhost; port: 6543}\nhosts: [c]").unwrap();
+    assert!(equal_dictionaries(&merged.to_dictionary(), &expected));
+    let origin = merged.origin(&["db", "port"]).unwrap();
+    assert!(origin.source == Source::Document("production.khi".into()));
+    assert_eq!((origin.from.line, origin.from.column), (1, 12));
+    assert_eq!(origin_to_string(merged.origin(&["db", "host"]).unwrap()), "defaults.khi:1:12");
+    assert!(merged.get("log").is_none());
+}
+
+#[test]
+fn test_merge_strategies() {
+    let layers = [
+        layer("db: {host: localhost}\nhosts: [a; b]", "defaults.khi"),
+        layer("db: {port: 1}\nhosts: [c]", "host.khi"),
+    ];
+    let shallow = MergeStrategy { deep: false, lists: ListStrategy::Append };
+    let merged = merge_all(&layers, &shallow).to_dictionary();
+    let expected = parse_dictionary_str("db: {port: 1}\nhosts: [a; b; c]").unwrap();
+    assert!(equal_dictionaries(&merged, &expected));
+    assert_eq!(merged.len(), 2);
+}
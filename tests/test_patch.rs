@@ -0,0 +1,48 @@
+use khi::diff::{diff_dictionaries, diff_lists, equal_dictionaries, Step};
+use khi::patch::{apply_patch_to_dictionary, apply_patch_to_list, Conflict, format_patch, parse_patch_str, Patch};
+use khi::parse::{parse_dictionary_str, parse_list_str};
+
+#[test]
+fn test_patch_round_trip() {
+    let a = parse_dictionary_str("name: Oak planks\nprice: 200\ntags: [wood; <Solid x:1>:plank]\nold: {a: 1}").unwrap();
+    let b = parse_dictionary_str("name: Oak planks\nprice: 250\ntags: [wood; light; <Plank x:2 y>:plank]\nbeauty: 1 | 2").unwrap();
+    let patch = Patch::from(diff_dictionaries(&a, &b));
+    let patch = parse_patch_str(&format_patch(&patch)).unwrap();
+    let mut c = a.clone();
+    apply_patch_to_dictionary(&mut c, &patch).unwrap();
+    assert!(equal_dictionaries(&b, &c));
+}
+
+#[test]
+fn test_patch_variants() {
+    let a = parse_list_str("[a; b; c]; 1").unwrap();
+    let b = parse_list_str("[a; x; c; d]; 1").unwrap();
+    let patch = Patch::from(diff_lists(&a, &b));
+    let mut variant = parse_list_str("[a; b; c]; 2").unwrap();
+    apply_patch_to_list(&mut variant, &patch).unwrap();
+    assert!(diff_lists(&variant, &parse_list_str("[a; x; c; d]; 2").unwrap()).is_empty());
+}
+
+#[test]
+fn test_patch_conflicts() {
+    let patch = parse_patch_str("<replace>: {path: [price]; old: 200; new: 250}\n<add>: {path: [name]; value: Oak}\n<remove>: {path: [missing]; old: 1}").unwrap();
+    let mut target = parse_dictionary_str("name: Pine planks\nprice: 180").unwrap();
+    let conflicts = apply_patch_to_dictionary(&mut target, &patch).unwrap_err();
+    assert_eq!(conflicts.len(), 3);
+    assert!(matches!(&conflicts[0], Conflict::ValueChanged(path) if *path == vec![Step::Key("price".into())]));
+    assert!(matches!(&conflicts[1], Conflict::KeyExists(..)));
+    assert!(matches!(&conflicts[2], Conflict::MissingPath(..)));
+    // The target is left untouched.
+    assert!(equal_dictionaries(&target, &parse_dictionary_str("name: Pine planks\nprice: 180").unwrap()));
+}
+
+#[test]
+fn test_patch_tag_name_conflict() {
+    let a = parse_dictionary_str("block: <Solid>:{hardness: 2}").unwrap();
+    let b = parse_dictionary_str("block: <Solid>:{hardness: 3}").unwrap();
+    let patch = Patch::from(diff_dictionaries(&a, &b));
+    let mut target = parse_dictionary_str("block: <Liquid>:{hardness: 2}").unwrap();
+    let conflicts = apply_patch_to_dictionary(&mut target, &patch).unwrap_err();
+    assert_eq!(conflicts.len(), 1);
+    assert!(matches!(&conflicts[0], Conflict::NameChanged(path) if *path == vec![Step::Key("block".into())]));
+}
@@ -0,0 +1,42 @@
+use khi::diff::equal_dictionaries;
+use khi::parse::parse_dictionary_str;
+use khi::reference::{ReferenceError, resolve_dictionary_references};
+
+#[test]
+fn test_references() {
+    let mut document = parse_dictionary_str("\
+defaults: {tags: [wood]; price: {base: 200; currency: NOK}}
+oak: {tags: <ref!>:defaults.tags; price: <ref!>:wooden.price}
+pine: <anchor! wooden>:{price: <ref!>:defaults.price; first: <ref!>:defaults.tags.0}
+").unwrap();
+    resolve_dictionary_references(&mut document).unwrap();
+    let expected = parse_dictionary_str("\
+defaults: {tags: [wood]; price: {base: 200; currency: NOK}}
+oak: {tags: [wood]; price: {base: 200; currency: NOK}}
+pine: {price: {base: 200; currency: NOK}; first: wood}
+").unwrap();
+    assert!(equal_dictionaries(&document, &expected));
+}
+
+#[test]
+fn test_reference_errors() {
+    let mut document = parse_dictionary_str("a: <ref!>:b\nb: {c: <ref!>:a}\nd: <ref!>:missing.key").unwrap();
+    let errors = resolve_dictionary_references(&mut document).unwrap_err();
+    // Both references of the cycle are reported.
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors.iter().filter(|e| matches!(e, ReferenceError::Cycle(..))).count(), 2);
+    assert!(errors.iter().any(|e| matches!(e, ReferenceError::Dangling(at, path) if at.line == 3 && path == "missing.key")));
+}
+
+#[test]
+fn test_reference_limits() {
+    // Each anchor refers to the previous one twice, doubling the copies.
+    let mut document = String::from("a0: <anchor! a0>:x\n");
+    for i in 1..40 {
+        document.push_str(&format!("a{}: <anchor! a{}>:[<ref!>:a{}; <ref!>:a{}]\n", i, i, i - 1, i - 1));
+    }
+    let mut document = parse_dictionary_str(&document).unwrap();
+    let errors = resolve_dictionary_references(&mut document).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], ReferenceError::TooLarge(..)));
+}
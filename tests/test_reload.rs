@@ -0,0 +1,47 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use khi::diff::{ChangeKind, Step};
+use khi::merge::MergeStrategy;
+use khi::reload::{ReloadEvent, Reloader};
+
+#[test]
+fn test_reload() {
+    let directory = std::env::temp_dir().join(format!("khi-test-reload-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let defaults = directory.join("defaults.khi");
+    let host = directory.join("host.khi");
+    fs::write(&defaults, "port: 80\nname: service").unwrap();
+    fs::write(&host, "port: 8080").unwrap();
+    let mut reloader = Reloader::new([&defaults, &host], MergeStrategy::default());
+    let changes = Rc::new(RefCell::new(0));
+    let counter = changes.clone();
+    reloader.subscribe(move |event| if let ReloadEvent::Changed(c) = event {
+        *counter.borrow_mut() += c.len();
+    });
+    let events = reloader.poll();
+    assert_eq!(events.len(), 1);
+    assert_eq!(*changes.borrow(), 2);
+    // A half-saved file is reported and the last good version is kept.
+    fs::write(&host, "port: {8081").unwrap();
+    let events = reloader.poll();
+    assert!(matches!(events[..], [ReloadEvent::Failed(..)]));
+    assert_eq!(reloader.config().explain("port").unwrap(), format!("port = 8080 (from {}:1:7)", host.display()));
+    fs::write(&host, "port: 8081\n").unwrap();
+    let events = reloader.poll();
+    match &events[..] {
+        [ReloadEvent::Changed(changes)] => {
+            assert_eq!(changes.len(), 1);
+            assert!(changes[0].path == vec![Step::Key("port".into())]);
+            assert!(matches!(changes[0].kind, ChangeKind::Changed(..)));
+        }
+        _ => panic!(),
+    }
+    assert!(reloader.poll().is_empty());
+    // An edit of the same size within the resolution of modification times.
+    fs::write(&host, "port: 8082\n").unwrap();
+    let events = reloader.poll();
+    assert!(matches!(events[..], [ReloadEvent::Changed(..)]));
+    assert!(reloader.poll().is_empty());
+    fs::remove_dir_all(&directory).unwrap();
+}
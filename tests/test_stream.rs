@@ -0,0 +1,127 @@
+use std::fs;
+use std::io::{BufReader, Cursor, Read};
+use khi::diff::equal;
+use khi::parse::{parse_dictionary_str, parse_list_str};
+use khi::stream::{stream_dictionary, stream_list, StreamError};
+
+#[test]
+fn test_stream_list_examples() {
+    for path in ["examples/elements.khi", "examples/inventory-log.khi", "examples/primes.khi", "examples/server-log.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let list = parse_list_str(&document).unwrap();
+        let streamed: Vec<_> = stream_list(BufReader::new(fs::File::open(path).unwrap())).map(|e| e.unwrap()).collect();
+        assert_eq!(streamed.len(), list.elements.len(), "{}", path);
+        for (a, b) in streamed.iter().zip(list.elements.iter()) {
+            assert!(equal(a, b), "{}", path);
+        }
+    }
+}
+
+#[test]
+fn test_stream_dictionary_examples() {
+    for path in ["examples/aluminium.a", "examples/materials.khi"] {
+        let document = fs::read_to_string(path).unwrap();
+        let dictionary = parse_dictionary_str(&document).unwrap();
+        let mut count = 0;
+        for entry in stream_dictionary(BufReader::new(fs::File::open(path).unwrap())) {
+            let (key, value) = entry.unwrap();
+            assert!(equal(&value, dictionary.entries.get(&key).unwrap()), "{}", path);
+            count += 1;
+        }
+        assert_eq!(count, dictionary.entries.len(), "{}", path);
+    }
+}
+
+#[test]
+fn test_stream_single_row() {
+    // A table document has at least two rows, but a stream yields each row.
+    let elements: Vec<_> = stream_list(Cursor::new("| 1 | 2 |\n")).map(|e| e.unwrap()).collect();
+    assert_eq!(elements.len(), 1);
+    assert!(parse_list_str("| 1 | 2 |").is_err());
+    let document = "| 1 | 2 |\n| 3 | 4 |\n|5|6| |7|8|\n";
+    let list = parse_list_str(document).unwrap();
+    let streamed: Vec<_> = stream_list(Cursor::new(document)).map(|e| e.unwrap()).collect();
+    assert_eq!(streamed.len(), list.elements.len());
+    for (a, b) in streamed.iter().zip(list.elements.iter()) {
+        assert!(equal(a, b));
+    }
+    assert!(stream_list(Cursor::new("| |\n")).any(|e| e.is_err()));
+}
+
+#[test]
+fn test_stream_positions() {
+    let document = "> a\n> {\n  x: 1\n}\n> b # Comment\n";
+    let positions: Vec<_> = stream_list(Cursor::new(document)).map(|e| e.unwrap().from().line).collect();
+    assert_eq!(positions, vec![1, 2, 5]);
+}
+
+#[test]
+fn test_stream_error_ends_iteration() {
+    let document = "> a\n> {b\n> c\n";
+    let mut stream = stream_list(Cursor::new(document));
+    assert!(stream.next().unwrap().is_ok());
+    assert!(matches!(stream.next(), Some(Err(StreamError::ParseError(..)))));
+    assert!(stream.next().is_none());
+}
+
+#[test]
+fn test_stream_read_error() {
+    let mut stream = stream_list(BufReader::new(Failing { sent: false }));
+    assert!(matches!(stream.next(), Some(Err(StreamError::IoError(..)))));
+    assert!(stream.next().is_none());
+}
+
+#[test]
+fn test_stream_generated_log() {
+    let log = Log { remaining: 10000, line: vec![], index: 0 };
+    let mut count = 0;
+    for element in stream_list(BufReader::new(log)) {
+        let element = element.unwrap();
+        assert_eq!(element.from().line, count + 1);
+        count += 1;
+    }
+    assert_eq!(count, 10000);
+}
+
+/// A reader generating a log in the format of the server log example.
+struct Log {
+    remaining: usize,
+    line: Vec<u8>,
+    index: usize,
+}
+
+impl Read for Log {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.index == self.line.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.remaining -= 1;
+            self.line = format!("> 2023-Nov-11 | 18.56.{:02} | <Start>\n", self.remaining % 60).into_bytes();
+            self.index = 0;
+        }
+        let n = buf.len().min(self.line.len() - self.index);
+        buf[..n].copy_from_slice(&self.line[self.index..self.index + n]);
+        self.index += n;
+        Ok(n)
+    }
+}
+
+/// A reader that fails after the first line.
+struct Failing {
+    sent: bool,
+}
+
+impl Read for Failing {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.sent {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "disconnected"))
+        } else {
+            self.sent = true;
+            let line = b"> a\n> b";
+            buf[..line.len()].copy_from_slice(line);
+            Ok(line.len())
+        }
+    }
+}
+
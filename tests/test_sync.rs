@@ -0,0 +1,33 @@
+#![cfg(feature = "sync")]
+
+use std::sync::Arc;
+use std::thread;
+use khi::Dictionary;
+use khi::merge::MergedDictionary;
+use khi::parse::parse_dictionary_str;
+use khi::pdm::{ParsedDictionary, ParsedValue};
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_parsed_values_are_sync() {
+    assert_send_sync::<ParsedValue>();
+    assert_send_sync::<ParsedDictionary>();
+    assert_send_sync::<MergedDictionary>();
+}
+
+#[test]
+fn test_share_document_between_threads() {
+    let config = Arc::new(parse_dictionary_str("name: Server\nworkers: 4\nlimits: {memory: 512}").unwrap());
+    let workers: Vec<_> = (0..4).map(|_| {
+        let config = Arc::clone(&config);
+        thread::spawn(move || {
+            let limits = config.get("limits").unwrap();
+            let ParsedValue::Dictionary(limits, ..) = limits else { panic!() };
+            limits.get("memory").is_some() && config.get("name").is_some()
+        })
+    }).collect();
+    for worker in workers {
+        assert!(worker.join().unwrap());
+    }
+}